@@ -1,7 +1,7 @@
 import argparse
 import grpc
 from quantify_pb2_grpc import QuantifyDataStub
-from quantify_pb2 import Ticker, AddTickerRequest
+from quantify_pb2 import Ticker, AddTickerRequest, RemoveTickerRequest, JobRequest, ListJobsRequest, DATA_POLICY
 
 # TODO: Channel address and port customizatin
 SERVICE = "localhost:50051"
@@ -21,6 +21,13 @@ parser.add_argument(
     help = "Add ticker"
 )
 
+parser.add_argument(
+    "--lookback-days",
+    type=int,
+    default=0,
+    help = "Days of daily candles to backfill for added tickers"
+)
+
 parser.add_argument(
     "-d", "--delete",
     action='append',
@@ -28,17 +35,48 @@ parser.add_argument(
     help = "Remove ticker" 
 )
 
-def add_tickers(qd: QuantifyDataStub, tickers):
+parser.add_argument(
+    "--data-policy",
+    choices=DATA_POLICY.keys(),
+    default="KEEP",
+    help = "What happens to the stored data of removed tickers"
+)
+
+parser.add_argument(
+    "--jobs",
+    action='store_true',
+    help = "List recent jobs"
+)
+
+parser.add_argument(
+    "--cancel",
+    metavar="JOB_ID",
+    help = "Cancel a running job"
+)
+
+def add_tickers(qd: QuantifyDataStub, tickers, lookback_days=0):
     requests = []
     for ticker in tickers:
         grpc_ticker = Ticker(name=ticker)
-        grpc_request = AddTickerRequest(ticker=grpc_ticker)
+        grpc_request = AddTickerRequest(ticker=grpc_ticker, lookback_days=lookback_days)
         requests.append(qd.AddTicker.future(grpc_request))
     for future in requests:
         print(future.result())
 
-def remove_tickers(qd: QuantifyDataStub, tickers):
-    print("Removing ticker")
+def remove_tickers(qd: QuantifyDataStub, tickers, data_policy):
+    requests = []
+    for ticker in tickers:
+        grpc_ticker = Ticker(name=ticker)
+        grpc_request = RemoveTickerRequest(ticker=grpc_ticker, data_policy=DATA_POLICY.Value(data_policy))
+        requests.append(qd.RemoveTicker.future(grpc_request))
+    for future in requests:
+        print(future.result())
+
+def list_jobs(qd: QuantifyDataStub):
+    print(qd.ListJobs(ListJobsRequest()))
+
+def cancel_job(qd: QuantifyDataStub, job_id):
+    print(qd.CancelJob(JobRequest(id=job_id)))
 
 
 # Entry point
@@ -47,9 +85,13 @@ def main(args):
     qd = QuantifyDataStub(channel)
 
     if args.add:
-        add_tickers(qd, args.add[0])
+        add_tickers(qd, args.add[0], args.lookback_days)
     if args.delete:
-        remove_tickers(qd, args.delete[0])
+        remove_tickers(qd, args.delete[0], args.data_policy)
+    if args.cancel:
+        cancel_job(qd, args.cancel)
+    if args.jobs:
+        list_jobs(qd)
 
 if __name__ == "__main__":
     args = parser.parse_args()
@@ -1,14 +1,15 @@
 use core::fmt;
-use std::error::Error;
 
 use chrono::{DateTime, NaiveDate, Utc};
 use chrono::serde::ts_milliseconds;
+use quantify_core::RateLimiter;
 use reqwest::Client;
 use serde::Deserialize;
 
-use crate::PolygonResponseError;
+use crate::error::{response_text, PolygonError};
 
-const MAX_POLYGON_AGGS_LIMIT: i32 = 50000; // as defined by Polygon.io's API
+/// Maximum number of aggregates per request, as defined by Polygon.io's API
+pub const MAX_POLYGON_AGGS_LIMIT: i32 = 50000;
 
 #[derive(Deserialize, Default)]
 pub struct AggregateData {
@@ -54,7 +55,7 @@ impl fmt::Debug for AggregateData {
 
 impl PartialEq for AggregateData {
     fn eq(&self, other: &Self) -> bool {
-        return self.datetime == other.datetime
+        self.datetime == other.datetime
             && self.open == other.open
             && self.high == other.high
             && self.low == other.low
@@ -101,18 +102,23 @@ struct PolygonAggResponse {
 /// interval - The granularity of the data. Defined by the enum Interval, which defines both the multiplier (eg. 5) and the interval (eg. minutes)
 /// adjusted - Whether the data is adjusted for splits
 /// limit - Limit to the number of data points fetched. Polygon.io defines the maximum limit to be 50000
+///
+/// Every page waits for the rate limiter.
+#[allow(clippy::too_many_arguments)]
 pub(super) async fn get_aggs (
     ticker: &str,
     client: &Client,
+    limiter: &RateLimiter,
+    base_url: &str,
     api_key: &str,
     start_date: &NaiveDate,
     end_date: &NaiveDate,
     interval: &Interval,
     adjusted: &bool,
-) -> Result<Vec<AggregateData>, Box<dyn Error>> {
+) -> Result<Vec<AggregateData>, PolygonError> {
 
     // Construct request
-    let mut request = String::from(format!("https://api.polygon.io/v2/aggs/ticker/{}/range", ticker));
+    let mut request = format!("{}/v2/aggs/ticker/{}/range", base_url, ticker);
     match interval {
         Interval::Seconds(m) => request.push_str(format!("/{}/second", m).as_str()),
         Interval::Minutes(m) => request.push_str(format!("/{}/minute", m).as_str()),
@@ -132,19 +138,15 @@ pub(super) async fn get_aggs (
     let mut aggs: Vec<AggregateData> = Vec::new();
     
     // Send request. Await response
-    let mut response = client
-        .get(request)
-        .send()
-        .await?
-        .text()
-        .await?;
+    limiter.acquire().await?;
+    let mut response = response_text(client.get(request).send().await?, ticker).await?;
 
     // Parse response
     let mut res: PolygonAggResponse = serde_json::from_str(&response)?;
 
     // Check if there is an error. If there is, return it
     if res.status == "ERROR" {
-        return Err(Box::new(PolygonResponseError{error: res.error}));
+        return Err(PolygonError::Vendor(res.error));
     }
 
     // Add the results to the output vector
@@ -156,17 +158,16 @@ pub(super) async fn get_aggs (
     // The value under the "results" list shows the results
     while !res.next_url.is_empty() {
         res.next_url.push_str(format!("&apiKey={}", api_key).as_str());
-        response = client
-            .get(res.next_url)
-            .send()
-            .await?
-            .text()
-            .await?;
+        limiter.acquire().await?;
+        response = response_text(client.get(res.next_url).send().await?, ticker).await?;
 
         res = serde_json::from_str(&response)?;
+        if res.status == "ERROR" {
+            return Err(PolygonError::Vendor(res.error));
+        }
 
         aggs.append(&mut res.results);
     }
 
-    return Ok(aggs);
+    Ok(aggs)
 }
\ No newline at end of file
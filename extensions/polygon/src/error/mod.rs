@@ -0,0 +1,139 @@
+use core::fmt;
+use std::error::Error;
+
+use quantify_core::{ErrorKind, QuotaExceeded, SourceError};
+use reqwest::{Response, StatusCode};
+use serde::Deserialize;
+
+/// Errors returned by the Polygon.io client
+#[derive(Debug)]
+pub enum PolygonError {
+    /// The request could not be sent, or the response body could not be read
+    ///
+    /// The URL is stripped from the error, as it carries the API key.
+    Transport(reqwest::Error),
+    /// The API key was rejected or is not entitled to the endpoint (HTTP 401 / 403)
+    Unauthorized { status: u16, message: String },
+    /// The ticker is unknown to Polygon.io (HTTP 404)
+    NotFound(String),
+    /// Too many requests for the plan (HTTP 429)
+    RateLimited(String),
+    /// The daily quota of the client's rate limiter is used
+    QuotaExceeded(QuotaExceeded),
+    /// Polygon.io failed internally (HTTP 5xx)
+    Server { status: u16, message: String },
+    /// Any other unexpected HTTP status
+    Status { status: u16, message: String },
+    /// Polygon.io reported an error in a successful response
+    Vendor(String),
+    /// The response could not be parsed
+    Parse(serde_json::Error),
+}
+
+impl PolygonError {
+    /// Classifies an unsuccessful HTTP status
+    ///
+    /// # Arguments
+    ///
+    /// * 'status'
+    /// * 'ticker' - The ticker of the request
+    /// * 'message' - The error message reported by Polygon.io, if any
+    pub(crate) fn from_status(status: StatusCode, ticker: &str, message: String) -> PolygonError {
+        let code = status.as_u16();
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => PolygonError::Unauthorized { status: code, message },
+            StatusCode::NOT_FOUND => PolygonError::NotFound(String::from(ticker)),
+            StatusCode::TOO_MANY_REQUESTS => PolygonError::RateLimited(message),
+            s if s.is_server_error() => PolygonError::Server { status: code, message },
+            _ => PolygonError::Status { status: code, message },
+        }
+    }
+
+    /// Classification shared by all market data sources
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            PolygonError::Transport(_) => ErrorKind::Transport,
+            PolygonError::Unauthorized { .. } => ErrorKind::Unauthorized,
+            PolygonError::NotFound(_) => ErrorKind::NotFound,
+            PolygonError::RateLimited(_) => ErrorKind::RateLimited,
+            PolygonError::QuotaExceeded(_) => ErrorKind::QuotaExceeded,
+            PolygonError::Server { .. } => ErrorKind::Server,
+            PolygonError::Status { .. } => ErrorKind::Status,
+            PolygonError::Vendor(_) => ErrorKind::Vendor,
+            PolygonError::Parse(_) => ErrorKind::Parse,
+        }
+    }
+}
+
+impl fmt::Display for PolygonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolygonError::Transport(e) => write!(f, "Polygon.io request failed: {e}"),
+            PolygonError::Unauthorized { status, message } => write!(f, "Polygon.io rejected the API key ({status}): {message}"),
+            PolygonError::NotFound(ticker) => write!(f, "Polygon.io does not know ticker {ticker}"),
+            PolygonError::RateLimited(message) => write!(f, "Polygon.io rate limit exceeded: {message}"),
+            PolygonError::QuotaExceeded(e) => write!(f, "Polygon.io {e}"),
+            PolygonError::Server { status, message } => write!(f, "Polygon.io server error ({status}): {message}"),
+            PolygonError::Status { status, message } => write!(f, "Polygon.io unexpected status ({status}): {message}"),
+            PolygonError::Vendor(message) => write!(f, "Polygon.io error: {message}"),
+            PolygonError::Parse(e) => write!(f, "Polygon.io response could not be parsed: {e}"),
+        }
+    }
+}
+
+impl Error for PolygonError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PolygonError::Transport(e) => Some(e),
+            PolygonError::Parse(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for PolygonError {
+    fn from(e: reqwest::Error) -> Self {
+        PolygonError::Transport(e.without_url())
+    }
+}
+
+impl From<serde_json::Error> for PolygonError {
+    fn from(e: serde_json::Error) -> Self {
+        PolygonError::Parse(e)
+    }
+}
+
+impl From<QuotaExceeded> for PolygonError {
+    fn from(e: QuotaExceeded) -> Self {
+        PolygonError::QuotaExceeded(e)
+    }
+}
+
+impl From<PolygonError> for SourceError {
+    fn from(e: PolygonError) -> Self {
+        SourceError::new(e.kind(), e)
+    }
+}
+
+// Error bodies carry the message under either key
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct PolygonErrorBody {
+    error: String,
+    message: String,
+}
+
+/// Returns the response body, or the error for an unsuccessful status
+pub(crate) async fn response_text(response: Response, ticker: &str) -> Result<String, PolygonError> {
+    let status = response.status();
+    let body = response.text().await?;
+    if status.is_success() {
+        return Ok(body);
+    }
+    let message = match serde_json::from_str::<PolygonErrorBody>(&body) {
+        Ok(parsed) if !parsed.error.is_empty() => parsed.error,
+        Ok(parsed) if !parsed.message.is_empty() => parsed.message,
+        _ => body,
+    };
+    Err(PolygonError::from_status(status, ticker, message))
+}
@@ -1,31 +1,64 @@
-use std::{env, error::Error};
+use std::{env, sync::Arc, time::Duration};
+use quantify_core::{RateLimit, RateLimiter};
 use reqwest::Client;
 use chrono::NaiveDate;
-use std::fmt;
 use agg::get_aggs;
 use meta::get_meta;
 
 // Re-exporting
-pub use agg::{AggregateData, Interval};
+pub use agg::{AggregateData, Interval, MAX_POLYGON_AGGS_LIMIT};
 pub use meta::{Metadata, Address, Locale, MarketType};
+pub use error::PolygonError;
 
 mod agg;
+mod error;
 mod meta;
+mod source;
 
+/// The Polygon.io REST API
+pub const DEFAULT_BASE_URL: &str = "https://api.polygon.io";
+
+/// The request rate of the free plan
+pub const DEFAULT_RATE_LIMIT: RateLimit = RateLimit::new(5, Duration::from_secs(60));
+
+/// A client to access Polygon.io REST APIs
+///
+/// See https://polygon.io/docs/stocks/getting-started
 pub struct PolygonRESTClient {
     web_client: Client,
+    limiter: Arc<RateLimiter>,
     api_key: String,
+    base_url: String,
 }
 
-impl Default for PolygonRESTClient {
-    fn default() -> Self {
-        PolygonRESTClient { web_client: Client::new(), api_key: get_api_key()}
+impl PolygonRESTClient {
+    /// Creates a new PolygonRESTClient with the default base URL
+    ///
+    /// # Arguments
+    ///
+    /// * `web_client`
+    /// * `api_key` - Polygon.io API key
+    pub fn new(web_client: Client, api_key: &str) -> PolygonRESTClient {
+        PolygonRESTClient::builder(api_key).web_client(web_client).build()
     }
-}
 
-impl PolygonRESTClient {
-    pub fn new(web_client: Client) -> PolygonRESTClient {
-        return PolygonRESTClient { web_client: web_client, api_key: get_api_key() };
+    /// Returns a builder for a PolygonRESTClient
+    ///
+    /// # Arguments
+    ///
+    /// * `api_key` - Polygon.io API key
+    pub fn builder(api_key: &str) -> PolygonRESTClientBuilder {
+        PolygonRESTClientBuilder {
+            web_client: None,
+            limiter: None,
+            api_key: String::from(api_key),
+            base_url: String::from(DEFAULT_BASE_URL),
+        }
+    }
+
+    /// The rate limiter every request waits for
+    pub fn rate_limiter(&self) -> &Arc<RateLimiter> {
+        &self.limiter
     }
 
     pub async fn get_aggs(
@@ -34,77 +67,134 @@ impl PolygonRESTClient {
         start_date: &NaiveDate,
         end_date: &NaiveDate,
         interval: &Interval,
-        adjusted: &bool,) -> Result<Vec<AggregateData>, Box<dyn Error>>
+        adjusted: &bool,) -> Result<Vec<AggregateData>, PolygonError>
     {
-        get_aggs(ticker, &self.web_client, &self.api_key, start_date, end_date, interval, adjusted).await
+        get_aggs(ticker, &self.web_client, &self.limiter, &self.base_url, &self.api_key, start_date, end_date, interval, adjusted).await
     }
 
     pub async fn get_meta (
         &self,
         ticker: &str,
         date: Option<&NaiveDate>,
-    ) -> Result<Metadata, Box<dyn Error + Send + Sync>> {
-        get_meta(ticker, &self.web_client, &self.api_key, date).await
+    ) -> Result<Metadata, PolygonError> {
+        get_meta(ticker, &self.web_client, &self.limiter, &self.base_url, &self.api_key, date).await
     }
 }
 
-pub fn get_api_key() -> String {
-    let key = "POLYGON_API_KEY";
-    match env::var(key) {
-        Ok(v) => return v,
-        Err(e) => panic!("${} is not set - {}$", key, e.to_string())
-    }
+/// Builds a [PolygonRESTClient]
+pub struct PolygonRESTClientBuilder {
+    web_client: Option<Client>,
+    limiter: Option<Arc<RateLimiter>>,
+    api_key: String,
+    base_url: String,
 }
 
-// Error-handling for responses
-#[derive(Debug)]
-struct PolygonResponseError {
-    error: String,
-}
+impl PolygonRESTClientBuilder {
+    /// Sets the reqwest client. A new one is created otherwise
+    pub fn web_client(mut self, web_client: Client) -> PolygonRESTClientBuilder {
+        self.web_client = Some(web_client);
+        self
+    }
+
+    /// Sets the base URL (eg. a mock server), without a trailing slash
+    pub fn base_url(mut self, base_url: &str) -> PolygonRESTClientBuilder {
+        self.base_url = String::from(base_url.trim_end_matches('/'));
+        self
+    }
+
+    /// Sets the request rate. [DEFAULT_RATE_LIMIT] otherwise
+    pub fn rate_limit(self, limit: RateLimit) -> PolygonRESTClientBuilder {
+        self.rate_limiter(Arc::new(RateLimiter::new(limit)))
+    }
 
-impl Error for PolygonResponseError {}
+    /// Shares a rate limiter with other clients of the same API key
+    pub fn rate_limiter(mut self, limiter: Arc<RateLimiter>) -> PolygonRESTClientBuilder {
+        self.limiter = Some(limiter);
+        self
+    }
 
-impl fmt::Display for PolygonResponseError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.error.as_str())
+    /// Builds the client
+    pub fn build(self) -> PolygonRESTClient {
+        PolygonRESTClient {
+            web_client: self.web_client.unwrap_or_default(),
+            limiter: self.limiter.unwrap_or_else(|| Arc::new(RateLimiter::new(DEFAULT_RATE_LIMIT))),
+            api_key: self.api_key,
+            base_url: self.base_url,
+        }
     }
 }
 
+/// Returns the api key stored in env variable POLYGON_API_KEY, if set
+pub fn get_api_key() -> Option<String> {
+    env::var("POLYGON_API_KEY").ok()
+}
+
 // Tests
 #[cfg(test)]
 mod tests {
-    use crate::{get_api_key, Interval, AggregateData};
+    use crate::{get_api_key, Interval, AggregateData, DEFAULT_BASE_URL, DEFAULT_RATE_LIMIT, PolygonRESTClient, PolygonError, Locale, MarketType};
     use crate::agg::get_aggs;
     use crate::meta::get_meta;
+    use quantify_core::{ErrorKind, MarketDataSource, RateLimit, RateLimiter, SourceError};
     use reqwest::Client;
     use chrono::{NaiveDate, Utc, TimeZone};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+    use wiremock::matchers::{method, path, query_param};
 
-    #[test]
-    fn test_get_api_key() {
-        let k: String = get_api_key();
-        let l = k.len();
-        assert_eq!(l, 32);
+    const API_KEY: &str = "test-key";
+
+    // Recorded responses. "{base_url}" is replaced by the mock server URI
+    fn fixture(name: &str, base_url: &str) -> String {
+        let body = match name {
+            "aggs_page1" => include_str!("../fixtures/aggs_page1.json"),
+            "aggs_page2" => include_str!("../fixtures/aggs_page2.json"),
+            "meta_nflx" => include_str!("../fixtures/meta_nflx.json"),
+            "error_unauthorized" => include_str!("../fixtures/error_unauthorized.json"),
+            "error_not_found" => include_str!("../fixtures/error_not_found.json"),
+            "error_rate_limited" => include_str!("../fixtures/error_rate_limited.json"),
+            "error_vendor" => include_str!("../fixtures/error_vendor.json"),
+            _ => panic!("Unknown fixture {name}"),
+        };
+        body.replace("{base_url}", base_url)
     }
 
-    #[tokio::test]
-    async fn test_get_meta() {
-        let fetched_result = get_meta(
-            "NFLX",
-            &Client::new(),
-            &get_api_key(),
-            Some(&NaiveDate::from_ymd_opt(2023, 8, 1).unwrap()),
-        ).await.unwrap();
+    fn mock_client(server: &MockServer) -> PolygonRESTClient {
+        PolygonRESTClient::builder(API_KEY).base_url(&server.uri()).build()
+    }
 
-        assert_eq!(fetched_result.ticker, "NFLX");
-        assert_eq!(fetched_result.market_cap, 183094943110.67);
+    async fn mount_meta(server: &MockServer, status: u16, fixture_name: &str) {
+        Mock::given(method("GET"))
+            .and(path("/v3/reference/tickers/NFLX"))
+            .and(query_param("apiKey", API_KEY))
+            .respond_with(ResponseTemplate::new(status).set_body_string(fixture(fixture_name, &server.uri())))
+            .mount(server)
+            .await;
     }
 
     #[tokio::test]
-    async fn test_get_aggs() {
-        let fetched_result = get_aggs(
+    async fn test_get_aggs_pagination() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v2/aggs/ticker/NFLX/range/5/minute/2022-08-01/2023-08-01"))
+            .and(query_param("adjusted", "true"))
+            .and(query_param("limit", "50000"))
+            .and(query_param("apiKey", API_KEY))
+            .respond_with(ResponseTemplate::new(200).set_body_string(fixture("aggs_page1", &server.uri())))
+            .expect(1)
+            .mount(&server)
+            .await;
+        // The next page must carry the API key as well
+        Mock::given(method("GET"))
+            .and(path("/v2/aggs/ticker/NFLX/range/5/minute/1659341800000/1690934400000"))
+            .and(query_param("cursor", "bGltaXQ9MiZzb3J0PWFzYw"))
+            .and(query_param("apiKey", API_KEY))
+            .respond_with(ResponseTemplate::new(200).set_body_string(fixture("aggs_page2", &server.uri())))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let fetched_result = mock_client(&server).get_aggs(
             "NFLX",
-            &Client::new(),
-            &get_api_key(),
             &NaiveDate::from_ymd_opt(2022, 8, 1).unwrap(),
             &NaiveDate::from_ymd_opt(2023, 8, 1).unwrap(),
             &Interval::Minutes(5),
@@ -135,7 +225,203 @@ mod tests {
             vwap: 437.3132,
         };
 
+        assert_eq!(fetched_result.len(), 4);
         assert_eq!(fetched_result[0], correct_first);
         assert_eq!(fetched_result[fetched_result.len()-1], correct_last);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_get_aggs_error_on_next_page() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v2/aggs/ticker/NFLX/range/5/minute/2022-08-01/2023-08-01"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(fixture("aggs_page1", &server.uri())))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/aggs/ticker/NFLX/range/5/minute/1659341800000/1690934400000"))
+            .respond_with(ResponseTemplate::new(429).set_body_string(fixture("error_rate_limited", &server.uri())))
+            .mount(&server)
+            .await;
+
+        let error = mock_client(&server).get_aggs(
+            "NFLX",
+            &NaiveDate::from_ymd_opt(2022, 8, 1).unwrap(),
+            &NaiveDate::from_ymd_opt(2023, 8, 1).unwrap(),
+            &Interval::Minutes(5),
+            &true,
+        ).await.err().unwrap();
+
+        assert!(matches!(error, PolygonError::RateLimited(ref message) if message.starts_with("You've exceeded")));
+        assert!(error.kind().is_transient());
+    }
+
+    #[tokio::test]
+    async fn test_get_aggs_daily_quota() {
+        let server = MockServer::start().await;
+        for (url_path, fixture_name) in [
+            ("/v2/aggs/ticker/NFLX/range/5/minute/2022-08-01/2023-08-01", "aggs_page1"),
+            ("/v2/aggs/ticker/NFLX/range/5/minute/1659341800000/1690934400000", "aggs_page2"),
+        ] {
+            Mock::given(method("GET"))
+                .and(path(url_path))
+                .respond_with(ResponseTemplate::new(200).set_body_string(fixture(fixture_name, &server.uri())))
+                .mount(&server)
+                .await;
+        }
+        // Pages count towards the quota
+        let limit = RateLimit::new(10, std::time::Duration::from_secs(1)).with_daily_quota(1);
+        let client = PolygonRESTClient::builder(API_KEY).base_url(&server.uri()).rate_limit(limit).build();
+
+        let error = client.get_aggs(
+            "NFLX",
+            &NaiveDate::from_ymd_opt(2022, 8, 1).unwrap(),
+            &NaiveDate::from_ymd_opt(2023, 8, 1).unwrap(),
+            &Interval::Minutes(5),
+            &true,
+        ).await.err().unwrap();
+
+        assert_eq!(error.kind(), ErrorKind::QuotaExceeded);
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+        let quota = client.quota().unwrap();
+        assert_eq!((quota.requests, quota.limit.daily_quota), (1, Some(1)));
+    }
+
+    #[tokio::test]
+    async fn test_get_meta() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v3/reference/tickers/NFLX"))
+            .and(query_param("date", "2023-08-01"))
+            .and(query_param("apiKey", API_KEY))
+            .respond_with(ResponseTemplate::new(200).set_body_string(fixture("meta_nflx", &server.uri())))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let fetched_result = mock_client(&server)
+            .get_meta("NFLX", Some(&NaiveDate::from_ymd_opt(2023, 8, 1).unwrap()))
+            .await.unwrap();
+
+        assert_eq!(fetched_result.ticker, "NFLX");
+        assert_eq!(fetched_result.name, "Netflix Inc");
+        assert_eq!(fetched_result.primary_exchange, "XNAS");
+        assert_eq!(fetched_result.market_cap, 183094943110.67);
+        assert_eq!(fetched_result.currency, "usd");
+        assert_eq!(fetched_result.list_date, NaiveDate::from_ymd_opt(2002, 5, 23).unwrap());
+        assert_eq!(fetched_result.address.city, "LOS GATOS");
+        assert_eq!(fetched_result.total_employees, 12800.0);
+        assert!(matches!(fetched_result.locale, Locale::US));
+        assert!(matches!(fetched_result.market_type, MarketType::Stocks));
+        // Missing fields take their default
+        assert_eq!(fetched_result.delisted_utc, "");
+        assert_eq!(fetched_result.ticker_suffix, "");
+    }
+
+    #[tokio::test]
+    async fn test_error_statuses() {
+        let cases = [
+            (401, "error_unauthorized", ErrorKind::Unauthorized),
+            (403, "error_unauthorized", ErrorKind::Unauthorized),
+            (404, "error_not_found", ErrorKind::NotFound),
+            (429, "error_rate_limited", ErrorKind::RateLimited),
+            (502, "error_vendor", ErrorKind::Server),
+            (400, "error_vendor", ErrorKind::Status),
+            // An error reported in a successful response
+            (200, "error_vendor", ErrorKind::Vendor),
+        ];
+        for (status, fixture_name, kind) in cases {
+            let server = MockServer::start().await;
+            mount_meta(&server, status, fixture_name).await;
+
+            let error = mock_client(&server).get_meta("NFLX", None).await.err().unwrap();
+            assert_eq!(error.kind(), kind, "status {status}");
+        }
+
+        // Messages are taken from the error body
+        let server = MockServer::start().await;
+        mount_meta(&server, 401, "error_unauthorized").await;
+        let error = mock_client(&server).get_meta("NFLX", None).await.err().unwrap();
+        assert!(matches!(error, PolygonError::Unauthorized { status: 401, ref message } if message == "Unknown API Key"));
+
+        // Unparsable bodies
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("<html>Bad Gateway</html>"))
+            .mount(&server)
+            .await;
+        let error = mock_client(&server).get_meta("NFLX", None).await.err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::Parse);
+    }
+
+    #[tokio::test]
+    async fn test_transport_error() {
+        // Nothing listens on the port once the listener is dropped
+        let address = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let client = PolygonRESTClient::builder(API_KEY).base_url(&format!("http://{address}")).build();
+        let error = client.get_aggs("NFLX", &NaiveDate::from_ymd_opt(2023, 8, 1).unwrap(), &NaiveDate::from_ymd_opt(2023, 8, 2).unwrap(), &Interval::Days(1), &true).await.err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::Transport);
+        // The request URL carries the API key
+        assert!(!error.to_string().contains(API_KEY));
+        assert!(!format!("{error:?}").contains(API_KEY));
+    }
+
+    #[tokio::test]
+    async fn test_market_data_source() {
+        let server = MockServer::start().await;
+        mount_meta(&server, 200, "meta_nflx").await;
+        let client = mock_client(&server);
+
+        // Tickers are uppercased
+        let metadata = client.get_metadata("nflx").await.unwrap();
+        assert_eq!(metadata.name, "Netflix Inc");
+        assert_eq!(metadata.exchange, "XNAS");
+
+        let error: SourceError = client.get_metadata("aapl").await.err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+    }
+
+    // Live API tests. Run with `cargo test -- --ignored` and POLYGON_API_KEY set
+    #[test]
+    #[ignore = "requires POLYGON_API_KEY"]
+    fn test_get_api_key() {
+        let k: String = get_api_key().expect("POLYGON_API_KEY is not set");
+        let l = k.len();
+        assert_eq!(l, 32);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires POLYGON_API_KEY and network access"]
+    async fn test_live_get_meta() {
+        let fetched_result = get_meta(
+            "NFLX",
+            &Client::new(),
+            &RateLimiter::new(DEFAULT_RATE_LIMIT),
+            DEFAULT_BASE_URL,
+            &get_api_key().unwrap(),
+            Some(&NaiveDate::from_ymd_opt(2023, 8, 1).unwrap()),
+        ).await.unwrap();
+
+        assert_eq!(fetched_result.ticker, "NFLX");
+        assert_eq!(fetched_result.market_cap, 183094943110.67);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires POLYGON_API_KEY and network access"]
+    async fn test_live_get_aggs() {
+        let fetched_result = get_aggs(
+            "NFLX",
+            &Client::new(),
+            &RateLimiter::new(DEFAULT_RATE_LIMIT),
+            DEFAULT_BASE_URL,
+            &get_api_key().unwrap(),
+            &NaiveDate::from_ymd_opt(2022, 8, 1).unwrap(),
+            &NaiveDate::from_ymd_opt(2023, 8, 1).unwrap(),
+            &Interval::Minutes(5),
+            &true,
+        ).await.unwrap();
+
+        assert_eq!(fetched_result[0].datetime, Utc.with_ymd_and_hms(2022, 8, 1, 8, 10, 0).unwrap());
+        assert_eq!(fetched_result[fetched_result.len()-1].datetime, Utc.with_ymd_and_hms(2023, 8, 1, 23, 55, 0).unwrap());
+    }
+}
@@ -1,11 +1,11 @@
 use core::fmt;
-use std::error::Error;
 
 use chrono::NaiveDate;
+use quantify_core::RateLimiter;
 use reqwest::Client;
 use serde::Deserialize;
 
-use crate::PolygonResponseError;
+use crate::error::{response_text, PolygonError};
 
 #[derive(Deserialize, Debug, Default)]
 #[serde(default)]
@@ -80,23 +80,18 @@ pub struct Address {
 }
 
 // Enum for locale
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Default)]
 pub enum Locale {
     #[serde(alias="us")]
     US,
     #[serde(alias="global")]
     Global,
+    #[default]
     Unknown, // To indicate a missing Locale. Not part of the API definition
 }
 
-impl Default for Locale {
-    fn default() -> Self {
-        Locale::Unknown
-    }
-}
-
 // Enum for market type
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Default)]
 pub enum MarketType {
     #[serde(alias="stocks")]
     Stocks,
@@ -108,15 +103,10 @@ pub enum MarketType {
     OTC,
     #[serde(alias="indices")]
     Indices,
+    #[default]
     Unknown, // To indicate a missing MarketType. Not part of the API definition
 }
 
-impl Default for MarketType {
-    fn default() -> Self {
-        MarketType::Unknown
-    }
-}
-
 #[derive(Deserialize, Default)]
 #[serde(default)]
 struct PolygonMetaResponse {
@@ -132,34 +122,30 @@ struct PolygonMetaResponse {
 pub(super) async fn get_meta (
     ticker: &str,
     client: &Client,
+    limiter: &RateLimiter,
+    base_url: &str,
     api_key: &str,
     date: Option<&NaiveDate>,
-) -> Result<Metadata, Box<dyn Error + Send + Sync>> {
+) -> Result<Metadata, PolygonError> {
 
     // Construct request
-    let mut request = String::from(format!("https://api.polygon.io/v3/reference/tickers/{}?", ticker));
-    if date.is_some() {
-        request.push_str(date.unwrap().format("%Y-%m-%d&").to_string().as_str());
+    let mut request = format!("{}/v3/reference/tickers/{}?", base_url, ticker);
+    if let Some(date) = date {
+        request.push_str(date.format("date=%Y-%m-%d&").to_string().as_str());
     }
     request.push_str(format!("apiKey={}", api_key).as_str());
 
-    println!("{}", request);
-    
     // Send request. Await response
-    let response = client
-        .get(request)
-        .send()
-        .await?
-        .text()
-        .await?;
+    limiter.acquire().await?;
+    let response = response_text(client.get(request).send().await?, ticker).await?;
 
     // Parse response
     let res: PolygonMetaResponse = serde_json::from_str(&response)?;
 
     // Check if there is an error. If there is, return it
     if res.status == "ERROR" {
-        return Err(Box::new(PolygonResponseError{error: res.error}));
+        return Err(PolygonError::Vendor(res.error));
     }
 
-    return Ok(res.results);
+    Ok(res.results)
 }
\ No newline at end of file
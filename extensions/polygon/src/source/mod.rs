@@ -0,0 +1,73 @@
+use chrono::{NaiveDate, TimeZone, Utc};
+use quantify_core::{async_trait, Bar, MarketDataSource, Metadata, QuotaUsage, SourceResult};
+
+use crate::{AggregateData, Interval, PolygonRESTClient};
+
+/// Whether bars are requested adjusted for splits
+const ADJUSTED: bool = true;
+
+impl From<&quantify_core::Interval> for Interval {
+    fn from(interval: &quantify_core::Interval) -> Self {
+        match *interval {
+            quantify_core::Interval::Minutes(m) => Interval::Minutes(m as i32),
+            quantify_core::Interval::Hours(m) => Interval::Hours(m as i32),
+            quantify_core::Interval::Days(m) => Interval::Days(m as i32),
+            quantify_core::Interval::Weeks(m) => Interval::Weeks(m as i32),
+            quantify_core::Interval::Months(m) => Interval::Months(m as i32),
+        }
+    }
+}
+
+fn to_bar(data: &AggregateData, interval: &quantify_core::Interval) -> Bar {
+    let timestamp = match interval {
+        // Polygon timestamps daily and coarser bars at midnight New York time
+        quantify_core::Interval::Minutes(_) | quantify_core::Interval::Hours(_) => data.datetime,
+        _ => Utc.from_utc_datetime(&data.datetime.date_naive().and_hms_opt(0, 0, 0).unwrap()),
+    };
+    Bar {
+        timestamp,
+        open: data.open,
+        high: data.high,
+        low: data.low,
+        close: data.close,
+        volume: data.volume,
+        num_transactions: Some(data.num_transactions as u64),
+        vwap: Some(data.vwap),
+        adjusted: ADJUSTED,
+    }
+}
+
+#[async_trait]
+impl MarketDataSource for PolygonRESTClient {
+    fn name(&self) -> &str {
+        "polygon"
+    }
+
+    fn supports(&self, _interval: &quantify_core::Interval) -> bool {
+        true
+    }
+
+    fn quota(&self) -> Option<QuotaUsage> {
+        Some(self.rate_limiter().usage())
+    }
+
+    async fn get_metadata(&self, ticker: &str) -> SourceResult<Metadata> {
+        let meta = self.get_meta(&ticker.to_uppercase(), None).await?;
+        Ok(Metadata {
+            ticker: meta.ticker,
+            name: meta.name,
+            exchange: meta.primary_exchange,
+        })
+    }
+
+    async fn get_bars(
+        &self,
+        ticker: &str,
+        start_date: &NaiveDate,
+        end_date: &NaiveDate,
+        interval: &quantify_core::Interval,
+    ) -> SourceResult<Vec<Bar>> {
+        let aggs = self.get_aggs(&ticker.to_uppercase(), start_date, end_date, &interval.into(), &ADJUSTED).await?;
+        Ok(aggs.iter().map(|agg| to_bar(agg, interval)).collect())
+    }
+}
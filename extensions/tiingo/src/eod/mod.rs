@@ -1,14 +1,19 @@
 use core::fmt;
-use std::{option::Option, error::Error};
+use std::option::Option;
 
 use chrono::NaiveDate;
+use quantify_core::RateLimiter;
 use reqwest::Client;
+use serde::Deserialize;
+use serde_json::Value;
 
-// TODO
-// Avoid panicking on fail
+use crate::error::{error_detail, response_text, TiingoError};
 
 // An object created from the Tiingo End-of-Day Endpoint API
 // https://www.tiingo.com/documentation/end-of-day
+// Columns are matched by header name. Unknown columns are ignored.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct EoD {
     // The date this data pertains to
     pub date: NaiveDate,
@@ -33,8 +38,10 @@ pub struct EoD {
     // The adjusted opening price for the asset on the given date
     pub adj_volume: u64, 
     // The dividend paid out on the date (ex-dividend date)
+    #[serde(rename = "divCash")]
     pub dividend: f64,
     // The factor used to adjust prices
+    #[serde(rename = "splitFactor")]
     pub split: f64,
 }
 
@@ -51,7 +58,7 @@ impl fmt::Debug for EoD {
 
 impl PartialEq for EoD {
     fn eq(&self, other: &Self) -> bool {
-        return self.date == other.date
+        self.date == other.date
             && self.open == other.open
             && self.close== other.close
             && self.high == other.high
@@ -63,7 +70,7 @@ impl PartialEq for EoD {
             && self.adj_close == other.adj_close
             && self.volume == other.volume
             && self.dividend == other.dividend
-            && self.split == other.split 
+            && self.split == other.split
     }
 }
 
@@ -85,16 +92,19 @@ impl fmt::Display for ResampleFreq {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub(super) async fn get_eod (
     ticker: &str,
     client: &Client,
+    limiter: &RateLimiter,
+    base_url: &str,
     api_key: &str,
     start_date: &Option<NaiveDate>,
     end_date: &Option<NaiveDate>,
     resample_freq: &Option<ResampleFreq>
-) -> Result<Vec<EoD>, Box<dyn Error + Send + Sync>> {
+) -> Result<Vec<EoD>, TiingoError> {
     // Construct request
-    let mut request = String::from(format!("https://api.tiingo.com/tiingo/daily/{}/prices?format=csv&token={}", ticker, api_key));
+    let mut request = format!("{}/tiingo/daily/{}/prices?format=csv&token={}", base_url, ticker, api_key);
     if let Some(start_date) = start_date {
         request.push_str(format!("&startDate={}", start_date.format("%F")).as_str());
     }
@@ -105,70 +115,35 @@ pub(super) async fn get_eod (
         request.push_str(format!("&resampleFreq={}", resample_freq).as_str());
     }
     // Send request. Await response
+    limiter.acquire().await?;
     let response = client
         .get(request)
         .header("Content-Type", "application/json")
         .send()
-        .await?
-        .text()
         .await?;
+    let response = response_text(response, ticker).await?;
 
     // Parse response
-    let mut result: Vec<EoD> = Vec::new();
-    let mut count = 0;
-    let (mut date, mut open, mut high, mut low, mut close, mut volume, mut adj_open, 
-         mut adj_high, mut adj_low, mut adj_close, mut adj_volume, mut dividend, mut split) =
-    (0,0,0,0,0,0,0,0,0,0,0,0,0);
-
-    for line in response.lines() {
-        count += 1;
-        let data= line.split(',');
-        if count == 1 {
-            for (i, column) in data.enumerate() {
-                match column {
-                    "date"        => date = i,
-                    "close"       => close = i,
-                    "high"        => high = i,
-                    "low"         => low = i,
-                    "open"        => open = i,
-                    "volume"      => volume = i,
-                    "adjClose"    => adj_close = i,
-                    "adjHigh"     => adj_high = i,
-                    "adjLow"      => adj_low = i,
-                    "adjOpen"     => adj_open = i,
-                    "adjVolume"   => adj_volume = i,
-                    "divCash"     => dividend = i,
-                    "splitFactor" => split = i,
-                    &_ => panic!("Unknown data in response. Check Tiingo API reference.")
-                }
-            }
-        }
-        else {
-            let data: Vec<&str> = data.collect();
+    parse_eod(&response)
+}
 
-            let date_string = data[date];
-            let date_parsed = date_string.parse::<NaiveDate>()?;
+/// Parses a CSV End-of-Day response
+///
+/// Tiingo answers some errors with a JSON body, even when CSV was requested
+pub(super) fn parse_eod(response: &str) -> Result<Vec<EoD>, TiingoError> {
+    let body = response.trim_start();
+    if body.starts_with('{') || body.starts_with('[') {
+        return match serde_json::from_str::<Value>(body) {
+            // No data in the requested range
+            Ok(Value::Array(a)) if a.is_empty() => Ok(Vec::new()),
+            _ => Err(TiingoError::Vendor(error_detail(body).unwrap_or_else(|| String::from(body)))),
+        };
+    }
 
-            result.push
-            (
-                EoD
-                {
-                    date: date_parsed,
-                    open: data[open].parse()?,
-                    high: data[high].parse()?,
-                    low: data[low].parse()?,
-                    close: data[close].parse()?,
-                    volume: data[volume].parse()?,
-                    adj_open: data[adj_open].parse()?,
-                    adj_high: data[adj_high].parse()?,
-                    adj_low: data[adj_low].parse()?,
-                    adj_close: data[adj_close].parse()?,
-                    adj_volume: data[adj_volume].parse()?,
-                    dividend: data[dividend].parse()?,
-                    split: data[split].parse()?
-                }
-            )
-        }
+    let mut reader = csv::Reader::from_reader(body.as_bytes());
+    let mut result: Vec<EoD> = Vec::new();
+    for record in reader.deserialize() {
+        result.push(record?);
     }
-    return Ok(result);
+    Ok(result)
 }
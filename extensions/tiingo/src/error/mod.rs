@@ -0,0 +1,147 @@
+use core::fmt;
+use std::error::Error;
+
+use quantify_core::{ErrorKind, QuotaExceeded, SourceError};
+use reqwest::{Response, StatusCode};
+use serde_json::Value;
+
+/// Errors returned by the Tiingo client
+#[derive(Debug)]
+pub enum TiingoError {
+    /// The request could not be sent, or the response body could not be read
+    ///
+    /// The URL is stripped from the error, as it carries the API token.
+    Transport(reqwest::Error),
+    /// The API token was rejected (HTTP 401 / 403)
+    Unauthorized { status: u16, message: String },
+    /// The ticker is unknown to Tiingo (HTTP 404)
+    NotFound(String),
+    /// Too many requests for the plan (HTTP 429)
+    RateLimited(String),
+    /// The daily quota of the client's rate limiter is used
+    QuotaExceeded(QuotaExceeded),
+    /// Tiingo failed internally (HTTP 5xx)
+    Server { status: u16, message: String },
+    /// Any other unexpected HTTP status
+    Status { status: u16, message: String },
+    /// Tiingo reported an error in a successful response
+    Vendor(String),
+    /// The response could not be parsed (JSON, CSV or dates)
+    Parse(Box<dyn Error + Send + Sync>),
+}
+
+impl TiingoError {
+    /// Classifies an unsuccessful HTTP status
+    ///
+    /// # Arguments
+    ///
+    /// * 'status'
+    /// * 'ticker' - The ticker of the request
+    /// * 'message' - The error message reported by Tiingo, if any
+    pub(crate) fn from_status(status: StatusCode, ticker: &str, message: String) -> TiingoError {
+        let code = status.as_u16();
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => TiingoError::Unauthorized { status: code, message },
+            StatusCode::NOT_FOUND => TiingoError::NotFound(String::from(ticker)),
+            StatusCode::TOO_MANY_REQUESTS => TiingoError::RateLimited(message),
+            s if s.is_server_error() => TiingoError::Server { status: code, message },
+            _ => TiingoError::Status { status: code, message },
+        }
+    }
+
+    /// Classification shared by all market data sources
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            TiingoError::Transport(_) => ErrorKind::Transport,
+            TiingoError::Unauthorized { .. } => ErrorKind::Unauthorized,
+            TiingoError::NotFound(_) => ErrorKind::NotFound,
+            TiingoError::RateLimited(_) => ErrorKind::RateLimited,
+            TiingoError::QuotaExceeded(_) => ErrorKind::QuotaExceeded,
+            TiingoError::Server { .. } => ErrorKind::Server,
+            TiingoError::Status { .. } => ErrorKind::Status,
+            TiingoError::Vendor(_) => ErrorKind::Vendor,
+            TiingoError::Parse(_) => ErrorKind::Parse,
+        }
+    }
+}
+
+impl fmt::Display for TiingoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TiingoError::Transport(e) => write!(f, "Tiingo request failed: {e}"),
+            TiingoError::Unauthorized { status, message } => write!(f, "Tiingo rejected the API token ({status}): {message}"),
+            TiingoError::NotFound(ticker) => write!(f, "Tiingo does not know ticker {ticker}"),
+            TiingoError::RateLimited(message) => write!(f, "Tiingo rate limit exceeded: {message}"),
+            TiingoError::QuotaExceeded(e) => write!(f, "Tiingo {e}"),
+            TiingoError::Server { status, message } => write!(f, "Tiingo server error ({status}): {message}"),
+            TiingoError::Status { status, message } => write!(f, "Tiingo unexpected status ({status}): {message}"),
+            TiingoError::Vendor(message) => write!(f, "Tiingo error: {message}"),
+            TiingoError::Parse(e) => write!(f, "Tiingo response could not be parsed: {e}"),
+        }
+    }
+}
+
+impl Error for TiingoError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TiingoError::Transport(e) => Some(e),
+            TiingoError::Parse(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for TiingoError {
+    fn from(e: reqwest::Error) -> Self {
+        TiingoError::Transport(e.without_url())
+    }
+}
+
+impl From<serde_json::Error> for TiingoError {
+    fn from(e: serde_json::Error) -> Self {
+        TiingoError::Parse(Box::new(e))
+    }
+}
+
+impl From<csv::Error> for TiingoError {
+    fn from(e: csv::Error) -> Self {
+        TiingoError::Parse(Box::new(e))
+    }
+}
+
+impl From<chrono::ParseError> for TiingoError {
+    fn from(e: chrono::ParseError) -> Self {
+        TiingoError::Parse(Box::new(e))
+    }
+}
+
+impl From<QuotaExceeded> for TiingoError {
+    fn from(e: QuotaExceeded) -> Self {
+        TiingoError::QuotaExceeded(e)
+    }
+}
+
+impl From<TiingoError> for SourceError {
+    fn from(e: TiingoError) -> Self {
+        SourceError::new(e.kind(), e)
+    }
+}
+
+/// Returns the message of a Tiingo error body ({"detail": "..."}), if the body is one
+pub(crate) fn error_detail(body: &str) -> Option<String> {
+    match serde_json::from_str::<Value>(body) {
+        Ok(Value::Object(map)) => map.get("detail").and_then(Value::as_str).map(String::from),
+        _ => None,
+    }
+}
+
+/// Returns the response body, or the error for an unsuccessful status
+pub(crate) async fn response_text(response: Response, ticker: &str) -> Result<String, TiingoError> {
+    let status = response.status();
+    let body = response.text().await?;
+    if status.is_success() {
+        return Ok(body);
+    }
+    let message = error_detail(&body).unwrap_or(body);
+    Err(TiingoError::from_status(status, ticker, message))
+}
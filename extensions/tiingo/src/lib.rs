@@ -1,40 +1,68 @@
-use std::{env, error::Error};
+use std::{env, sync::Arc, time::Duration};
 use eod::get_eod;
 use meta::get_metadata;
 use reqwest::Client;
 use chrono::NaiveDate;
+use quantify_core::{RateLimit, RateLimiter};
+
+pub use error::TiingoError;
 
 pub mod eod;
+mod error;
 pub mod meta;
+mod source;
+
+/// The Tiingo REST API
+pub const DEFAULT_BASE_URL: &str = "https://api.tiingo.com";
+
+/// The request rate of the free plan
+pub const DEFAULT_RATE_LIMIT: RateLimit = RateLimit::new(50, Duration::from_secs(3600)).with_daily_quota(1000);
 
 /// A client to access Tiingo REST APIs
 /// 
 /// See https://www.tiingo.com/documentation/general/overview
 pub struct TiingoRESTClient {
     web_client: Client,
-    api_key: String
-}
-impl Default for TiingoRESTClient {
-    fn default() -> Self {
-        TiingoRESTClient { web_client: Client::new(), api_key: get_api_key()}
-    }
+    limiter: Arc<RateLimiter>,
+    api_key: String,
+    base_url: String
 }
 impl TiingoRESTClient {
-    /// Creates a new TiingoRESTClient
+    /// Creates a new TiingoRESTClient with the default base URL
     /// 
     /// # Arguments
     /// 
     /// * `web_client`
-    pub fn new(web_client: Client) -> TiingoRESTClient{
-        TiingoRESTClient {web_client, api_key: get_api_key()}
+    /// * `api_key` - Tiingo API token
+    pub fn new(web_client: Client, api_key: &str) -> TiingoRESTClient{
+        TiingoRESTClient::builder(api_key).web_client(web_client).build()
+    }
+
+    /// Returns a builder for a TiingoRESTClient
+    /// 
+    /// # Arguments
+    /// 
+    /// * `api_key` - Tiingo API token
+    pub fn builder(api_key: &str) -> TiingoRESTClientBuilder {
+        TiingoRESTClientBuilder {
+            web_client: None,
+            limiter: None,
+            api_key: String::from(api_key),
+            base_url: String::from(DEFAULT_BASE_URL)
+        }
+    }
+
+    /// The rate limiter every request waits for
+    pub fn rate_limiter(&self) -> &Arc<RateLimiter> {
+        &self.limiter
     }
 
     /// Gets Metadata
     pub async fn get_metadata(
         &self,
-        ticker: &str) -> Result<meta::Metadata, Box<dyn Error + Send + Sync>>
+        ticker: &str) -> Result<meta::Metadata, TiingoError>
     {
-        get_metadata(ticker, &self.web_client, &self.api_key).await
+        get_metadata(ticker, &self.web_client, &self.limiter, &self.base_url, &self.api_key).await
     }
 
     /// Gets end-of-day candle data
@@ -43,39 +71,277 @@ impl TiingoRESTClient {
         ticker: &str,
         start_date: &Option<NaiveDate>,
         end_date: &Option<NaiveDate>,
-        resample_freq: &Option<eod::ResampleFreq>) -> Result<Vec<eod::EoD>, Box<dyn Error + Send + Sync>>
+        resample_freq: &Option<eod::ResampleFreq>) -> Result<Vec<eod::EoD>, TiingoError>
     {
-        get_eod(ticker, &self.web_client, &self.api_key, start_date, end_date, resample_freq).await
+        get_eod(ticker, &self.web_client, &self.limiter, &self.base_url, &self.api_key, start_date, end_date, resample_freq).await
     }
 }
 
-/// Returns the api key stored in env variable TIINGO_API_KEY
-pub fn get_api_key() -> String {
-    let key = "TIINGO_API_KEY";
-    match env::var(key) {
-        Ok(v) => return v,
-        Err(e) => panic!("${} is not set - {}$", key, e.to_string())
+/// Builds a [TiingoRESTClient]
+pub struct TiingoRESTClientBuilder {
+    web_client: Option<Client>,
+    limiter: Option<Arc<RateLimiter>>,
+    api_key: String,
+    base_url: String
+}
+impl TiingoRESTClientBuilder {
+    /// Sets the reqwest client. A new one is created otherwise
+    pub fn web_client(mut self, web_client: Client) -> TiingoRESTClientBuilder {
+        self.web_client = Some(web_client);
+        self
+    }
+
+    /// Sets the base URL (eg. a mock server), without a trailing slash
+    pub fn base_url(mut self, base_url: &str) -> TiingoRESTClientBuilder {
+        self.base_url = String::from(base_url.trim_end_matches('/'));
+        self
+    }
+
+    /// Sets the request rate. [DEFAULT_RATE_LIMIT] otherwise
+    pub fn rate_limit(self, limit: RateLimit) -> TiingoRESTClientBuilder {
+        self.rate_limiter(Arc::new(RateLimiter::new(limit)))
+    }
+
+    /// Shares a rate limiter with other clients of the same API token
+    pub fn rate_limiter(mut self, limiter: Arc<RateLimiter>) -> TiingoRESTClientBuilder {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    /// Builds the client
+    pub fn build(self) -> TiingoRESTClient {
+        TiingoRESTClient {
+            web_client: self.web_client.unwrap_or_default(),
+            limiter: self.limiter.unwrap_or_else(|| Arc::new(RateLimiter::new(DEFAULT_RATE_LIMIT))),
+            api_key: self.api_key,
+            base_url: self.base_url
+        }
     }
 }
 
+/// Returns the api key stored in env variable TIINGO_API_KEY, if set
+pub fn get_api_key() -> Option<String> {
+    env::var("TIINGO_API_KEY").ok()
+}
+
 #[cfg(test)]
 mod tests {
     use reqwest::Client;
-    use crate::{eod::{get_eod, ResampleFreq, EoD}, meta::get_metadata};
+    use crate::{eod::{get_eod, parse_eod, ResampleFreq, EoD}, meta::get_metadata};
+    use quantify_core::{ErrorKind, Interval, MarketDataSource, RateLimit};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+    use wiremock::matchers::{method, path, query_param};
     use super::*;
 
+    const API_KEY: &str = "test-token";
+
+    fn mock_client(server: &MockServer) -> TiingoRESTClient {
+        TiingoRESTClient::builder(API_KEY).base_url(&server.uri()).build()
+    }
+
+    async fn mount(server: &MockServer, url_path: &str, status: u16, body: &str) {
+        Mock::given(method("GET"))
+            .and(path(url_path))
+            .and(query_param("token", API_KEY))
+            .respond_with(ResponseTemplate::new(status).set_body_string(body))
+            .mount(server)
+            .await;
+    }
+
+    #[test]
+    fn test_parse_eod() {
+        // Columns out of order, with an unknown column
+        let response = "date,open,high,low,close,volume,adjClose,adjHigh,adjLow,adjOpen,adjVolume,divCash,splitFactor,fxRate\n\
+            2014-01-02,1115.46,1117.75,1108.26,1113.12,3639100,27.9141683635953,28.0302767791511,27.7922921433791,27.9728495066623,145114657,0.0,1.0,1.0\n";
+        let eods = parse_eod(response).unwrap();
+
+        assert_eq!(eods.len(), 1);
+        assert_eq!(eods[0], EoD {
+            date: NaiveDate::from_ymd_opt(2014, 1, 2).unwrap(),
+            open: 1115.46,
+            high: 1117.75,
+            low: 1108.26,
+            close: 1113.12,
+            volume: 3639100,
+            adj_open: 27.9728495066623,
+            adj_high: 28.0302767791511,
+            adj_low: 27.7922921433791,
+            adj_close: 27.9141683635953,
+            adj_volume: 145114657,
+            dividend: 0.0,
+            split: 1.0
+        });
+    }
+
+    #[test]
+    fn test_parse_eod_error() {
+        assert!(parse_eod("[]").unwrap().is_empty());
+        assert!(parse_eod("date,open,high,low,close,volume,adjClose,adjHigh,adjLow,adjOpen,adjVolume,divCash,splitFactor\n").unwrap().is_empty());
+
+        let error = parse_eod(r#"{"detail": "Error: Ticker 'XXXX' not found"}"#).err().unwrap();
+        assert!(matches!(error, TiingoError::Vendor(ref detail) if detail == "Error: Ticker 'XXXX' not found"));
+
+        // Missing column and malformed value
+        let error = parse_eod("date,open\n2014-01-02,1115.46\n").err().unwrap();
+        assert!(matches!(error, TiingoError::Parse(_)));
+        let error = parse_eod("date,open,high,low,close,volume,adjClose,adjHigh,adjLow,adjOpen,adjVolume,divCash,splitFactor\n\
+            2014-01-02,x,1,1,1,1,1,1,1,1,1,0,1\n").err().unwrap();
+        assert!(matches!(error, TiingoError::Parse(_)));
+    }
+
+    #[tokio::test]
+    async fn test_get_eod() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/tiingo/daily/GOOGL/prices"))
+            .and(query_param("format", "csv"))
+            .and(query_param("token", API_KEY))
+            .and(query_param("startDate", "2014-01-01"))
+            .and(query_param("endDate", "2014-01-03"))
+            .and(query_param("resampleFreq", "daily"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(include_str!("../fixtures/eod_googl.csv")))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let eods = mock_client(&server).get_eod(
+            "GOOGL",
+            &NaiveDate::from_ymd_opt(2014, 1, 1),
+            &NaiveDate::from_ymd_opt(2014, 1, 3),
+            &Some(ResampleFreq::DAILY)
+        ).await.unwrap();
+
+        assert_eq!(eods.len(), 2);
+        assert_eq!(eods[0].date, NaiveDate::from_ymd_opt(2014, 1, 2).unwrap());
+        assert_eq!(eods[0].close, 1113.12);
+        assert_eq!(eods[1].date, NaiveDate::from_ymd_opt(2014, 1, 3).unwrap());
+        assert_eq!(eods[1].adj_volume, 132803960);
+    }
+
+    #[tokio::test]
+    async fn test_get_eod_csv_edge_cases() {
+        let server = MockServer::start().await;
+        mount(&server, "/tiingo/daily/GOOGL/prices", 200, include_str!("../fixtures/eod_extra_columns.csv")).await;
+        mount(&server, "/tiingo/daily/EMPTY/prices", 200, include_str!("../fixtures/eod_empty.csv")).await;
+        mount(&server, "/tiingo/daily/JSON/prices", 200, "[]").await;
+        mount(&server, "/tiingo/daily/DETAIL/prices", 200, include_str!("../fixtures/error_not_found.json")).await;
+        let client = mock_client(&server);
+
+        // Reordered, unknown and quoted columns with CRLF line endings
+        let eods = client.get_eod("GOOGL", &None, &None, &None).await.unwrap();
+        let from_fixture = parse_eod(include_str!("../fixtures/eod_googl.csv")).unwrap();
+        assert_eq!(eods, from_fixture[..1]);
+
+        // No data in the range, as a bare header or an empty JSON array
+        assert!(client.get_eod("EMPTY", &None, &None, &None).await.unwrap().is_empty());
+        assert!(client.get_eod("JSON", &None, &None, &None).await.unwrap().is_empty());
+
+        // A JSON error body in place of CSV
+        let error = client.get_eod("DETAIL", &None, &None, &None).await.err().unwrap();
+        assert!(matches!(error, TiingoError::Vendor(ref detail) if detail == "Error: Ticker 'XXXX' not found"));
+    }
+
+    #[tokio::test]
+    async fn test_get_metadata() {
+        let server = MockServer::start().await;
+        mount(&server, "/tiingo/daily/GOOGL", 200, include_str!("../fixtures/meta_googl.json")).await;
+
+        let metadata = mock_client(&server).get_metadata("GOOGL").await.unwrap();
+        assert_eq!(metadata.ticker, "GOOGL");
+        assert_eq!(metadata.name, "Alphabet Inc - Class A");
+        assert_eq!(metadata.exchange_code, "NASDAQ");
+        assert_eq!(metadata.start_date, NaiveDate::from_ymd_opt(2004, 8, 19).unwrap());
+        assert_eq!(metadata.end_date, NaiveDate::from_ymd_opt(2023, 8, 1).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_error_statuses() {
+        let cases = [
+            (401, include_str!("../fixtures/error_unauthorized.json"), ErrorKind::Unauthorized),
+            (404, include_str!("../fixtures/error_not_found.json"), ErrorKind::NotFound),
+            (429, include_str!("../fixtures/error_rate_limited.json"), ErrorKind::RateLimited),
+            (503, "Service Unavailable", ErrorKind::Server),
+            (400, "Bad Request", ErrorKind::Status),
+            // An error reported in a successful response
+            (200, include_str!("../fixtures/error_not_found.json"), ErrorKind::Vendor),
+            (200, "<html></html>", ErrorKind::Parse),
+        ];
+        for (status, body, kind) in cases {
+            let server = MockServer::start().await;
+            mount(&server, "/tiingo/daily/GOOGL", status, body).await;
+
+            let error = mock_client(&server).get_metadata("GOOGL").await.err().unwrap();
+            assert_eq!(error.kind(), kind, "status {status}");
+        }
+
+        // Messages are taken from the error body
+        let server = MockServer::start().await;
+        mount(&server, "/tiingo/daily/GOOGL", 401, include_str!("../fixtures/error_unauthorized.json")).await;
+        let error = mock_client(&server).get_metadata("GOOGL").await.err().unwrap();
+        assert!(matches!(error, TiingoError::Unauthorized { status: 401, ref message } if message == "Invalid token."));
+    }
+
+    #[tokio::test]
+    async fn test_transport_error() {
+        // Nothing listens on the port once the listener is dropped
+        let address = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let client = TiingoRESTClient::builder(API_KEY).base_url(&format!("http://{address}")).build();
+        let error = client.get_metadata("GOOGL").await.err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::Transport);
+        // The request URL carries the API token
+        assert!(!error.to_string().contains(API_KEY));
+        assert!(!format!("{error:?}").contains(API_KEY));
+    }
+
+    #[tokio::test]
+    async fn test_market_data_source() {
+        let server = MockServer::start().await;
+        mount(&server, "/tiingo/daily/GOOGL/prices", 200, include_str!("../fixtures/eod_googl.csv")).await;
+        let client = mock_client(&server);
+        let start = NaiveDate::from_ymd_opt(2014, 1, 1).unwrap();
+        let end = NaiveDate::from_ymd_opt(2014, 1, 3).unwrap();
+
+        let bars = client.get_bars("GOOGL", &start, &end, &Interval::Days(1)).await.unwrap();
+        assert_eq!(bars.len(), 2);
+        assert_eq!(bars[0].timestamp.date_naive(), NaiveDate::from_ymd_opt(2014, 1, 2).unwrap());
+        assert_eq!(bars[0].volume, 3639100.0);
+
+        let error = client.get_bars("GOOGL", &start, &end, &Interval::Hours(1)).await.err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::Unsupported);
+    }
+
+    #[tokio::test]
+    async fn test_daily_quota() {
+        let server = MockServer::start().await;
+        mount(&server, "/tiingo/daily/GOOGL", 200, include_str!("../fixtures/meta_googl.json")).await;
+        // Clients sharing a limiter share the quota
+        let limit = RateLimit::new(10, Duration::from_secs(1)).with_daily_quota(1);
+        let client = TiingoRESTClient::builder(API_KEY).base_url(&server.uri()).rate_limit(limit).build();
+        let other = TiingoRESTClient::builder(API_KEY).base_url(&server.uri()).rate_limiter(client.rate_limiter().clone()).build();
+
+        client.get_metadata("GOOGL").await.unwrap();
+        let error = other.get_metadata("GOOGL").await.err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::QuotaExceeded);
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+        assert_eq!(client.quota().unwrap().requests, 1);
+    }
+
+    // Live API tests. Run with `cargo test -- --ignored` and TIINGO_API_KEY set
     #[test]
+    #[ignore = "requires TIINGO_API_KEY"]
     fn test_get_api_key() {
-        let k: String = get_api_key();
+        let k: String = get_api_key().expect("TIINGO_API_KEY is not set");
         let l = k.len();
         assert_eq!(l, 40);
     }
 
     #[tokio::test]
+    #[ignore = "requires TIINGO_API_KEY and network access"]
     async fn test_rest_api() {
         let client = Client::new();
+        let limiter = RateLimiter::new(DEFAULT_RATE_LIMIT);
         // Metadata
-        let fetched_result = get_metadata("GOOGL", &client, &get_api_key()).await.unwrap();
+        let fetched_result = get_metadata("GOOGL", &client, &limiter, DEFAULT_BASE_URL, &get_api_key().unwrap()).await.unwrap();
         assert_eq!(fetched_result.ticker, "GOOGL");
         assert_eq!(fetched_result.name, "Alphabet Inc - Class A");
 
@@ -86,7 +352,9 @@ mod tests {
         let fetched_result = get_eod(
             "GOOGL",
             &client,
-            &get_api_key(),
+            &limiter,
+            DEFAULT_BASE_URL,
+            &get_api_key().unwrap(),
             &Some(test_start), 
             &Some(test_end),
             &Some(ResampleFreq::DAILY)
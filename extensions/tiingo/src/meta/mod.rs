@@ -1,10 +1,12 @@
 use core::fmt;
-use std::error::Error;
 
 use reqwest::Client;
 use chrono::NaiveDate;
+use quantify_core::RateLimiter;
 use serde_json::Value;
 
+use crate::error::{error_detail, response_text, TiingoError};
+
 pub struct Metadata {
     /// Ticker related to the asset
     pub ticker: String,
@@ -28,7 +30,7 @@ impl fmt::Display for Metadata {
 
 impl PartialEq for Metadata {
     fn eq(&self, other: &Self) -> bool {
-        return self.ticker == other.ticker
+        self.ticker == other.ticker
             && self.name == other.name
             && self.exchange_code == other.exchange_code
             && self.description == other.description
@@ -40,22 +42,27 @@ impl PartialEq for Metadata {
 pub(super) async fn get_metadata(
     ticker: &str,
     client: &Client,
+    limiter: &RateLimiter,
+    base_url: &str,
     api_key: &str
-) -> Result<Metadata, Box<dyn Error + Send + Sync>> {
+) -> Result<Metadata, TiingoError> {
     // Construct request
-    let request: String = String::from(format!("https://api.tiingo.com/tiingo/daily/{}?token={}", ticker, api_key));
+    let request = format!("{}/tiingo/daily/{}?token={}", base_url, ticker, api_key);
 
     // Send request
-    let response: String = client
+    limiter.acquire().await?;
+    let response = client
         .get(request)
         .header("Content-Type", "application/json")
         .send()
-        .await?
-        .text()
         .await?;
+    let response = response_text(response, ticker).await?;
 
     // Parse response
-    let v: Value = serde_json::from_str(&response).unwrap();
+    if let Some(detail) = error_detail(&response) {
+        return Err(TiingoError::Vendor(detail));
+    }
+    let v: Value = serde_json::from_str(&response)?;
     let start_date = v["startDate"].to_string()
         .trim_matches('"').parse::<NaiveDate>()?;
     let end_date = v["endDate"].to_string()
@@ -67,7 +74,7 @@ pub(super) async fn get_metadata(
         name: v["name"].to_string().trim_matches('"').to_string(),
         exchange_code: v["exchangeCode"].to_string().trim_matches('"').to_string(),
         description: v["description"].to_string().trim_matches('"').to_string(),
-        start_date: start_date,
-        end_date: end_date
+        start_date,
+        end_date
     })
 }
\ No newline at end of file
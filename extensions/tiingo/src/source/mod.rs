@@ -0,0 +1,69 @@
+use chrono::{NaiveDate, TimeZone, Utc};
+use quantify_core::{async_trait, Bar, ErrorKind, Interval, MarketDataSource, Metadata, QuotaUsage, SourceError, SourceResult};
+
+use crate::{eod::{EoD, ResampleFreq}, TiingoRESTClient};
+
+/// Tiingo only serves end-of-day data, resampled to single units
+fn resample_freq(interval: &Interval) -> Option<ResampleFreq> {
+    match interval {
+        Interval::Days(1) => Some(ResampleFreq::DAILY),
+        Interval::Weeks(1) => Some(ResampleFreq::WEEKLY),
+        Interval::Months(1) => Some(ResampleFreq::MONTHLY),
+        _ => None,
+    }
+}
+
+fn to_bar(data: &EoD) -> Bar {
+    // Tiingo does not report transactions or VWAP
+    Bar {
+        timestamp: Utc.from_utc_datetime(&data.date.and_hms_opt(0, 0, 0).unwrap()),
+        open: data.open,
+        high: data.high,
+        low: data.low,
+        close: data.close,
+        volume: data.volume as f64,
+        num_transactions: None,
+        vwap: None,
+        // The adjusted prices are also adjusted for dividends, so the raw prices are used
+        adjusted: false,
+    }
+}
+
+#[async_trait]
+impl MarketDataSource for TiingoRESTClient {
+    fn name(&self) -> &str {
+        "tiingo"
+    }
+
+    fn supports(&self, interval: &Interval) -> bool {
+        resample_freq(interval).is_some()
+    }
+
+    fn quota(&self) -> Option<QuotaUsage> {
+        Some(self.rate_limiter().usage())
+    }
+
+    async fn get_metadata(&self, ticker: &str) -> SourceResult<Metadata> {
+        let meta = TiingoRESTClient::get_metadata(self, ticker).await?;
+        Ok(Metadata {
+            ticker: meta.ticker,
+            name: meta.name,
+            exchange: meta.exchange_code,
+        })
+    }
+
+    async fn get_bars(
+        &self,
+        ticker: &str,
+        start_date: &NaiveDate,
+        end_date: &NaiveDate,
+        interval: &Interval,
+    ) -> SourceResult<Vec<Bar>> {
+        let resample_freq = match resample_freq(interval) {
+            Some(freq) => freq,
+            None => return Err(SourceError::new(ErrorKind::Unsupported, format!("Tiingo does not support {interval} bars"))),
+        };
+        let eods = self.get_eod(ticker, &Some(*start_date), &Some(*end_date), &Some(resample_freq)).await?;
+        Ok(eods.iter().map(to_bar).collect())
+    }
+}
@@ -0,0 +1,193 @@
+use core::fmt;
+use std::error::Error;
+
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
+use quantify_core::RateLimiter;
+use reqwest::Client;
+use serde::Deserialize;
+
+// Yahoo rejects requests without a browser-like user agent
+const USER_AGENT: &str = "Mozilla/5.0";
+
+/// An object created from the Yahoo Finance chart endpoint
+pub struct Chart {
+    /// Exchange symbol of the asset
+    pub symbol: String,
+    /// Company or asset name
+    pub name: String,
+    /// Exchange code (eg. NMS)
+    pub exchange: String,
+    /// Offset of the exchange time zone from UTC, in seconds
+    pub gmt_offset: i64,
+    /// Candles, sorted by time
+    pub quotes: Vec<Quote>,
+}
+
+/// A single candle
+#[derive(Debug, PartialEq)]
+pub struct Quote {
+    // Start of the window
+    pub datetime: DateTime<Utc>,
+    // The opening price for the asset in the given window
+    pub open: f64,
+    // The high price for the asset in the given window
+    pub high: f64,
+    // The low price for the asset in the given window
+    pub low: f64,
+    // The closing price for the asset in the given window
+    pub close: f64,
+    // The number of shares traded for the asset in the given window
+    pub volume: f64,
+}
+
+impl fmt::Display for Chart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Chart: {} ({}, {})", self.symbol, self.name, self.exchange)
+    }
+}
+
+// Error-handling for responses
+#[derive(Debug)]
+pub struct YahooResponseError {
+    pub(crate) error: String,
+}
+
+impl Error for YahooResponseError {}
+
+impl fmt::Display for YahooResponseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.error.as_str())
+    }
+}
+
+#[derive(Deserialize)]
+struct ChartResponse {
+    chart: ChartBody,
+}
+
+#[derive(Deserialize)]
+struct ChartBody {
+    result: Option<Vec<ChartResult>>,
+    error: Option<ChartError>,
+}
+
+#[derive(Deserialize)]
+struct ChartError {
+    code: String,
+    description: String,
+}
+
+#[derive(Deserialize)]
+struct ChartResult {
+    meta: ChartMeta,
+    #[serde(default)]
+    timestamp: Vec<i64>,
+    indicators: Indicators,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct ChartMeta {
+    symbol: String,
+    long_name: Option<String>,
+    short_name: Option<String>,
+    exchange_name: String,
+    gmtoffset: i64,
+}
+
+#[derive(Deserialize)]
+struct Indicators {
+    quote: Vec<QuoteIndicator>,
+}
+
+// Yahoo reports missing values as null
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct QuoteIndicator {
+    open: Vec<Option<f64>>,
+    high: Vec<Option<f64>>,
+    low: Vec<Option<f64>>,
+    close: Vec<Option<f64>>,
+    volume: Vec<Option<f64>>,
+}
+
+/// Parses a chart response body, skipping candles with missing values
+pub(super) fn parse_chart(response: &str) -> Result<Chart, Box<dyn Error + Send + Sync>> {
+    let res: ChartResponse = serde_json::from_str(response)?;
+    if let Some(error) = res.chart.error {
+        return Err(Box::new(YahooResponseError { error: format!("{}: {}", error.code, error.description) }));
+    }
+    let result = match res.chart.result.and_then(|mut results| results.pop()) {
+        Some(result) => result,
+        None => return Err(Box::new(YahooResponseError { error: String::from("Empty chart response") })),
+    };
+
+    let mut quotes = Vec::new();
+    if let Some(indicator) = result.indicators.quote.first() {
+        for (i, timestamp) in result.timestamp.iter().enumerate() {
+            let value = |values: &Vec<Option<f64>>| values.get(i).copied().flatten();
+            let (Some(open), Some(high), Some(low), Some(close)) = (
+                value(&indicator.open), value(&indicator.high), value(&indicator.low), value(&indicator.close)
+            ) else {
+                continue;
+            };
+            let datetime = match Utc.timestamp_opt(*timestamp, 0).single() {
+                Some(datetime) => datetime,
+                None => continue,
+            };
+            quotes.push(Quote { datetime, open, high, low, close, volume: value(&indicator.volume).unwrap_or(0.0) });
+        }
+    }
+
+    let meta = result.meta;
+    Ok(Chart {
+        name: meta.long_name.or(meta.short_name).unwrap_or_else(|| meta.symbol.clone()),
+        symbol: meta.symbol,
+        exchange: meta.exchange_name,
+        gmt_offset: meta.gmtoffset,
+        quotes,
+    })
+}
+
+/// Get chart data
+///
+/// # Arguments to pass into API
+///
+/// ticker - Target ticker
+/// start_date - Start date of the data fetched, inclusive
+/// end_date - End date of the data fetched, inclusive
+/// interval - Yahoo interval code (eg. 1m, 1h, 1d, 1wk, 1mo)
+///
+/// The request waits for the rate limiter.
+pub(super) async fn get_chart(
+    ticker: &str,
+    client: &Client,
+    limiter: &RateLimiter,
+    base_url: &str,
+    start_date: &NaiveDate,
+    end_date: &NaiveDate,
+    interval: &str,
+) -> Result<Chart, Box<dyn Error + Send + Sync>> {
+    let period = |date: NaiveDate| Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap()).timestamp();
+    // Construct request
+    let request = format!(
+        "{}/v8/finance/chart/{}?period1={}&period2={}&interval={}",
+        base_url,
+        ticker,
+        period(*start_date),
+        period(*end_date + Duration::days(1)),
+        interval
+    );
+
+    // Send request. Await response
+    limiter.acquire().await?;
+    let response = client
+        .get(request)
+        .header("User-Agent", USER_AGENT)
+        .send()
+        .await?
+        .text()
+        .await?;
+
+    parse_chart(&response)
+}
@@ -0,0 +1,158 @@
+use std::{env, error::Error, sync::Arc, time::Duration};
+use quantify_core::{RateLimit, RateLimiter};
+use reqwest::Client;
+use chrono::NaiveDate;
+use chart::get_chart;
+
+// Re-exporting
+pub use chart::{Chart, Quote, YahooResponseError};
+
+mod chart;
+mod source;
+
+/// The Yahoo Finance query API
+pub const DEFAULT_BASE_URL: &str = "https://query1.finance.yahoo.com";
+
+/// A conservative request rate, as Yahoo Finance publishes no limit but blocks bursts
+pub const DEFAULT_RATE_LIMIT: RateLimit = RateLimit::new(30, Duration::from_secs(60));
+
+/// A client to access the Yahoo Finance chart API
+///
+/// Yahoo Finance does not require an API key
+pub struct YahooFinanceClient {
+    web_client: Client,
+    limiter: Arc<RateLimiter>,
+    base_url: String,
+}
+
+impl Default for YahooFinanceClient {
+    fn default() -> Self {
+        YahooFinanceClient::builder().build()
+    }
+}
+
+impl YahooFinanceClient {
+    /// Creates a new YahooFinanceClient with the default base URL
+    ///
+    /// # Arguments
+    ///
+    /// * `web_client`
+    pub fn new(web_client: Client) -> YahooFinanceClient {
+        YahooFinanceClient::builder().web_client(web_client).build()
+    }
+
+    /// Returns a builder for a YahooFinanceClient
+    pub fn builder() -> YahooFinanceClientBuilder {
+        YahooFinanceClientBuilder { web_client: None, limiter: None, base_url: String::from(DEFAULT_BASE_URL) }
+    }
+
+    /// The rate limiter every request waits for
+    pub fn rate_limiter(&self) -> &Arc<RateLimiter> {
+        &self.limiter
+    }
+
+    /// Gets chart (candle) data and ticker metadata
+    pub async fn get_chart(
+        &self,
+        ticker: &str,
+        start_date: &NaiveDate,
+        end_date: &NaiveDate,
+        interval: &str) -> Result<Chart, Box<dyn Error + Send + Sync>>
+    {
+        get_chart(ticker, &self.web_client, &self.limiter, &self.base_url, start_date, end_date, interval).await
+    }
+}
+
+/// Builds a [YahooFinanceClient]
+pub struct YahooFinanceClientBuilder {
+    web_client: Option<Client>,
+    limiter: Option<Arc<RateLimiter>>,
+    base_url: String,
+}
+
+impl YahooFinanceClientBuilder {
+    /// Sets the reqwest client. A new one is created otherwise
+    pub fn web_client(mut self, web_client: Client) -> YahooFinanceClientBuilder {
+        self.web_client = Some(web_client);
+        self
+    }
+
+    /// Sets the base URL (eg. a mock server), without a trailing slash
+    pub fn base_url(mut self, base_url: &str) -> YahooFinanceClientBuilder {
+        self.base_url = String::from(base_url.trim_end_matches('/'));
+        self
+    }
+
+    /// Sets the request rate. [DEFAULT_RATE_LIMIT] otherwise
+    pub fn rate_limit(self, limit: RateLimit) -> YahooFinanceClientBuilder {
+        self.rate_limiter(Arc::new(RateLimiter::new(limit)))
+    }
+
+    /// Shares a rate limiter with other clients
+    pub fn rate_limiter(mut self, limiter: Arc<RateLimiter>) -> YahooFinanceClientBuilder {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    /// Builds the client
+    pub fn build(self) -> YahooFinanceClient {
+        YahooFinanceClient {
+            web_client: self.web_client.unwrap_or_default(),
+            limiter: self.limiter.unwrap_or_else(|| Arc::new(RateLimiter::new(DEFAULT_RATE_LIMIT))),
+            base_url: self.base_url,
+        }
+    }
+}
+
+/// Returns false if env variable YFINANCE_DISABLED is set to 1 or true
+pub fn is_enabled() -> bool {
+    !matches!(env::var("YFINANCE_DISABLED").as_deref().map(str::trim), Ok("1" | "true"))
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use crate::chart::parse_chart;
+    use crate::Quote;
+
+    #[test]
+    fn test_parse_chart() {
+        let response = r#"{"chart":{"result":[{
+            "meta":{"symbol":"AAPL","longName":"Apple Inc.","exchangeName":"NMS","gmtoffset":-14400},
+            "timestamp":[1690896600,1690983000],
+            "indicators":{"quote":[{
+                "open":[196.24,null],"high":[196.73,195.18],"low":[195.28,191.85],
+                "close":[195.61,192.58],"volume":[35175100,50389300]}]}}],"error":null}}"#;
+        let chart = parse_chart(response).unwrap();
+
+        assert_eq!(chart.symbol, "AAPL");
+        assert_eq!(chart.name, "Apple Inc.");
+        assert_eq!(chart.exchange, "NMS");
+        // Candles with missing values are skipped
+        assert_eq!(chart.quotes, vec![Quote {
+            datetime: Utc.with_ymd_and_hms(2023, 8, 1, 13, 30, 0).unwrap(),
+            open: 196.24,
+            high: 196.73,
+            low: 195.28,
+            close: 195.61,
+            volume: 35175100.0,
+        }]);
+    }
+
+    #[test]
+    fn test_chart_display() {
+        let response = r#"{"chart":{"result":[{
+            "meta":{"symbol":"NFLX","longName":"Netflix, Inc.","exchangeName":"NMS","gmtoffset":-14400},
+            "indicators":{"quote":[{}]}}],"error":null}}"#;
+        let chart = parse_chart(response).unwrap();
+        assert_eq!(chart.to_string(), "Chart: NFLX (Netflix, Inc., NMS)");
+    }
+
+    #[test]
+    fn test_parse_chart_error() {
+        let response = r#"{"chart":{"result":null,"error":{"code":"Not Found","description":"No data found, symbol may be delisted"}}}"#;
+        let error = parse_chart(response).err().unwrap();
+        assert_eq!(error.to_string(), "Not Found: No data found, symbol may be delisted");
+    }
+}
@@ -0,0 +1,107 @@
+use std::error::Error;
+
+use chrono::{Duration, NaiveDate, TimeZone, Utc};
+use quantify_core::{async_trait, Bar, ErrorKind, Interval, MarketDataSource, Metadata, QuotaExceeded, QuotaUsage, SourceError, SourceResult};
+
+use crate::{chart::Quote, YahooFinanceClient, YahooResponseError};
+
+/// Yahoo interval code for the supported intervals
+fn interval_code(interval: &Interval) -> Option<&'static str> {
+    match interval {
+        Interval::Minutes(1) => Some("1m"),
+        Interval::Minutes(2) => Some("2m"),
+        Interval::Minutes(5) => Some("5m"),
+        Interval::Minutes(15) => Some("15m"),
+        Interval::Minutes(30) => Some("30m"),
+        Interval::Minutes(90) => Some("90m"),
+        Interval::Hours(1) => Some("1h"),
+        Interval::Days(1) => Some("1d"),
+        Interval::Days(5) => Some("5d"),
+        Interval::Weeks(1) => Some("1wk"),
+        Interval::Months(1) => Some("1mo"),
+        Interval::Months(3) => Some("3mo"),
+        _ => None,
+    }
+}
+
+/// Classifies a chart error by downcasting
+fn source_error(error: Box<dyn Error + Send + Sync>) -> SourceError {
+    let kind = if error.is::<reqwest::Error>() {
+        ErrorKind::Transport
+    } else if error.is::<QuotaExceeded>() {
+        ErrorKind::QuotaExceeded
+    } else if error.is::<serde_json::Error>() {
+        ErrorKind::Parse
+    } else {
+        match error.downcast_ref::<YahooResponseError>() {
+            // Unknown symbols are reported as {"code": "Not Found", ...}
+            Some(e) if e.error.starts_with("Not Found") => ErrorKind::NotFound,
+            _ => ErrorKind::Vendor,
+        }
+    };
+    SourceError::new(kind, error)
+}
+
+fn to_bar(quote: &Quote, interval: &Interval, gmt_offset: i64) -> Bar {
+    let timestamp = match interval {
+        Interval::Minutes(_) | Interval::Hours(_) => quote.datetime,
+        // Yahoo timestamps daily and coarser bars at the market open
+        _ => {
+            let date = (quote.datetime + Duration::seconds(gmt_offset)).date_naive();
+            Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap())
+        },
+    };
+    // Yahoo does not report transactions or VWAP
+    Bar {
+        timestamp,
+        open: quote.open,
+        high: quote.high,
+        low: quote.low,
+        close: quote.close,
+        volume: quote.volume,
+        num_transactions: None,
+        vwap: None,
+        // Chart prices are adjusted for splits, but not dividends
+        adjusted: true,
+    }
+}
+
+#[async_trait]
+impl MarketDataSource for YahooFinanceClient {
+    fn name(&self) -> &str {
+        "yfinance"
+    }
+
+    fn supports(&self, interval: &Interval) -> bool {
+        interval_code(interval).is_some()
+    }
+
+    fn quota(&self) -> Option<QuotaUsage> {
+        Some(self.rate_limiter().usage())
+    }
+
+    async fn get_metadata(&self, ticker: &str) -> SourceResult<Metadata> {
+        let today = Utc::now().date_naive();
+        let chart = self.get_chart(&ticker.to_uppercase(), &today, &today, "1d").await.map_err(source_error)?;
+        Ok(Metadata {
+            ticker: chart.symbol,
+            name: chart.name,
+            exchange: chart.exchange,
+        })
+    }
+
+    async fn get_bars(
+        &self,
+        ticker: &str,
+        start_date: &NaiveDate,
+        end_date: &NaiveDate,
+        interval: &Interval,
+    ) -> SourceResult<Vec<Bar>> {
+        let code = match interval_code(interval) {
+            Some(code) => code,
+            None => return Err(SourceError::new(ErrorKind::Unsupported, format!("Yahoo Finance does not support {interval} bars"))),
+        };
+        let chart = self.get_chart(&ticker.to_uppercase(), start_date, end_date, code).await.map_err(source_error)?;
+        Ok(chart.quotes.iter().map(|quote| to_bar(quote, interval, chart.gmt_offset)).collect())
+    }
+}
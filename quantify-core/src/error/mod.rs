@@ -0,0 +1,90 @@
+use core::fmt;
+use std::error::Error;
+
+/// Classification of market data source failures
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The request could not be sent, or the response could not be read
+    Transport,
+    /// The credentials were rejected (HTTP 401 / 403)
+    Unauthorized,
+    /// The ticker is unknown to the source (HTTP 404)
+    NotFound,
+    /// Too many requests (HTTP 429)
+    RateLimited,
+    /// The daily request quota is used, until the next UTC day
+    QuotaExceeded,
+    /// The source failed internally (HTTP 5xx)
+    Server,
+    /// Any other unexpected HTTP status
+    Status,
+    /// The source reported an error in a successful response
+    Vendor,
+    /// The response could not be parsed
+    Parse,
+    /// The request is not supported by the source (eg. an interval)
+    Unsupported,
+}
+
+impl ErrorKind {
+    /// Whether the same request may succeed if retried later
+    pub fn is_transient(&self) -> bool {
+        matches!(self, ErrorKind::Transport | ErrorKind::RateLimited | ErrorKind::Server)
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            ErrorKind::Transport => "transport error",
+            ErrorKind::Unauthorized => "unauthorized",
+            ErrorKind::NotFound => "not found",
+            ErrorKind::RateLimited => "rate limited",
+            ErrorKind::QuotaExceeded => "quota exceeded",
+            ErrorKind::Server => "server error",
+            ErrorKind::Status => "unexpected status",
+            ErrorKind::Vendor => "vendor error",
+            ErrorKind::Parse => "parse error",
+            ErrorKind::Unsupported => "unsupported",
+        };
+        write!(f, "{description}")
+    }
+}
+
+/// A market data source failure
+///
+/// Wraps the vendor specific error, which can be recovered with [Error::source] and downcasting.
+#[derive(Debug)]
+pub struct SourceError {
+    kind: ErrorKind,
+    error: Box<dyn Error + Send + Sync>,
+}
+
+impl SourceError {
+    /// Creates a new SourceError
+    ///
+    /// # Arguments
+    ///
+    /// * 'kind' - Classification of the failure
+    /// * 'error' - The underlying error, or a message
+    pub fn new(kind: ErrorKind, error: impl Into<Box<dyn Error + Send + Sync>>) -> SourceError {
+        SourceError { kind, error: error.into() }
+    }
+
+    /// Classification of the failure
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+}
+
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.error, self.kind)
+    }
+}
+
+impl Error for SourceError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.error.as_ref())
+    }
+}
@@ -0,0 +1,13 @@
+//! Provider-agnostic interfaces shared by quantify-data and the extension crates
+
+// Re-exporting
+pub use error::{ErrorKind, SourceError};
+pub use limiter::{QuotaExceeded, QuotaUsage, RateLimit, RateLimiter};
+pub use registry::SourceRegistry;
+pub use source::{Bar, Interval, MarketDataSource, Metadata, SourceResult};
+pub use async_trait::async_trait;
+
+mod error;
+mod limiter;
+mod registry;
+mod source;
@@ -0,0 +1,241 @@
+use core::fmt;
+use std::{error::Error, str::FromStr, sync::Mutex, time::Duration};
+
+use chrono::{NaiveDate, Utc};
+use tokio::time::Instant;
+
+use crate::{ErrorKind, SourceError};
+
+/// Request rate allowed by a vendor
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    /// Requests per period, which can also be sent at once
+    pub requests: u32,
+    pub period: Duration,
+    /// Requests per UTC day, if limited
+    pub daily_quota: Option<u64>,
+}
+
+impl RateLimit {
+    /// Creates a RateLimit without a daily quota
+    pub const fn new(requests: u32, period: Duration) -> RateLimit {
+        RateLimit { requests, period, daily_quota: None }
+    }
+
+    /// Sets the requests allowed per UTC day
+    pub const fn with_daily_quota(self, daily_quota: u64) -> RateLimit {
+        RateLimit { daily_quota: Some(daily_quota), ..self }
+    }
+
+    /// Reads the rate limit of a vendor from the environment
+    ///
+    /// `{PREFIX}_RATE_LIMIT` sets the requests per period (eg. "5/60s", see [RateLimit::from_str])
+    /// and `{PREFIX}_DAILY_QUOTA` the requests per day. Unset variables keep the default.
+    pub fn from_env(prefix: &str, default: RateLimit) -> Result<RateLimit, String> {
+        let mut limit = match std::env::var(format!("{prefix}_RATE_LIMIT")) {
+            Ok(spec) => RateLimit { daily_quota: default.daily_quota, ..spec.parse()? },
+            Err(_) => default,
+        };
+        if let Ok(quota) = std::env::var(format!("{prefix}_DAILY_QUOTA")) {
+            limit.daily_quota = Some(quota.trim().parse().map_err(|_| format!("Invalid daily quota {quota}"))?);
+        }
+        Ok(limit)
+    }
+
+    /// Tokens added to the bucket per second
+    fn rate(&self) -> f64 {
+        f64::from(self.requests) / self.period.as_secs_f64()
+    }
+}
+
+impl FromStr for RateLimit {
+    type Err = String;
+
+    /// Parses requests per period, eg. "5/60s", "5/min", "50/h" or "100/1"
+    fn from_str(spec: &str) -> Result<RateLimit, String> {
+        let invalid = || format!("Invalid rate limit {spec}, expected requests/period (eg. 5/60s)");
+        let (requests, period) = spec.trim().split_once('/').ok_or_else(invalid)?;
+        let requests: u32 = requests.trim().parse().map_err(|_| invalid())?;
+        let period = period.trim();
+        let seconds: u64 = match period {
+            "s" | "sec" => 1,
+            "m" | "min" => 60,
+            "h" | "hour" => 3600,
+            "d" | "day" => 86400,
+            _ => period.trim_end_matches('s').parse().map_err(|_| invalid())?,
+        };
+        if requests == 0 || seconds == 0 {
+            return Err(invalid());
+        }
+        Ok(RateLimit::new(requests, Duration::from_secs(seconds)))
+    }
+}
+
+impl fmt::Display for RateLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}s", self.requests, self.period.as_secs_f64())?;
+        if let Some(quota) = self.daily_quota {
+            write!(f, ", {quota}/day")?;
+        }
+        Ok(())
+    }
+}
+
+/// The daily quota of a vendor is used
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuotaExceeded {
+    pub daily_quota: u64,
+}
+
+impl fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "daily quota of {} requests used", self.daily_quota)
+    }
+}
+
+impl Error for QuotaExceeded {}
+
+impl From<QuotaExceeded> for SourceError {
+    fn from(e: QuotaExceeded) -> Self {
+        SourceError::new(ErrorKind::QuotaExceeded, e)
+    }
+}
+
+/// Requests sent to a vendor today
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuotaUsage {
+    pub limit: RateLimit,
+    /// The UTC day counted
+    pub date: NaiveDate,
+    pub requests: u64,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+    date: NaiveDate,
+    requests: u64,
+}
+
+/// A token bucket shared by the requests to a vendor
+///
+/// Starts full, so that up to [RateLimit::requests] can be sent at once.
+pub struct RateLimiter {
+    limit: RateLimit,
+    bucket: Mutex<Bucket>,
+}
+
+impl RateLimiter {
+    /// Creates a RateLimiter
+    pub fn new(limit: RateLimit) -> RateLimiter {
+        RateLimiter {
+            limit,
+            bucket: Mutex::new(Bucket {
+                tokens: f64::from(limit.requests),
+                refilled_at: Instant::now(),
+                date: Utc::now().date_naive(),
+                requests: 0,
+            }),
+        }
+    }
+
+    /// The configured rate limit
+    pub fn limit(&self) -> RateLimit {
+        self.limit
+    }
+
+    /// Waits until a request can be sent, and counts it
+    ///
+    /// Fails once the daily quota is used.
+    pub async fn acquire(&self) -> Result<(), QuotaExceeded> {
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().unwrap();
+                self.refill(&mut bucket);
+                if let Some(daily_quota) = self.limit.daily_quota.filter(|quota| bucket.requests >= *quota) {
+                    return Err(QuotaExceeded { daily_quota });
+                }
+                if bucket.tokens >= 1.0 {
+                    bucket.tokens -= 1.0;
+                    bucket.requests += 1;
+                    return Ok(());
+                }
+                Duration::from_secs_f64((1.0 - bucket.tokens) / self.limit.rate())
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Requests counted today
+    pub fn usage(&self) -> QuotaUsage {
+        let mut bucket = self.bucket.lock().unwrap();
+        self.refill(&mut bucket);
+        QuotaUsage { limit: self.limit, date: bucket.date, requests: bucket.requests }
+    }
+
+    /// Adds the tokens earned since the last refill, and resets the daily count on a new day
+    fn refill(&self, bucket: &mut Bucket) {
+        let now = Instant::now();
+        let earned = now.duration_since(bucket.refilled_at).as_secs_f64() * self.limit.rate();
+        bucket.tokens = (bucket.tokens + earned).min(f64::from(self.limit.requests));
+        bucket.refilled_at = now;
+
+        let today = Utc::now().date_naive();
+        if today != bucket.date {
+            bucket.date = today;
+            bucket.requests = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use crate::{ErrorKind, RateLimit, RateLimiter, SourceError};
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("5/60s".parse(), Ok(RateLimit::new(5, Duration::from_secs(60))));
+        assert_eq!("5/min".parse(), Ok(RateLimit::new(5, Duration::from_secs(60))));
+        assert_eq!(" 50 / h ".parse(), Ok(RateLimit::new(50, Duration::from_secs(3600))));
+        assert_eq!("100/1".parse(), Ok(RateLimit::new(100, Duration::from_secs(1))));
+        for invalid in ["5", "0/min", "5/0", "x/min", "5/fortnight"] {
+            assert!(invalid.parse::<RateLimit>().is_err(), "{invalid}");
+        }
+        assert_eq!(RateLimit::new(5, Duration::from_secs(60)).with_daily_quota(100).to_string(), "5/60s, 100/day");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_acquire() {
+        let limiter = RateLimiter::new(RateLimit::new(2, Duration::from_secs(60)));
+        let start = Instant::now();
+
+        // The bucket starts full
+        limiter.acquire().await.unwrap();
+        limiter.acquire().await.unwrap();
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        // Then refills at 1 token per 30s
+        limiter.acquire().await.unwrap();
+        assert!(start.elapsed() >= Duration::from_secs(30));
+        limiter.acquire().await.unwrap();
+        assert!(start.elapsed() >= Duration::from_secs(60));
+        assert_eq!(limiter.usage().requests, 4);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_daily_quota() {
+        let limiter = RateLimiter::new(RateLimit::new(10, Duration::from_secs(1)).with_daily_quota(2));
+        limiter.acquire().await.unwrap();
+        limiter.acquire().await.unwrap();
+
+        let error = limiter.acquire().await.err().unwrap();
+        assert_eq!(error.daily_quota, 2);
+        assert_eq!(SourceError::from(error).kind(), ErrorKind::QuotaExceeded);
+        assert!(!ErrorKind::QuotaExceeded.is_transient());
+        assert_eq!(limiter.usage().requests, 2);
+    }
+}
@@ -0,0 +1,87 @@
+use std::sync::Arc;
+
+use crate::source::MarketDataSource;
+
+/// An ordered collection of market data sources
+///
+/// Order is significant: earlier sources take priority when results are combined.
+#[derive(Default, Clone)]
+pub struct SourceRegistry {
+    sources: Vec<Arc<dyn MarketDataSource>>,
+}
+
+impl SourceRegistry {
+    /// Creates an empty registry
+    pub fn new() -> SourceRegistry {
+        SourceRegistry { sources: Vec::new() }
+    }
+
+    /// Adds a source with lower priority than all sources already registered
+    pub fn register(&mut self, source: Arc<dyn MarketDataSource>) {
+        self.sources.push(source);
+    }
+
+    /// Finds a source by name
+    pub fn get(&self, name: &str) -> Option<&Arc<dyn MarketDataSource>> {
+        self.sources.iter().find(|source| source.name() == name)
+    }
+
+    /// Iterates over sources in priority order
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<dyn MarketDataSource>> {
+        self.sources.iter()
+    }
+
+    /// The number of registered sources
+    pub fn len(&self) -> usize {
+        self.sources.len()
+    }
+
+    /// Whether no sources are registered
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use chrono::NaiveDate;
+
+    use crate::{Bar, Interval, MarketDataSource, Metadata, SourceRegistry, SourceResult};
+
+    struct NamedSource(&'static str);
+
+    #[async_trait]
+    impl MarketDataSource for NamedSource {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn supports(&self, _interval: &Interval) -> bool {
+            true
+        }
+
+        async fn get_metadata(&self, ticker: &str) -> SourceResult<Metadata> {
+            Ok(Metadata { ticker: ticker.to_string(), name: self.0.to_string(), exchange: String::new() })
+        }
+
+        async fn get_bars(&self, _ticker: &str, _start_date: &NaiveDate, _end_date: &NaiveDate, _interval: &Interval) -> SourceResult<Vec<Bar>> {
+            Ok(Vec::new())
+        }
+    }
+
+    #[test]
+    fn test_registry_order() {
+        let mut registry = SourceRegistry::new();
+        registry.register(Arc::new(NamedSource("first")));
+        registry.register(Arc::new(NamedSource("second")));
+
+        let names: Vec<&str> = registry.iter().map(|source| source.name()).collect();
+        assert_eq!(names, vec!["first", "second"]);
+        assert_eq!(registry.get("second").unwrap().name(), "second");
+        assert!(registry.get("third").is_none());
+        assert_eq!(registry.len(), 2);
+    }
+}
@@ -0,0 +1,111 @@
+use core::fmt;
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+
+use crate::{QuotaUsage, SourceError};
+
+/// Result of a market data source request
+pub type SourceResult<T> = Result<T, SourceError>;
+
+/// Ticker metadata common to all sources
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metadata {
+    /// Exchange symbol of the asset, as reported by the source
+    pub ticker: String,
+    /// Company or asset name
+    pub name: String,
+    /// The exchange the asset is listed on, in the source's notation
+    pub exchange: String,
+}
+
+impl fmt::Display for Metadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Metadata: {}{}{}", self.ticker, self.name, self.exchange)
+    }
+}
+
+/// An OHLCV bar common to all sources
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bar {
+    /// Start of the bar. Daily and coarser bars start at midnight UTC of their first trading date
+    pub timestamp: DateTime<Utc>,
+    /// The opening price in the window
+    pub open: f64,
+    /// The high price in the window
+    pub high: f64,
+    /// The low price in the window
+    pub low: f64,
+    /// The closing price in the window
+    pub close: f64,
+    /// The number of shares traded in the window
+    pub volume: f64,
+    /// The number of transactions in the window, if reported by the source
+    pub num_transactions: Option<u64>,
+    /// The volume weighted average price, if reported by the source
+    pub vwap: Option<f64>,
+    /// Whether prices are adjusted for splits
+    pub adjusted: bool,
+}
+
+/// Bar size, as a multiplier of a time unit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interval {
+    Minutes(u32),
+    Hours(u32),
+    Days(u32),
+    Weeks(u32),
+    Months(u32),
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Interval::Minutes(m) => write!(f, "{m} minute"),
+            Interval::Hours(m) => write!(f, "{m} hour"),
+            Interval::Days(m) => write!(f, "{m} day"),
+            Interval::Weeks(m) => write!(f, "{m} week"),
+            Interval::Months(m) => write!(f, "{m} month"),
+        }
+    }
+}
+
+/// A vendor of market data
+///
+/// Implemented by each extension crate, so that consumers can treat vendors uniformly.
+#[async_trait]
+pub trait MarketDataSource: Send + Sync {
+    /// A short unique name of the source (eg. "polygon")
+    fn name(&self) -> &str;
+
+    /// Whether the source can provide bars of the given interval
+    fn supports(&self, interval: &Interval) -> bool;
+
+    /// Requests sent today, if the source is rate limited
+    fn quota(&self) -> Option<QuotaUsage> {
+        None
+    }
+
+    /// Gets ticker metadata
+    ///
+    /// # Arguments
+    ///
+    /// * 'ticker' - The financial ticker, in any case
+    async fn get_metadata(&self, ticker: &str) -> SourceResult<Metadata>;
+
+    /// Gets bars sorted by time
+    ///
+    /// # Arguments
+    ///
+    /// * 'ticker' - The financial ticker, in any case
+    /// * 'start_date' - First date of the range, inclusive
+    /// * 'end_date' - Last date of the range, inclusive
+    /// * 'interval' - Bar size. Must be supported by the source
+    async fn get_bars(
+        &self,
+        ticker: &str,
+        start_date: &NaiveDate,
+        end_date: &NaiveDate,
+        interval: &Interval,
+    ) -> SourceResult<Vec<Bar>>;
+}
@@ -20,7 +20,7 @@ pub struct QuantifyDataPoller {
 impl QuantifyDataPoller {
     pub async fn connect(addr: &str) -> Result<QuantifyDataPoller, tonic::transport::Error> {
         let client = QuantifyDataClient::connect(String::from(addr)).await?;
-        return Ok(QuantifyDataPoller{client});
+        Ok(QuantifyDataPoller{client})
     }
 
     pub async fn update_candle_data(&mut self, ticker: &str) -> Result<(), tonic::Status> {
@@ -0,0 +1,98 @@
+use std::{error::Error, fmt, future::Future, time::Duration};
+
+use tokio_util::sync::CancellationToken;
+
+/// How long interrupted tasks may take to record their state before being dropped
+pub(super) const GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// Why a task was stopped before finishing
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interrupted {
+    /// Its token, or the token of a parent task, was cancelled
+    Cancelled,
+    /// It ran longer than the executor's task timeout
+    TimedOut(Duration)
+}
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Interrupted::Cancelled => write!(f, "Task cancelled"),
+            Interrupted::TimedOut(timeout) => write!(f, "Task timed out after {timeout:?}")
+        }
+    }
+}
+
+impl Error for Interrupted {}
+
+/// Runs a task until it finishes, its token is cancelled, or it times out
+///
+/// Interrupted tasks keep running for a grace period, so that tasks watching their
+/// token may record their state and stop. They are dropped at their next await point
+/// afterwards. A timeout cancels the token, which stops the tasks it started.
+pub(super) async fn run<F>(task: F, cancel: &CancellationToken, timeout: Option<Duration>, grace: Duration) -> Result<(), Box<dyn Error + Send + Sync>>
+where F: Future<Output = Result<(), Box<dyn Error + Send + Sync>>>
+{
+    let deadline = async {
+        match timeout {
+            Some(timeout) => tokio::time::sleep(timeout).await,
+            None => futures::future::pending().await
+        }
+    };
+    let mut task = std::pin::pin!(task);
+    let interrupted = tokio::select! {
+        // Tasks finishing on cancellation return their own result
+        biased;
+        result = &mut task => return result,
+        _ = cancel.cancelled() => Interrupted::Cancelled,
+        _ = deadline => {
+            cancel.cancel();
+            Interrupted::TimedOut(timeout.unwrap_or_default())
+        }
+    };
+    let _ = tokio::time::timeout(grace, task).await;
+    Err(interrupted.into())
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio_util::sync::CancellationToken;
+
+    use super::{run, Interrupted};
+
+    const GRACE: Duration = Duration::from_millis(10);
+
+    #[tokio::test]
+    async fn test_run() {
+        let cancel = CancellationToken::new();
+        assert!(run(async { Ok(()) }, &cancel, Some(Duration::from_millis(10)), GRACE).await.is_ok());
+
+        // Timeouts cancel the tasks started by the interrupted one
+        let child = cancel.child_token();
+        let result = run(futures::future::pending(), &cancel, Some(Duration::from_millis(10)), GRACE).await;
+        assert_eq!(result.unwrap_err().downcast_ref(), Some(&Interrupted::TimedOut(Duration::from_millis(10))));
+        assert!(child.is_cancelled());
+
+        let result = run(futures::future::pending(), &child, None, GRACE).await;
+        assert_eq!(result.unwrap_err().downcast_ref(), Some(&Interrupted::Cancelled));
+    }
+
+    // Test that interrupted tasks may record their state before stopping
+    #[tokio::test]
+    async fn test_run_grace() {
+        let cancel = CancellationToken::new();
+        let stopped = std::sync::Mutex::new(false);
+        let task = async {
+            cancel.cancelled().await;
+            tokio::task::yield_now().await;
+            *stopped.lock().unwrap() = true;
+            Err(Interrupted::Cancelled)?
+        };
+        let result = run(task, &cancel, Some(Duration::from_millis(10)), GRACE).await;
+        assert_eq!(result.unwrap_err().downcast_ref(), Some(&Interrupted::TimedOut(Duration::from_millis(10))));
+        assert!(*stopped.lock().unwrap());
+    }
+}
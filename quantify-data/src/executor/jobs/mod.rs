@@ -0,0 +1,407 @@
+use std::{collections::HashMap, sync::{Arc, Mutex}};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use mongodb::bson::{oid::ObjectId, serde_helpers::{chrono_datetime_as_bson_datetime, chrono_datetime_as_bson_datetime_optional}};
+use serde::{Serialize, Deserialize};
+use tokio_util::sync::CancellationToken;
+
+use super::{Executor, TaskFactory, TaskHandle, queue::Priority, retry::Retry};
+use super::tasks::{AddTickerTask, BackfillTask, DataPolicy, Granularity, RemoveTickerTask, UpdateCandleDataTask};
+
+/// Lifecycle of a submitted task
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JobState {
+    /// Submitted, not started yet
+    Queued,
+    Running,
+    Succeeded,
+    /// Returned an error, or panicked
+    Failed,
+    /// Stopped by [JobRegistry::cancel], or by the cancellation of its parent task
+    Cancelled,
+    /// Ran longer than its task timeout
+    TimedOut
+}
+
+/// Serializable description of a task, run again by the executor reclaiming its job
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum JobSpec {
+    AddTicker {
+        ticker: String,
+        granularities: Vec<Granularity>,
+        /// First date backfilled once the ticker is subscribed, if any
+        #[serde(default)]
+        backfill_from: Option<NaiveDate>
+    },
+    RemoveTicker { ticker: String, data_policy: DataPolicy },
+    UpdateCandleData {
+        ticker: String,
+        granularity: Granularity,
+        /// Dates fetched, inclusive, since the latest stored candle if not set
+        #[serde(default)]
+        range: Option<(NaiveDate, NaiveDate)>
+    },
+    Backfill { ticker: String, granularity: Granularity, start_date: NaiveDate }
+}
+
+impl JobSpec {
+    /// Human readable description of the task
+    pub fn name(&self) -> String {
+        match self {
+            JobSpec::AddTicker { ticker, .. } => format!("Add ticker {ticker}"),
+            JobSpec::RemoveTicker { ticker, .. } => format!("Remove ticker {ticker}"),
+            JobSpec::UpdateCandleData { ticker, granularity, .. } => format!("Update {ticker} candles ({granularity:?})"),
+            JobSpec::Backfill { ticker, granularity, start_date } =>
+                format!("Backfill {}", BackfillTask::new(ticker, *granularity, *start_date).job_id())
+        }
+    }
+
+    /// The ticker whose candles the task writes, if any
+    pub fn candle_ticker(&self) -> Option<&str> {
+        match self {
+            JobSpec::AddTicker { ticker, .. } | JobSpec::UpdateCandleData { ticker, .. } | JobSpec::Backfill { ticker, .. } => Some(ticker),
+            JobSpec::RemoveTicker { .. } => None
+        }
+    }
+
+    /// Runs the task described under an existing job, retried as when first submitted
+    pub(super) fn resume(&self, executor: &Arc<Executor>, record: JobRecord) -> TaskHandle {
+        match self {
+            JobSpec::AddTicker { ticker, granularities, backfill_from } => {
+                let task = AddTickerTask::new(ticker, granularities);
+                let task = Arc::new(match backfill_from {
+                    Some(start_date) => task.with_backfill(*start_date),
+                    None => task
+                });
+                let handle = executor.run_job(record, &executor.retrying(&task));
+                // Backfills start once the ticker is subscribed, as when first submitted
+                let executor = executor.clone();
+                tokio::spawn(async move {
+                    handle.await??;
+                    task.submit_backfills(&executor);
+                    Ok(())
+                })
+            },
+            JobSpec::RemoveTicker { ticker, data_policy } =>
+                executor.run_job(record, &Arc::new(RemoveTickerTask::new(ticker, *data_policy))),
+            JobSpec::UpdateCandleData { ticker, granularity, range } => {
+                let task = match range {
+                    Some((start_date, end_date)) => UpdateCandleDataTask::for_range(ticker, *granularity, *start_date, *end_date),
+                    None => UpdateCandleDataTask::new(ticker, *granularity)
+                };
+                executor.run_job(record, &executor.retrying(&Arc::new(task)))
+            },
+            JobSpec::Backfill { ticker, granularity, start_date } =>
+                executor.run_job(record, &Arc::new(BackfillTask::new(ticker, *granularity, *start_date)))
+        }
+    }
+}
+
+/// A task described by a [JobSpec], so that its job survives restarts
+pub trait Resumable: TaskFactory {
+    /// The description of the task
+    fn spec(&self) -> JobSpec;
+}
+
+impl<T: Resumable + Send + Sync + 'static> Resumable for Retry<T> {
+    fn spec(&self) -> JobSpec {
+        self.task().spec()
+    }
+}
+
+/// A task submitted to the executor
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JobRecord {
+    pub id: String,
+    /// Human readable description of the task
+    pub name: String,
+    pub state: JobState,
+    #[serde(with = "chrono_datetime_as_bson_datetime")]
+    pub created_at: DateTime<Utc>,
+    #[serde(default, with = "chrono_datetime_as_bson_datetime_optional")]
+    pub started_at: Option<DateTime<Utc>>,
+    #[serde(default, with = "chrono_datetime_as_bson_datetime_optional")]
+    pub finished_at: Option<DateTime<Utc>>,
+    /// Cause of the failure
+    pub error: Option<String>,
+    /// The task, if the job can be reclaimed by another executor
+    #[serde(default)]
+    pub spec: Option<JobSpec>,
+    #[serde(default)]
+    pub priority: Priority,
+    /// Executor holding the lease of an unfinished job
+    #[serde(default)]
+    pub owner: Option<String>,
+    /// Time after which the job may be reclaimed, unless its owner renews the lease
+    #[serde(default, with = "chrono_datetime_as_bson_datetime_optional")]
+    pub lease_expires_at: Option<DateTime<Utc>>
+}
+
+impl JobRecord {
+    pub(super) fn new(name: &str, priority: Priority, spec: Option<JobSpec>) -> JobRecord {
+        JobRecord {
+            id: ObjectId::new().to_hex(),
+            name: String::from(name),
+            state: JobState::Queued,
+            created_at: Utc::now(),
+            started_at: None,
+            finished_at: None,
+            error: None,
+            spec,
+            priority,
+            owner: None,
+            lease_expires_at: None
+        }
+    }
+
+    /// Whether the job is queued or running
+    pub fn is_unfinished(&self) -> bool {
+        matches!(self.state, JobState::Queued | JobState::Running)
+    }
+
+    /// Whether the job is unleased, or its lease expired before a time
+    pub fn lease_expired(&self, now: DateTime<Utc>) -> bool {
+        self.lease_expires_at.is_none_or(|expires| expires < now)
+    }
+}
+
+struct ActiveJob {
+    record: JobRecord,
+    cancel: CancellationToken
+}
+
+/// Tracks the jobs which are not finished
+///
+/// Finished jobs are only kept in storage.
+pub struct JobRegistry {
+    active: Mutex<HashMap<String, ActiveJob>>
+}
+
+impl JobRegistry {
+    pub(super) fn new() -> JobRegistry {
+        JobRegistry { active: Mutex::new(HashMap::new()) }
+    }
+
+    /// Registers a queued job, stopped by cancelling the token
+    pub(super) fn insert(&self, record: &JobRecord, cancel: CancellationToken) {
+        self.active.lock().unwrap().insert(record.id.clone(), ActiveJob { record: record.clone(), cancel });
+    }
+
+    /// Records the renewed lease of a job
+    pub(super) fn renew(&self, id: &str, lease_expires_at: DateTime<Utc>) {
+        if let Some(job) = self.active.lock().unwrap().get_mut(id) {
+            job.record.lease_expires_at = Some(lease_expires_at);
+        }
+    }
+
+    /// Marks a job as running
+    pub(super) fn start(&self, id: &str) -> Option<JobRecord> {
+        let mut active = self.active.lock().unwrap();
+        let job = active.get_mut(id)?;
+        job.record.state = JobState::Running;
+        job.record.started_at = Some(Utc::now());
+        Some(job.record.clone())
+    }
+
+    /// Records the outcome of a job, which stays active until [JobRegistry::remove]
+    pub(super) fn finish(&self, id: &str, state: JobState, error: Option<String>) -> Option<JobRecord> {
+        let mut active = self.active.lock().unwrap();
+        let job = active.get_mut(id)?;
+        job.record.state = state;
+        job.record.finished_at = Some(Utc::now());
+        job.record.error = error;
+        Some(job.record.clone())
+    }
+
+    /// Forgets a finished job
+    pub(super) fn remove(&self, id: &str) {
+        self.active.lock().unwrap().remove(id);
+    }
+
+    /// Gets an active job
+    pub fn get(&self, id: &str) -> Option<JobRecord> {
+        self.active.lock().unwrap().get(id).map(|job| job.record.clone())
+    }
+
+    /// Lists the active jobs
+    pub fn list(&self) -> Vec<JobRecord> {
+        self.active.lock().unwrap().values().map(|job| job.record.clone()).collect()
+    }
+
+    /// Stops a queued or running job, and the tasks it started
+    ///
+    /// Returns false if the job is not active
+    pub fn cancel(&self, id: &str) -> bool {
+        match self.active.lock().unwrap().get(id).filter(|job| job.record.finished_at.is_none()) {
+            Some(job) => {
+                job.cancel.cancel();
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Stops the queued or running jobs writing the candles of a ticker, returning their ids
+    pub fn cancel_ticker(&self, ticker: &str) -> Vec<String> {
+        self.active.lock().unwrap().values()
+            .filter(|job| job.record.finished_at.is_none())
+            .filter(|job| job.record.spec.as_ref().and_then(JobSpec::candle_ticker).is_some_and(|t| t.eq_ignore_ascii_case(ticker)))
+            .map(|job| {
+                job.cancel.cancel();
+                job.record.id.clone()
+            })
+            .collect()
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use chrono::{DateTime, Utc};
+    use quantify_core::SourceRegistry;
+    use tokio_util::sync::CancellationToken;
+
+    use super::{JobRecord, JobSpec, JobState};
+    use crate::executor::{Executor, Task, TaskFactory, cancel::Interrupted};
+    use crate::executor::queue::Priority;
+    use crate::executor::storage::Storage;
+    use crate::executor::tasks::{DataPolicy, Granularity, resolver::ConsensusEngine};
+    use crate::executor::testing::executor;
+
+    /// Fails if asked to, or never finishes until cancelled
+    struct TestTask {
+        error: Option<&'static str>,
+        pending: bool
+    }
+    impl TaskFactory for TestTask {
+        fn init(this: Arc<Self>, _executor: Arc<Executor>, _storage: Arc<dyn Storage>, _client: reqwest::Client, cancel: CancellationToken) -> Task {
+            Box::new(async move {
+                if this.pending {
+                    cancel.cancelled().await;
+                    Err(Interrupted::Cancelled)?;
+                }
+                match this.error {
+                    Some(e) => Err(e.into()),
+                    None => Ok(())
+                }
+            })
+        }
+    }
+
+    /// A running job of a stopped executor
+    fn stopped(name: &str, spec: Option<JobSpec>, lease_expires_at: DateTime<Utc>) -> JobRecord {
+        let mut record = JobRecord::new(name, Priority::Normal, spec);
+        record.state = JobState::Running;
+        record.owner = Some(String::from("stopped"));
+        record.lease_expires_at = Some(lease_expires_at);
+        record
+    }
+
+    #[tokio::test]
+    async fn test_submit() {
+        let (exec, storage) = executor(Vec::new());
+
+        let (id, handle) = exec.submit("succeeds", Priority::Normal, &Arc::new(TestTask { error: None, pending: false }));
+        handle.await.unwrap().unwrap();
+        let job = storage.get_job(&id).await.unwrap().unwrap();
+        assert_eq!((job.name.as_str(), job.state, job.error), ("succeeds", JobState::Succeeded, None));
+        assert!(job.started_at.is_some_and(|started| started <= job.finished_at.unwrap()));
+
+        let (id, handle) = exec.submit("fails", Priority::Normal, &Arc::new(TestTask { error: Some("no data"), pending: false }));
+        assert_eq!(handle.await.unwrap().unwrap_err().to_string(), "no data");
+        let job = exec.job(&id).await.unwrap().unwrap();
+        assert_eq!((job.state, job.error.as_deref()), (JobState::Failed, Some("no data")));
+
+        let jobs = exec.list_jobs(10).await.unwrap();
+        assert_eq!(jobs.iter().map(|job| job.name.as_str()).collect::<Vec<_>>(), vec!["fails", "succeeds"]);
+        assert_eq!(exec.list_jobs(1).await.unwrap().len(), 1);
+        assert!(exec.job("missing").await.unwrap().is_none());
+        assert!(exec.jobs().list().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cancel() {
+        let (exec, storage) = executor(Vec::new());
+
+        let (id, handle) = exec.submit("pending", Priority::Normal, &Arc::new(TestTask { error: None, pending: true }));
+        for _ in 0..100 {
+            if exec.jobs().get(&id).is_some_and(|job| job.state == JobState::Running) {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+        }
+        assert_eq!(exec.job(&id).await.unwrap().unwrap().state, JobState::Running);
+
+        assert!(exec.jobs().cancel(&id));
+        assert!(handle.await.unwrap().is_err());
+        assert_eq!(storage.get_job(&id).await.unwrap().unwrap().state, JobState::Cancelled);
+        // Finished jobs can not be cancelled
+        assert!(!exec.jobs().cancel(&id));
+    }
+
+    #[test]
+    fn test_spec_bson() {
+        let date = chrono::NaiveDate::from_ymd_opt(2023, 1, 1).unwrap();
+        let spec = JobSpec::UpdateCandleData { ticker: String::from("nflx"), granularity: Granularity::Days(1), range: Some((date, date)) };
+        let record = stopped(&spec.name(), Some(spec), Utc::now());
+        let document = mongodb::bson::to_document(&record).unwrap();
+        assert_eq!(document.get_document("spec").unwrap().get_str("kind"), Ok("update_candle_data"));
+        let stored: JobRecord = mongodb::bson::from_document(document).unwrap();
+        assert_eq!(stored.spec, record.spec);
+        assert_eq!(stored.priority, Priority::Normal);
+    }
+
+    #[tokio::test]
+    async fn test_reclaim() {
+        let (exec, storage) = executor(Vec::new());
+        let expired = Utc::now() - chrono::Duration::seconds(1);
+        let spec = JobSpec::RemoveTicker { ticker: String::from("nflx"), data_policy: DataPolicy::Keep };
+        let resumable = stopped(&spec.name(), Some(spec.clone()), expired);
+        let lost = stopped("not resumable", None, expired);
+        let leased = stopped("leased", Some(spec), Utc::now() + chrono::Duration::minutes(1));
+        for record in [&resumable, &lost, &leased] {
+            storage.upsert_job(record).await.unwrap();
+        }
+
+        let resumed = exec.reclaim_jobs().await.unwrap();
+        assert_eq!(resumed.iter().map(|(id, _)| id).collect::<Vec<_>>(), vec![&resumable.id]);
+        for (_, handle) in resumed {
+            handle.await.unwrap().unwrap();
+        }
+        let job = storage.get_job(&resumable.id).await.unwrap().unwrap();
+        assert_eq!((job.name.as_str(), job.state), ("Remove ticker nflx", JobState::Succeeded));
+        assert_ne!(job.owner.as_deref(), Some("stopped"));
+        let job = storage.get_job(&lost.id).await.unwrap().unwrap();
+        assert_eq!((job.state, job.error.as_deref()), (JobState::Failed, Some("Interrupted by a stopped server")));
+
+        // Unexpired leases are kept by their owner
+        assert_eq!(storage.get_job(&leased.id).await.unwrap(), Some(leased.clone()));
+        assert!(exec.reclaim_jobs().await.unwrap().is_empty());
+        let stale = JobRecord { owner: Some(String::from("stale")), ..leased };
+        assert!(storage.upsert_job(&stale).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_lease() {
+        let (_, storage) = executor(Vec::new());
+        let lease = Duration::from_millis(30);
+        let exec = Arc::new(Executor::new(storage.clone(), reqwest::Client::new(), SourceRegistry::new(), ConsensusEngine::default(), 0.005)
+            .with_job_lease(lease));
+        let other = Arc::new(Executor::new(storage.clone(), reqwest::Client::new(), SourceRegistry::new(), ConsensusEngine::default(), 0.005));
+
+        let (id, handle) = exec.submit("pending", Priority::Normal, &Arc::new(TestTask { error: None, pending: true }));
+        tokio::time::sleep(lease * 3).await;
+        // Renewed while running
+        let job = storage.get_job(&id).await.unwrap().unwrap();
+        assert!(job.lease_expires_at.is_some_and(|expires| expires > Utc::now()));
+        assert!(other.reclaim_jobs().await.unwrap().is_empty());
+        assert!(storage.claim_job(&id, "other", Utc::now()).await.unwrap().is_none());
+
+        exec.jobs().cancel(&id);
+        assert!(handle.await.unwrap().is_err());
+        assert!(storage.list_expired_jobs(Utc::now() + chrono::Duration::minutes(1)).await.unwrap().is_empty());
+    }
+}
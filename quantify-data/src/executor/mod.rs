@@ -1,41 +1,229 @@
 #![warn(missing_docs)]
 
 use core::future::Future;
-use std::{sync::Arc, error::Error};
+use std::{sync::Arc, error::Error, env, time::Duration};
 
-use mongodb::{self, options::ClientOptions, Database};
-use tokio::{spawn, task::JoinHandle};
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use quantify_core::{RateLimit, SourceRegistry};
+use storage::{BackfillStatus, MemoryStorage, MongoStorage, Storage};
+use tasks::{BackfillTask, resolver::ConsensusEngine};
+use tokio::{spawn, sync::{oneshot, Semaphore}, task::JoinHandle};
+use tokio_util::sync::CancellationToken;
 
-const QUANTIFY_DATABASE: &str = "quantify";
+/// Database URI selecting in-memory storage
+const MEMORY_URI: &str = "memory://";
+const CONSENSUS: &str = "QUANTIFY_CONSENSUS";
+const DISCREPANCY_TOLERANCE: &str = "QUANTIFY_DISCREPANCY_TOLERANCE";
+const DEFAULT_DISCREPANCY_TOLERANCE: f64 = 0.005;
+const WORKERS: &str = "QUANTIFY_WORKERS";
+const QUEUE_CAPACITY: &str = "QUANTIFY_QUEUE_CAPACITY";
+const DEFAULT_WORKERS: usize = 8;
+const DEFAULT_QUEUE_CAPACITY: usize = 1024;
+const TASK_TIMEOUT: &str = "QUANTIFY_TASK_TIMEOUT";
+const DEFAULT_TASK_TIMEOUT: Duration = Duration::from_secs(3600);
+const JOB_LEASE: &str = "QUANTIFY_JOB_LEASE";
+const DEFAULT_JOB_LEASE: Duration = Duration::from_secs(30);
+const MAX_STREAMS: &str = "QUANTIFY_MAX_STREAMS";
+const DEFAULT_MAX_STREAMS: usize = 16;
 
 pub mod tasks;
+pub mod cancel;
+pub mod scheduler;
+pub mod storage;
+pub mod jobs;
+pub mod queue;
+pub mod retry;
+#[cfg(test)]
+pub mod testing;
+
+use cancel::Interrupted;
+use jobs::{JobRecord, JobRegistry, JobSpec, JobState, Resumable};
+use queue::{Priority, WorkQueue};
+use retry::{Retry, RetryPolicy};
+use scheduler::{JobId, Schedule, Scheduler};
 
 /// Asynchronously manages execution of tasks
 /// 
-/// Handles on the quantify database and scheduler(TODO).
+/// Handles on the quantify storage, market data sources and scheduler.
 pub struct Executor {
-    db_ref: Database,
-    client: reqwest::Client
+    storage: Arc<dyn Storage>,
+    client: reqwest::Client,
+    sources: SourceRegistry,
+    consensus: ConsensusEngine,
+    discrepancy_tolerance: f64,
+    scheduler: Scheduler,
+    jobs: JobRegistry,
+    retry_policy: RetryPolicy,
+    queue: WorkQueue,
+    /// Maximum number of children of a task running at once
+    child_limit: usize,
+    task_timeout: Option<Duration>,
+    /// Slots of the tasks streaming to clients, which run outside the queue
+    streams: Arc<Semaphore>,
+    /// Owner of the leases of the jobs run by this executor
+    instance: String,
+    job_lease: Duration
+}
+
+/// State of the running task, shared with the tasks it starts
+struct TaskContext {
+    /// Parent of the tokens of its children
+    cancel: CancellationToken,
+    /// Slots of its children running at once
+    children: Arc<Semaphore>
+}
+
+tokio::task_local! {
+    /// Set while a task runs, so that the tasks it starts are known to be its children
+    static IN_TASK: TaskContext;
 }
 impl Executor {
     /// Constructs a new executor
     ///
     /// # Arguments
     /// 
-    /// * 'uri' - A string slice that represents the mongo database connection
+    /// * 'uri' - A string slice that represents the mongo database connection,
+    ///   or "memory://" for storage lost on exit
     ///
-    pub async fn build(uri: &str) -> Result<Executor, mongodb::error::Error>
+    /// Pending migrations are applied to a mongo database.
+    /// Sources are weighted by priority in the consensus engine, which
+    /// is configured by QUANTIFY_CONSENSUS (see [ConsensusEngine::parse]).
+    /// QUANTIFY_DISCREPANCY_TOLERANCE sets the relative difference above which
+    /// sources are considered in disagreement. Vendor request rates are configured
+    /// by POLYGON_RATE_LIMIT, TIINGO_RATE_LIMIT, YFINANCE_RATE_LIMIT, and the _DAILY_QUOTA counterparts
+    /// (see [RateLimit::from_env]). QUANTIFY_WORKERS bounds the tasks running at once,
+    /// and QUANTIFY_QUEUE_CAPACITY the tasks waiting for them. QUANTIFY_TASK_TIMEOUT
+    /// sets the seconds a task may run, 0 for no timeout, and QUANTIFY_JOB_LEASE the
+    /// seconds after which the jobs of a stopped executor may be reclaimed.
+    /// QUANTIFY_MAX_STREAMS bounds the streams to clients running at once.
+    pub async fn build(uri: &str) -> Result<Executor, Box<dyn Error + Send + Sync>>
     {
-        let mut client_options = ClientOptions::parse(uri).await?;
-        client_options.app_name = Some("Quantify".to_string());
-        let mongo_client = mongodb::Client::with_options(client_options)?; 
-        let db_ref = mongo_client.database(QUANTIFY_DATABASE);
+        let storage: Arc<dyn Storage> = match uri {
+            MEMORY_URI => Arc::new(MemoryStorage::new()),
+            _ => {
+                let storage = MongoStorage::connect(uri).await?;
+                for name in storage.migrate().await? {
+                    println!("Applied migration {name}");
+                }
+                Arc::new(storage)
+            }
+        };
         let client = reqwest::Client::new();
+        let sources = default_sources(&client)?;
+        let consensus = match env::var(CONSENSUS) {
+            Ok(spec) => ConsensusEngine::parse(&spec)?,
+            Err(_) => ConsensusEngine::default()
+        }.with_priority(sources.iter().map(|source| source.name()));
+        let discrepancy_tolerance = match env::var(DISCREPANCY_TOLERANCE) {
+            Ok(tolerance) => tolerance.parse()?,
+            Err(_) => DEFAULT_DISCREPANCY_TOLERANCE
+        };
+
+        let workers = match env::var(WORKERS) {
+            Ok(workers) => workers.parse()?,
+            Err(_) => DEFAULT_WORKERS
+        };
+        let capacity = match env::var(QUEUE_CAPACITY) {
+            Ok(capacity) => capacity.parse()?,
+            Err(_) => DEFAULT_QUEUE_CAPACITY
+        };
+        let task_timeout = match env::var(TASK_TIMEOUT) {
+            Ok(seconds) => Some(Duration::from_secs(seconds.parse()?)).filter(|timeout| !timeout.is_zero()),
+            Err(_) => Some(DEFAULT_TASK_TIMEOUT)
+        };
+        let job_lease = match env::var(JOB_LEASE) {
+            Ok(seconds) => Duration::from_secs(seconds.parse()?),
+            Err(_) => DEFAULT_JOB_LEASE
+        };
+        let max_streams = match env::var(MAX_STREAMS) {
+            Ok(streams) => streams.parse()?,
+            Err(_) => DEFAULT_MAX_STREAMS
+        };
+
+        Ok(Executor::new(storage, client, sources, consensus, discrepancy_tolerance)
+            .with_queue(workers, capacity)
+            .with_task_timeout(task_timeout)
+            .with_job_lease(job_lease)
+            .with_max_streams(max_streams))
+    }
+
+    /// Constructs a new executor from its parts
+    ///
+    /// # Arguments
+    ///
+    /// * 'storage' - Persistence of tickers and candles
+    /// * 'client' - reqwest client
+    /// * 'sources' - Market data sources, in priority order
+    /// * 'consensus' - Resolves data reported by multiple sources
+    /// * 'discrepancy_tolerance' - Relative difference above which sources are considered in disagreement
+    pub fn new(
+        storage: Arc<dyn Storage>,
+        client: reqwest::Client,
+        sources: SourceRegistry,
+        consensus: ConsensusEngine,
+        discrepancy_tolerance: f64
+    ) -> Executor {
+        Executor {
+            storage, client, sources, consensus, discrepancy_tolerance,
+            scheduler: Scheduler::new(), jobs: JobRegistry::new(), retry_policy: RetryPolicy::default(),
+            queue: WorkQueue::new(DEFAULT_WORKERS, DEFAULT_QUEUE_CAPACITY),
+            child_limit: DEFAULT_WORKERS,
+            task_timeout: Some(DEFAULT_TASK_TIMEOUT),
+            streams: Arc::new(Semaphore::new(DEFAULT_MAX_STREAMS)),
+            instance: ObjectId::new().to_hex(),
+            job_lease: DEFAULT_JOB_LEASE
+        }
+    }
 
-        Ok(Executor {db_ref, client})
+    /// Sets the number of tasks running at once, and waiting for them
+    ///
+    /// The children of a task are bounded by the number of workers too.
+    pub fn with_queue(self, workers: usize, capacity: usize) -> Executor {
+        Executor { queue: WorkQueue::new(workers, capacity), child_limit: workers.max(1), ..self }
+    }
+
+    /// Sets how long tasks may run, including the tasks they start, before they are interrupted
+    ///
+    /// See [TaskFactory::timeout].
+    pub fn with_task_timeout(self, task_timeout: Option<Duration>) -> Executor {
+        Executor { task_timeout, ..self }
     }
 
-    /// Runs a task
+    /// Sets how long the jobs of this executor stay leased without being renewed
+    pub fn with_job_lease(self, job_lease: Duration) -> Executor {
+        Executor { job_lease, ..self }
+    }
+
+    /// Sets the number of streams to clients running at once, see [Executor::execute_stream]
+    pub fn with_max_streams(self, streams: usize) -> Executor {
+        Executor { streams: Arc::new(Semaphore::new(streams.max(1))), ..self }
+    }
+
+    /// Sets the retry policy of ingestion tasks, [RetryPolicy::default] otherwise
+    pub fn with_retry_policy(self, retry_policy: RetryPolicy) -> Executor {
+        Executor { retry_policy, ..self }
+    }
+
+    /// Resolves data reported by multiple sources
+    pub fn consensus(&self) -> &ConsensusEngine
+    {
+        &self.consensus
+    }
+
+    /// Relative difference above which sources are considered in disagreement
+    pub fn discrepancy_tolerance(&self) -> f64
+    {
+        self.discrepancy_tolerance
+    }
+
+    /// Market data sources, in priority order
+    pub fn sources(&self) -> &SourceRegistry
+    {
+        &self.sources
+    }
+
+    /// Runs a task, with [Priority::Normal]
     /// 
     /// Calls tokio spawn internally
     /// 
@@ -43,18 +231,350 @@ impl Executor {
     /// 
     /// * 'self' - a reference counted Executor, to ensure lifespan is above all tasks
     /// * 'task' - the task to execute, in the form of a task factory
-    pub fn execute(self: &Arc<Self>, task: &Arc<impl TaskFactory>) -> JoinHandle<Result<(), Box<dyn Error + Send + Sync>>>
+    pub fn execute(self: &Arc<Self>, task: &Arc<impl TaskFactory>) -> TaskHandle
+    {
+        self.execute_with_priority(Priority::Normal, task)
+    }
+
+    /// Runs a task once a worker is free, or fails if too many tasks are waiting
+    ///
+    /// Tasks started by a running task do not wait for a worker, which their parent
+    /// holds until they finish. They wait for one of the parent's child slots instead,
+    /// as many as there are workers, and are cancelled with their parent. Interrupted
+    /// tasks fail with [Interrupted].
+    ///
+    /// # Arguments
+    ///
+    /// * 'self' - a reference counted Executor, to ensure lifespan is above all tasks
+    /// * 'priority' - Order in which waiting tasks are started
+    /// * 'task' - the task to execute, in the form of a task factory
+    pub fn execute_with_priority(self: &Arc<Self>, priority: Priority, task: &Arc<impl TaskFactory>) -> TaskHandle
+    {
+        let (cancel, parent) = task_token();
+        self.spawn_task(task, priority, cancel, parent, None)
+    }
+
+    /// Runs a task paced by a client, such as a stream, once a stream slot is free
+    ///
+    /// Streams do not hold a worker, so that slow clients never delay other tasks.
+    /// They still time out, and are cancelled with their parent if started by a task.
+    pub fn execute_stream(self: &Arc<Self>, task: &Arc<impl TaskFactory>) -> TaskHandle
+    {
+        let (cancel, _) = task_token();
+        self.spawn_task(task, Priority::Interactive, cancel, Some(self.streams.clone()), None)
+    }
+
+    /// Spawns a task, signalling when it starts running
+    fn spawn_task(
+        self: &Arc<Self>,
+        task: &Arc<impl TaskFactory>,
+        priority: Priority,
+        cancel: CancellationToken,
+        slots: Option<Arc<Semaphore>>,
+        started: Option<oneshot::Sender<()>>
+    ) -> TaskHandle
     {
-        let task: Task = TaskFactory::init(
+        let timeout = task.timeout(self.task_timeout);
+        let task = Box::into_pin(TaskFactory::init(
             task.clone(),
             self.clone(), 
-            self.db_ref.clone(),
-            self.client.clone()
-        );
-        spawn(Box::into_pin(task))
+            self.storage.clone(),
+            self.client.clone(),
+            cancel.clone()
+        ));
+        let executor = self.clone();
+        spawn(async move {
+            // Bounded by the given slots instead of the queue
+            let (_worker, _slot) = match slots {
+                Some(slots) => (None, Some(tokio::select! {
+                    slot = slots.acquire_owned() => slot?,
+                    _ = cancel.cancelled() => return Err(Interrupted::Cancelled.into())
+                })),
+                None => (Some(tokio::select! {
+                    worker = executor.queue.acquire(priority) => worker?,
+                    _ = cancel.cancelled() => return Err(Interrupted::Cancelled.into())
+                }), None)
+            };
+            if let Some(started) = started {
+                let _ = started.send(());
+            }
+            let context = TaskContext { cancel: cancel.clone(), children: Arc::new(Semaphore::new(executor.child_limit)) };
+            IN_TASK.scope(context, cancel::run(task, &cancel, timeout, cancel::GRACE_PERIOD)).await
+        })
+    }
+
+    /// Wraps a task to be retried according to the executor's retry policy
+    pub fn retrying<T: TaskFactory>(&self, task: &Arc<T>) -> Arc<Retry<T>>
+    {
+        Arc::new(Retry::new(task, self.retry_policy.clone()))
+    }
+
+    /// Runs a task as a job, tracked until it finishes
+    ///
+    /// The job state is persisted on every change. Errors of the task are recorded,
+    /// and returned through the handle as for [Executor::execute].
+    ///
+    /// # Arguments
+    ///
+    /// * 'self' - a reference counted Executor, to ensure lifespan is above all tasks
+    /// * 'name' - a human readable description of the task
+    /// * 'priority' - Order in which waiting tasks are started, see [Executor::execute_with_priority]
+    /// * 'task' - the task to execute, in the form of a task factory
+    pub fn submit<T: TaskFactory + Send + Sync + 'static>(self: &Arc<Self>, name: &str, priority: Priority, task: &Arc<T>) -> (String, TaskHandle)
+    {
+        let record = JobRecord::new(name, priority, None);
+        (record.id.clone(), self.run_job(record, task))
+    }
+
+    /// Runs a task as a job which survives restarts
+    ///
+    /// The job is stored along with the task's [jobs::JobSpec], from which it runs again
+    /// if this executor stops before it finishes (see [Executor::reclaim_jobs]).
+    ///
+    /// # Arguments
+    ///
+    /// * 'self' - a reference counted Executor, to ensure lifespan is above all tasks
+    /// * 'priority' - Order in which waiting tasks are started, see [Executor::execute_with_priority]
+    /// * 'task' - the task to execute, in the form of a task factory
+    pub fn submit_resumable<T: Resumable + Send + Sync + 'static>(self: &Arc<Self>, priority: Priority, task: &Arc<T>) -> (String, TaskHandle)
+    {
+        let spec = task.spec();
+        let record = JobRecord::new(&spec.name(), priority, Some(spec));
+        (record.id.clone(), self.run_job(record, task))
+    }
+
+    /// Runs a task under a job record, leased by this executor until the task finishes
+    fn run_job<T: TaskFactory + Send + Sync + 'static>(self: &Arc<Self>, mut record: JobRecord, task: &Arc<T>) -> TaskHandle
+    {
+        let (cancel, parent) = task_token();
+        record.owner = Some(self.instance.clone());
+        record.lease_expires_at = Some(self.lease_expiry());
+        self.jobs.insert(&record, cancel.clone());
+        let executor = self.clone();
+        let task = task.clone();
+        spawn(async move {
+            executor.save_job(&record).await;
+            let lease = spawn(executor.clone().renew_lease(record.id.clone(), cancel.clone()));
+
+            // Queued until a worker is free
+            let (started, on_start) = oneshot::channel();
+            let run = executor.spawn_task(&task, record.priority, cancel, parent, Some(started));
+            if on_start.await.is_ok() {
+                if let Some(running) = executor.jobs.start(&record.id) {
+                    executor.save_job(&running).await;
+                }
+            }
+            let (state, result): (JobState, Result<(), Box<dyn Error + Send + Sync>>) = match run.await {
+                Ok(Ok(())) => (JobState::Succeeded, Ok(())),
+                Ok(Err(e)) => match e.downcast_ref() {
+                    Some(Interrupted::Cancelled) => (JobState::Cancelled, Err(e)),
+                    Some(Interrupted::TimedOut(_)) => (JobState::TimedOut, Err(e)),
+                    None => (JobState::Failed, Err(e))
+                },
+                Err(e) => (JobState::Failed, Err(e.into()))
+            };
+            lease.abort();
+
+            if let Some(finished) = executor.jobs.finish(&record.id, state, result.as_ref().err().map(|e| e.to_string())) {
+                executor.save_job(&finished).await;
+            }
+            executor.jobs.remove(&record.id);
+            result
+        })
+    }
+
+    /// Time until which a job claimed now is leased
+    fn lease_expiry(&self) -> DateTime<Utc>
+    {
+        chrono::Duration::from_std(self.job_lease).ok()
+            .and_then(|lease| Utc::now().checked_add_signed(lease))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC)
+    }
+
+    /// Renews the lease of a job until aborted, cancelling the job if another executor claimed it
+    async fn renew_lease(self: Arc<Self>, id: String, cancel: CancellationToken)
+    {
+        let mut renewals = tokio::time::interval((self.job_lease / 3).max(Duration::from_millis(1)));
+        // The first tick completes at once
+        renewals.tick().await;
+        loop {
+            renewals.tick().await;
+            match self.storage.claim_job(&id, &self.instance, self.lease_expiry()).await {
+                Ok(Some(record)) => self.jobs.renew(&id, record.lease_expires_at.unwrap_or_else(|| self.lease_expiry())),
+                Ok(None) => {
+                    println!("Lost the lease of job {id}, cancelling it");
+                    cancel.cancel();
+                    return;
+                },
+                Err(e) => println!("Failed to renew the lease of job {id}: {e}")
+            }
+        }
+    }
+
+    /// Runs again the unfinished jobs of stopped executors, whose lease expired
+    ///
+    /// Each job is claimed before it runs, so that executors starting together never
+    /// share a job. Jobs submitted without a [JobSpec] can not run again, and are
+    /// marked as failed, as are the running backfills no unfinished job resumes.
+    /// Returns the ids and handles of the resumed jobs.
+    pub async fn reclaim_jobs(self: &Arc<Self>) -> Result<Vec<(String, TaskHandle)>, Box<dyn Error + Send + Sync>>
+    {
+        let mut resumed = Vec::new();
+        for expired in self.storage.list_expired_jobs(Utc::now()).await? {
+            // Claimed by another executor since listed
+            let mut record = match self.storage.claim_job(&expired.id, &self.instance, self.lease_expiry()).await? {
+                Some(record) => record,
+                None => continue
+            };
+            match record.spec.clone() {
+                Some(spec) => {
+                    println!("Reclaimed job {} ({})", record.id, record.name);
+                    record.state = JobState::Queued;
+                    record.started_at = None;
+                    resumed.push((record.id.clone(), spec.resume(self, record)));
+                },
+                None => {
+                    record.state = JobState::Failed;
+                    record.finished_at = Some(Utc::now());
+                    record.error = Some(String::from("Interrupted by a stopped server"));
+                    self.save_job(&record).await;
+                }
+            }
+        }
+        self.fail_orphaned_backfills().await?;
+        Ok(resumed)
+    }
+
+    /// Marks as failed the running backfills whose job finished or was lost
+    async fn fail_orphaned_backfills(&self) -> Result<(), Box<dyn Error + Send + Sync>>
+    {
+        // Every unfinished job, whatever its lease
+        let owned: Vec<String> = self.storage.list_expired_jobs(DateTime::<Utc>::MAX_UTC).await?
+            .into_iter()
+            .filter_map(|record| match record.spec {
+                Some(JobSpec::Backfill { ticker, granularity, start_date }) => Some(BackfillTask::new(&ticker, granularity, start_date).job_id()),
+                _ => None
+            })
+            .collect();
+        for mut backfill in self.storage.list_backfill_jobs(BackfillStatus::Running).await? {
+            if owned.contains(&backfill.id) {
+                continue;
+            }
+            println!("Backfill {} is no longer running", backfill.id);
+            backfill.status = BackfillStatus::Failed;
+            backfill.error = Some(String::from("Interrupted by a stopped server"));
+            backfill.updated_at = Utc::now();
+            self.storage.upsert_backfill_job(&backfill).await?;
+        }
+        Ok(())
+    }
+
+    /// Persists a job, logging failures as they do not affect the task
+    async fn save_job(&self, record: &JobRecord)
+    {
+        if let Err(e) = self.storage.upsert_job(record).await {
+            println!("Failed to save job {} ({}): {e}", record.id, record.name);
+        }
+    }
+
+    /// Gets a job, active or finished
+    pub async fn job(&self, id: &str) -> Result<Option<JobRecord>, Box<dyn Error + Send + Sync>>
+    {
+        match self.jobs.get(id) {
+            Some(record) => Ok(Some(record)),
+            None => self.storage.get_job(id).await
+        }
+    }
+
+    /// Lists the most recent jobs, newest first
+    ///
+    /// # Arguments
+    ///
+    /// * 'limit' - Maximum number of jobs returned
+    pub async fn list_jobs(&self, limit: usize) -> Result<Vec<JobRecord>, Box<dyn Error + Send + Sync>>
+    {
+        let mut records = self.jobs.list();
+        for stored in self.storage.list_jobs(limit).await? {
+            if !records.iter().any(|record| record.id == stored.id) {
+                records.push(stored);
+            }
+        }
+        records.sort_by_key(|record| std::cmp::Reverse(record.created_at));
+        records.truncate(limit);
+        Ok(records)
+    }
+
+    /// The registry of active jobs
+    pub fn jobs(&self) -> &JobRegistry
+    {
+        &self.jobs
+    }
+
+    /// Runs a task repeatedly
+    /// 
+    /// Runs of the same job never overlap
+    /// 
+    /// # Arguments
+    /// 
+    /// * 'self' - a reference counted Executor, to ensure lifespan is above all tasks
+    /// * 'name' - a human readable name for the job
+    /// * 'task' - the task to execute, in the form of a task factory
+    /// * 'schedule' - when the task runs
+    pub fn schedule<T: TaskFactory + Send + Sync + 'static>(self: &Arc<Self>, name: &str, task: &Arc<T>, schedule: Schedule) -> JobId
+    {
+        self.scheduler.register(self.clone(), name, task.clone(), schedule)
+    }
+
+    /// The scheduler of recurring jobs
+    pub fn scheduler(&self) -> &Scheduler
+    {
+        &self.scheduler
     }
 }
 
+/// A token for a new task, and the child slots of the running task if started by one
+fn task_token() -> (CancellationToken, Option<Arc<Semaphore>>) {
+    match IN_TASK.try_with(|context| (context.cancel.child_token(), context.children.clone())) {
+        Ok((cancel, children)) => (cancel, Some(children)),
+        Err(_) => (CancellationToken::new(), None)
+    }
+}
+
+/// Registers every vendor with credentials in the environment
+///
+/// Priority order is Polygon, Tiingo, then Yahoo Finance, which needs no credentials
+/// and is registered unless YFINANCE_DISABLED is set. Each vendor client is
+/// shared by all tasks, and so is its rate limiter.
+fn default_sources(client: &reqwest::Client) -> Result<SourceRegistry, String> {
+    let mut sources = SourceRegistry::new();
+    match polygon::get_api_key() {
+        Some(key) => {
+            let limit = RateLimit::from_env("POLYGON", polygon::DEFAULT_RATE_LIMIT)?;
+            let polygon = polygon::PolygonRESTClient::builder(&key).web_client(client.clone()).rate_limit(limit).build();
+            sources.register(Arc::new(polygon))
+        },
+        None => println!("POLYGON_API_KEY is not set. Polygon.io is disabled")
+    }
+    match tiingo::get_api_key() {
+        Some(key) => {
+            let limit = RateLimit::from_env("TIINGO", tiingo::DEFAULT_RATE_LIMIT)?;
+            let tiingo = tiingo::TiingoRESTClient::builder(&key).web_client(client.clone()).rate_limit(limit).build();
+            sources.register(Arc::new(tiingo))
+        },
+        None => println!("TIINGO_API_KEY is not set. Tiingo is disabled")
+    }
+    if yfinance::is_enabled() {
+        let limit = RateLimit::from_env("YFINANCE", yfinance::DEFAULT_RATE_LIMIT)?;
+        let yfinance = yfinance::YahooFinanceClient::builder().web_client(client.clone()).rate_limit(limit).build();
+        sources.register(Arc::new(yfinance))
+    } else {
+        println!("YFINANCE_DISABLED is set. Yahoo Finance is disabled")
+    }
+    Ok(sources)
+}
+
+/// Handle to the result of a spawned task
+pub type TaskHandle = JoinHandle<Result<(), Box<dyn Error + Send + Sync>>>;
 /// An spawnable function
 type Task = Box<dyn Future<Output=Result<(),Box<dyn Error + Send + Sync>>> + Send +'static>;
 /// An executable task
@@ -66,8 +586,9 @@ pub trait TaskFactory
     /// 
     /// * 'this'
     /// * 'executor' - For use in recursive calls
-    /// * 'db_ref' - Mongo database handle for quantify
+    /// * 'storage' - Persistence of tickers and candles
     /// * 'client' - reqwest client
+    /// * 'cancel' - Cancelled when the task is interrupted, see [Executor::execute_with_priority]
     /// 
     /// # Examples
     /// 
@@ -76,52 +597,57 @@ pub trait TaskFactory
     ///     count: Mutex<i32>
     /// }
     /// impl TaskFactory for ExampleTask {
-    ///     fn init(this: Arc<Self>, _executor: Arc<Executor>, _db_ref: Database) -> Task {
+    ///     fn init(this: Arc<Self>, _executor: Arc<Executor>, _storage: Arc<dyn Storage>, _client: reqwest::Client, _cancel: CancellationToken) -> Task {
     ///         Box::new(async move {
     ///             let mut count = this.count.lock().unwrap();
     ///             *count += 1;
+    ///             Ok(())
     ///         })
     ///     }
     /// }
     /// ```
-    fn init(this: Arc<Self>, executor: Arc<Executor>, db_ref: Database, client: reqwest::Client) -> Task;
+    fn init(this: Arc<Self>, executor: Arc<Executor>, storage: Arc<dyn Storage>, client: reqwest::Client, cancel: CancellationToken) -> Task;
+
+    /// How long the task may run before it is interrupted
+    ///
+    /// Defaults to the executor's task timeout. Tasks mostly waiting for their
+    /// children, which are bounded by their own timeout, may run without one.
+    fn timeout(&self, default: Option<Duration>) -> Option<Duration> {
+        default
+    }
 }
 
 // Tests
 #[cfg(test)]
 mod tests {
-    use mongodb::Database;
-    use std::{env, sync::{Arc, Mutex}};
-    use std::io::{self,Write};
-    use super::{Executor, TaskFactory, Task};
+    use std::{sync::{Arc, Mutex}, time::Duration};
+    use tokio::sync::Notify;
+    use tokio_util::sync::CancellationToken;
+    use super::{Executor, TaskFactory, Task, cancel::Interrupted, jobs::JobState, queue::Priority, storage::Storage};
 
-    // Creates an executor based on environment variables
-    async fn create_executor() -> Option<Arc<Executor>> {
-        let client_uri =
-            env::var("QUANTIFY_DATABASE_URI").expect("You must set the QUANTIFY_DATABASE_URI environment var!");
-        let exec = Arc::new(match Executor::build(&client_uri).await {
-            Ok(exec) => exec,
-            Err(_) => {
-                write!(&mut io::stdout(), "Skipping test: MongoDB cannot be accessed \n").unwrap();
-                return None;
-            }
-        });
-        return Some(exec);
+    // Creates an executor with in-memory storage
+    async fn create_executor() -> Arc<Executor> {
+        Arc::new(Executor::build(super::MEMORY_URI).await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_executor_build() {
+        let exec = create_executor().await;
+        // Yahoo Finance needs no credentials
+        assert!(exec.sources().get("yfinance").is_some());
+        assert!(Executor::build("not a uri").await.is_err());
     }
 
     // Create simple task (Test basic execution)// Test basic task execution
     #[tokio::test]
     async fn test_executor_simple() {
         // Create executor
-        let exec = match create_executor().await {
-            Some(exec) => exec,
-            None => return,
-        };
+        let exec = create_executor().await;
         struct ExampleTask {
             count: Mutex<i32>
         }
         impl TaskFactory for ExampleTask {
-            fn init(this: Arc<Self>, _executor: Arc<Executor>, _db_ref: Database, _client: reqwest::Client) -> Task {
+            fn init(this: Arc<Self>, _executor: Arc<Executor>, _storage: Arc<dyn Storage>, _client: reqwest::Client, _cancel: CancellationToken) -> Task {
                 Box::new(async move {
                     let mut count = this.count.lock().unwrap();
                     *count += 1;
@@ -146,16 +672,13 @@ mod tests {
     // Create fibonacci task (Test recursive)
     #[tokio::test]
     async fn test_executor_recursive() {
-        let exec = match create_executor().await {
-            Some(exec) => exec,
-            None => return,
-        };
+        let exec = create_executor().await;
         struct FibonacciTask {
             n: i32,
             v: Mutex<i32>
         }
         impl TaskFactory for FibonacciTask {
-            fn init(this: Arc<Self>, executor: Arc<Executor>, _db_ref: Database, _client: reqwest::Client) -> Task {
+            fn init(this: Arc<Self>, executor: Arc<Executor>, _storage: Arc<dyn Storage>, _client: reqwest::Client, _cancel: CancellationToken) -> Task {
                 Box::new(async move {
                     if this.n == 0 {
                         *this.v.lock().unwrap() = 0;
@@ -181,4 +704,166 @@ mod tests {
         let _ = exec.execute(&example).await;
         assert_eq!(*example.v.lock().unwrap(), 8);
     }
-}
\ No newline at end of file
+
+    // Test bounded concurrency: children bypass the queue, waiting tasks are bounded
+    #[tokio::test]
+    async fn test_executor_queue() {
+        let exec = Arc::new(Executor::build(super::MEMORY_URI).await.unwrap().with_queue(1, 1));
+        struct NestedTask {
+            depth: i32,
+            gate: Arc<Notify>
+        }
+        impl TaskFactory for NestedTask {
+            fn init(this: Arc<Self>, executor: Arc<Executor>, _storage: Arc<dyn Storage>, _client: reqwest::Client, _cancel: CancellationToken) -> Task {
+                Box::new(async move {
+                    this.gate.notified().await;
+                    if this.depth > 0 {
+                        // Would deadlock if queued behind the parent's worker
+                        this.gate.notify_one();
+                        executor.execute(&Arc::new(NestedTask {depth: this.depth - 1, gate: this.gate.clone()})).await??;
+                    }
+                    Ok(())
+                })
+            }
+        }
+
+        let gate = Arc::new(Notify::new());
+        let running = exec.execute(&Arc::new(NestedTask {depth: 3, gate: gate.clone()}));
+        let waiting = exec.execute(&Arc::new(NestedTask {depth: 0, gate: Arc::new(Notify::new())}));
+        while exec.queue.load() != (1, 1) {
+            tokio::task::yield_now().await;
+        }
+        let refused = exec.execute(&Arc::new(NestedTask {depth: 0, gate: gate.clone()})).await.unwrap();
+        assert_eq!(refused.err().unwrap().to_string(), "Executor queue is full (1 tasks waiting)");
+
+        gate.notify_one();
+        running.await.unwrap().unwrap();
+        waiting.abort();
+    }
+
+    // Test that the children of a task run at most as many at once as there are workers
+    #[tokio::test]
+    async fn test_executor_fan_out() {
+        let exec = Arc::new(Executor::build(super::MEMORY_URI).await.unwrap().with_queue(2, 1));
+        #[derive(Default)]
+        struct Load {
+            running: Mutex<(usize, usize)>,
+            finished: Mutex<usize>
+        }
+        struct ChildTask {
+            load: Arc<Load>
+        }
+        impl TaskFactory for ChildTask {
+            fn init(this: Arc<Self>, _executor: Arc<Executor>, _storage: Arc<dyn Storage>, _client: reqwest::Client, _cancel: CancellationToken) -> Task {
+                Box::new(async move {
+                    {
+                        let mut running = this.load.running.lock().unwrap();
+                        running.0 += 1;
+                        running.1 = running.1.max(running.0);
+                    }
+                    // Let the other children start if they may
+                    for _ in 0..10 {
+                        tokio::task::yield_now().await;
+                    }
+                    this.load.running.lock().unwrap().0 -= 1;
+                    *this.load.finished.lock().unwrap() += 1;
+                    Ok(())
+                })
+            }
+        }
+        struct ParentTask {
+            load: Arc<Load>
+        }
+        impl TaskFactory for ParentTask {
+            fn init(this: Arc<Self>, executor: Arc<Executor>, _storage: Arc<dyn Storage>, _client: reqwest::Client, _cancel: CancellationToken) -> Task {
+                Box::new(async move {
+                    let children = (0..10).map(|_| executor.execute(&Arc::new(ChildTask {load: this.load.clone()})));
+                    for child in futures::future::join_all(children).await {
+                        child??;
+                    }
+                    Ok(())
+                })
+            }
+        }
+
+        let load = Arc::new(Load::default());
+        exec.execute(&Arc::new(ParentTask {load: load.clone()})).await.unwrap().unwrap();
+        assert_eq!(*load.finished.lock().unwrap(), 10);
+        assert_eq!(load.running.lock().unwrap().1, 2);
+    }
+
+    // Test interruptions: cancellation reaches child tasks, timeouts are a distinct job state
+    #[tokio::test]
+    async fn test_executor_cancel() {
+        let exec = Arc::new(Executor::build(super::MEMORY_URI).await.unwrap().with_task_timeout(Some(Duration::from_millis(50))));
+        struct ParentTask {
+            child: Arc<ChildTask>
+        }
+        struct ChildTask {
+            cancelled: Mutex<bool>
+        }
+        impl TaskFactory for ParentTask {
+            fn init(this: Arc<Self>, executor: Arc<Executor>, _storage: Arc<dyn Storage>, _client: reqwest::Client, _cancel: CancellationToken) -> Task {
+                Box::new(async move {
+                    executor.execute(&this.child).await?
+                })
+            }
+        }
+        impl TaskFactory for ChildTask {
+            fn init(this: Arc<Self>, _executor: Arc<Executor>, _storage: Arc<dyn Storage>, _client: reqwest::Client, cancel: CancellationToken) -> Task {
+                Box::new(async move {
+                    cancel.cancelled().await;
+                    *this.cancelled.lock().unwrap() = true;
+                    Err(Interrupted::Cancelled)?
+                })
+            }
+        }
+
+        let child = Arc::new(ChildTask { cancelled: Mutex::new(false) });
+        let (id, handle) = exec.submit("cancelled", Priority::Normal, &Arc::new(ParentTask { child: child.clone() }));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(exec.jobs().cancel(&id));
+        assert_eq!(handle.await.unwrap().unwrap_err().to_string(), "Task cancelled");
+        assert_eq!(exec.job(&id).await.unwrap().unwrap().state, JobState::Cancelled);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(*child.cancelled.lock().unwrap());
+
+        let (id, handle) = exec.submit("timed out", Priority::Normal, &Arc::new(ChildTask { cancelled: Mutex::new(false) }));
+        assert_eq!(handle.await.unwrap().unwrap_err().to_string(), "Task timed out after 50ms");
+        let job = exec.job(&id).await.unwrap().unwrap();
+        assert_eq!((job.state, job.error.as_deref()), (JobState::TimedOut, Some("Task timed out after 50ms")));
+    }
+
+    // Test that tasks waiting for slow children may run longer than the task timeout
+    #[tokio::test]
+    async fn test_executor_timeout() {
+        let exec = Arc::new(Executor::build(super::MEMORY_URI).await.unwrap().with_task_timeout(Some(Duration::from_millis(50))));
+        struct SlowTask;
+        impl TaskFactory for SlowTask {
+            fn init(_this: Arc<Self>, _executor: Arc<Executor>, _storage: Arc<dyn Storage>, _client: reqwest::Client, _cancel: CancellationToken) -> Task {
+                Box::new(async move {
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    Ok(())
+                })
+            }
+        }
+        struct FanOutTask;
+        impl TaskFactory for FanOutTask {
+            fn init(_this: Arc<Self>, executor: Arc<Executor>, _storage: Arc<dyn Storage>, _client: reqwest::Client, _cancel: CancellationToken) -> Task {
+                Box::new(async move {
+                    for _ in 0..5 {
+                        executor.execute(&Arc::new(SlowTask)).await??;
+                    }
+                    Ok(())
+                })
+            }
+            fn timeout(&self, _default: Option<Duration>) -> Option<Duration> {
+                None
+            }
+        }
+
+        let (id, handle) = exec.submit("fan out", Priority::Normal, &Arc::new(FanOutTask));
+        handle.await.unwrap().unwrap();
+        assert_eq!(exec.job(&id).await.unwrap().unwrap().state, JobState::Succeeded);
+    }
+}
@@ -0,0 +1,202 @@
+use core::fmt;
+use std::{cmp::Ordering, collections::BinaryHeap, error::Error, sync::{Arc, Mutex}};
+
+use serde::{Serialize, Deserialize};
+use tokio::sync::oneshot;
+
+/// Order in which queued tasks are started
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Priority {
+    /// Long running work nobody waits for (eg. backfills)
+    Background,
+    /// Recurring work (eg. scheduled updates)
+    #[default]
+    Normal,
+    /// Requests a client waits for
+    Interactive
+}
+
+/// The queue holds as many waiting tasks as allowed
+#[derive(Debug)]
+pub struct QueueFull {
+    capacity: usize
+}
+
+impl fmt::Display for QueueFull {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Executor queue is full ({} tasks waiting)", self.capacity)
+    }
+}
+
+impl Error for QueueFull {}
+
+struct Waiter {
+    priority: Priority,
+    /// Arrival order, so that tasks of the same priority start in order
+    seq: u64,
+    start: oneshot::Sender<Worker>
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for Waiter {}
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Waiter {
+    /// Highest priority, then earliest arrival, first
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority.cmp(&other.priority).then(other.seq.cmp(&self.seq))
+    }
+}
+
+struct QueueState {
+    running: usize,
+    waiting: BinaryHeap<Waiter>,
+    seq: u64
+}
+
+/// Bounds the number of tasks running at once
+///
+/// Tasks wait for a worker by priority. Once `capacity` tasks are waiting, new ones are refused.
+pub struct WorkQueue {
+    workers: usize,
+    capacity: usize,
+    state: Arc<Mutex<QueueState>>
+}
+
+impl WorkQueue {
+    /// Constructs a new WorkQueue
+    ///
+    /// # Arguments
+    ///
+    /// * 'workers' - Maximum number of tasks running at once
+    /// * 'capacity' - Maximum number of tasks waiting for a worker
+    pub fn new(workers: usize, capacity: usize) -> WorkQueue {
+        WorkQueue {
+            workers: workers.max(1),
+            capacity,
+            state: Arc::new(Mutex::new(QueueState { running: 0, waiting: BinaryHeap::new(), seq: 0 }))
+        }
+    }
+
+    /// Waits for a worker, held until the returned handle is dropped
+    pub async fn acquire(&self, priority: Priority) -> Result<Worker, QueueFull> {
+        let start = {
+            let mut state = self.state.lock().unwrap();
+            if state.running < self.workers && state.waiting.is_empty() {
+                state.running += 1;
+                return Ok(Worker { state: Some(self.state.clone()) });
+            }
+            // Waiters which gave up do not count towards the capacity
+            state.waiting.retain(|waiter| !waiter.start.is_closed());
+            if state.waiting.len() >= self.capacity {
+                return Err(QueueFull { capacity: self.capacity });
+            }
+            let (sender, receiver) = oneshot::channel();
+            state.seq += 1;
+            let seq = state.seq;
+            state.waiting.push(Waiter { priority, seq, start: sender });
+            receiver
+        };
+        // Workers are only dropped with the queue
+        Ok(start.await.expect("Work queue dropped"))
+    }
+
+    /// The number of running and waiting tasks
+    #[cfg(test)]
+    pub fn load(&self) -> (usize, usize) {
+        let state = self.state.lock().unwrap();
+        (state.running, state.waiting.len())
+    }
+}
+
+/// A worker of a [WorkQueue], handed to the next waiting task when dropped
+pub struct Worker {
+    /// Unset once handed over
+    state: Option<Arc<Mutex<QueueState>>>
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        let Some(shared) = self.state.take() else { return };
+        let mut state = shared.lock().unwrap();
+        let mut worker = Worker { state: Some(shared.clone()) };
+        // Waiters which gave up are skipped
+        while let Some(waiter) = state.waiting.pop() {
+            match waiter.start.send(worker) {
+                Ok(()) => return,
+                Err(returned) => worker = returned
+            }
+        }
+        state.running -= 1;
+        worker.state = None;
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::{Priority, WorkQueue};
+
+    #[tokio::test]
+    async fn test_priority() {
+        let queue = Arc::new(WorkQueue::new(1, 10));
+        let first = queue.acquire(Priority::Background).await.unwrap();
+
+        let started = Arc::new(Mutex::new(Vec::new()));
+        let mut handles = Vec::new();
+        for (name, priority) in [("background", Priority::Background), ("normal", Priority::Normal), ("interactive", Priority::Interactive), ("normal 2", Priority::Normal)] {
+            let (waiting, started) = (queue.clone(), started.clone());
+            handles.push(tokio::spawn(async move {
+                let _worker = waiting.acquire(priority).await.unwrap();
+                started.lock().unwrap().push(name);
+            }));
+            // Queued in order
+            while queue.load().1 < handles.len() {
+                tokio::task::yield_now().await;
+            }
+        }
+        assert_eq!(queue.load(), (1, 4));
+
+        drop(first);
+        for handle in handles {
+            handle.await.unwrap();
+        }
+        assert_eq!(*started.lock().unwrap(), vec!["interactive", "normal", "normal 2", "background"]);
+        assert_eq!(queue.load(), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn test_backpressure() {
+        let queue = Arc::new(WorkQueue::new(1, 1));
+        let first = queue.acquire(Priority::Normal).await.unwrap();
+
+        let waiting = {
+            let queue = queue.clone();
+            tokio::spawn(async move { queue.acquire(Priority::Normal).await.map(|_| ()) })
+        };
+        while queue.load().1 < 1 {
+            tokio::task::yield_now().await;
+        }
+        let error = queue.acquire(Priority::Interactive).await.err().unwrap();
+        assert_eq!(error.to_string(), "Executor queue is full (1 tasks waiting)");
+
+        // Waiters which gave up neither fill the queue nor get a worker
+        waiting.abort();
+        let _ = waiting.await;
+        let mut next = std::pin::pin!(queue.acquire(Priority::Normal));
+        assert!(futures::poll!(next.as_mut()).is_pending());
+        assert_eq!(queue.load(), (1, 1));
+        drop(first);
+        let _worker = next.await.unwrap();
+        assert_eq!(queue.load(), (1, 0));
+    }
+}
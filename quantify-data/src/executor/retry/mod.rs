@@ -0,0 +1,264 @@
+use std::{error::Error, sync::{Arc, Mutex}, time::Duration};
+
+use quantify_core::SourceError;
+use rand::Rng;
+use reqwest::Client;
+use tokio_util::sync::CancellationToken;
+
+use super::{Executor, Task, TaskFactory, cancel::Interrupted, storage::Storage};
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 4;
+const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(60);
+const DEFAULT_MULTIPLIER: f64 = 2.0;
+
+/// Decides whether a task failing with an error may succeed if run again
+pub type RetryPredicate = fn(&(dyn Error + Send + Sync + 'static)) -> bool;
+
+/// How failed tasks are run again
+///
+/// Retries wait a random delay up to the backoff ("full jitter"), which starts at
+/// the initial backoff and grows by the multiplier after every attempt, up to the maximum.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    multiplier: f64,
+    retryable: RetryPredicate
+}
+
+impl Default for RetryPolicy {
+    /// Retries [is_transient] errors 3 times, backing off from 1s up to 60s
+    fn default() -> Self {
+        RetryPolicy::new(DEFAULT_MAX_ATTEMPTS)
+    }
+}
+
+impl RetryPolicy {
+    /// Constructs a RetryPolicy with the default backoff, retrying [is_transient] errors
+    ///
+    /// # Arguments
+    ///
+    /// * 'max_attempts' - Maximum number of runs of the task, including the first one
+    pub fn new(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: max_attempts.max(1),
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+            multiplier: DEFAULT_MULTIPLIER,
+            retryable: is_transient
+        }
+    }
+
+    /// Sets the backoff
+    ///
+    /// # Arguments
+    ///
+    /// * 'initial' - Backoff after the first attempt
+    /// * 'max' - Upper bound of the backoff
+    /// * 'multiplier' - Growth of the backoff after every attempt
+    pub fn with_backoff(self, initial: Duration, max: Duration, multiplier: f64) -> RetryPolicy {
+        RetryPolicy { initial_backoff: initial, max_backoff: max.max(initial), multiplier: multiplier.max(1.0), ..self }
+    }
+
+    /// Sets the errors which are retried
+    pub fn with_retryable(self, retryable: RetryPredicate) -> RetryPolicy {
+        RetryPolicy { retryable, ..self }
+    }
+
+    /// Maximum number of runs of a task
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Upper bound of the delay after a failed attempt, counted from 1
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = i32::try_from(attempt.saturating_sub(1)).unwrap_or(i32::MAX);
+        let backoff = self.initial_backoff.as_secs_f64() * self.multiplier.powi(exponent);
+        Duration::from_secs_f64(backoff.min(self.max_backoff.as_secs_f64()))
+    }
+
+    /// Whether a task which failed at an attempt, counted from 1, is run again
+    fn should_retry(&self, attempt: u32, error: &(dyn Error + Send + Sync + 'static)) -> bool {
+        attempt < self.max_attempts && (self.retryable)(error)
+    }
+
+    /// Random delay after a failed attempt
+    fn delay(&self, attempt: u32) -> Duration {
+        self.backoff(attempt).mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+    }
+}
+
+/// Whether an error is temporary: a transient source failure, or a network or
+/// retryable Mongo failure
+pub fn is_transient(error: &(dyn Error + Send + Sync + 'static)) -> bool {
+    if let Some(error) = error.downcast_ref::<SourceError>() {
+        return error.kind().is_transient();
+    }
+    if let Some(error) = error.downcast_ref::<mongodb::error::Error>() {
+        return error.contains_label(mongodb::error::RETRYABLE_WRITE_ERROR)
+            || error.contains_label(mongodb::error::TRANSIENT_TRANSACTION_ERROR)
+            || matches!(*error.kind,
+                mongodb::error::ErrorKind::Io(_)
+                | mongodb::error::ErrorKind::ServerSelection { .. }
+                | mongodb::error::ErrorKind::ConnectionPoolCleared { .. });
+    }
+    false
+}
+
+/// Runs a task again when it fails, according to a [RetryPolicy]
+pub struct Retry<T> {
+    task: Arc<T>,
+    policy: RetryPolicy,
+    attempts: Mutex<u32>
+}
+
+impl<T> Retry<T> {
+    /// Constructs a new instance of Retry
+    ///
+    /// # Arguments
+    ///
+    /// * 'task' - The retried task, which keeps the results of the last attempt
+    /// * 'policy' - When and how often the task is retried
+    pub fn new(task: &Arc<T>, policy: RetryPolicy) -> Retry<T> {
+        Retry { task: task.clone(), policy, attempts: Mutex::new(0) }
+    }
+
+    /// The retried task
+    pub fn task(&self) -> &Arc<T> {
+        &self.task
+    }
+
+    /// The number of attempts of the last run
+    pub fn attempts(&self) -> u32 {
+        *self.attempts.lock().unwrap()
+    }
+}
+
+impl<T: TaskFactory + Send + Sync + 'static> TaskFactory for Retry<T> {
+    /// [Retry]
+    fn init(this: Arc<Self>, executor: Arc<Executor>, storage: Arc<dyn Storage>, client: Client, cancel: CancellationToken) -> Task {
+        Box::new(async move {
+            let mut attempt = 1;
+            loop {
+                *this.attempts.lock().unwrap() = attempt;
+                let task = TaskFactory::init(this.task.clone(), executor.clone(), storage.clone(), client.clone(), cancel.clone());
+                match Box::into_pin(task).await {
+                    Err(e) if this.policy.should_retry(attempt, e.as_ref()) => {
+                        let delay = this.policy.delay(attempt);
+                        println!("Attempt {attempt} of {} failed, retrying in {delay:?}: {e}", this.policy.max_attempts);
+                        tokio::select! {
+                            _ = tokio::time::sleep(delay) => attempt += 1,
+                            _ = cancel.cancelled() => return Err(Interrupted::Cancelled)?
+                        }
+                    },
+                    result => return result
+                }
+            }
+        })
+    }
+
+    fn timeout(&self, default: Option<Duration>) -> Option<Duration> {
+        self.task.timeout(default)
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use std::{error::Error, sync::{Arc, Mutex}, time::Duration};
+
+    use quantify_core::{ErrorKind, RateLimit, RateLimiter, SourceError};
+    use tokio_util::sync::CancellationToken;
+
+    use super::{is_transient, Retry, RetryPolicy};
+    use crate::executor::{Executor, Task, TaskFactory, storage::Storage};
+    use crate::executor::tasks::{Granularity, UpdateCandleDataTask};
+    use crate::executor::testing::{executor, StaticSource};
+
+    /// Fails with the given errors, then succeeds
+    struct FlakyTask {
+        errors: Mutex<Vec<ErrorKind>>
+    }
+    impl TaskFactory for FlakyTask {
+        fn init(this: Arc<Self>, _executor: Arc<Executor>, _storage: Arc<dyn Storage>, _client: reqwest::Client, _cancel: CancellationToken) -> Task {
+            Box::new(async move {
+                match this.errors.lock().unwrap().pop() {
+                    Some(kind) => Err(SourceError::new(kind, "flaky"))?,
+                    None => Ok(())
+                }
+            })
+        }
+    }
+
+    fn policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy::new(max_attempts).with_backoff(Duration::from_millis(1), Duration::from_millis(1), 2.0)
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy::default().with_backoff(Duration::from_secs(1), Duration::from_secs(5), 2.0);
+        let backoffs: Vec<u64> = (1..=5).map(|attempt| policy.backoff(attempt).as_secs()).collect();
+        assert_eq!(backoffs, vec![1, 2, 4, 5, 5]);
+        assert!((1..=5).all(|attempt| policy.delay(attempt) <= policy.backoff(attempt)));
+        assert_eq!(RetryPolicy::new(0).max_attempts(), 1);
+    }
+
+    #[test]
+    fn test_is_transient() {
+        let error: Box<dyn Error + Send + Sync> = SourceError::new(ErrorKind::RateLimited, "slow down").into();
+        assert!(is_transient(error.as_ref()));
+        let error: Box<dyn Error + Send + Sync> = SourceError::new(ErrorKind::NotFound, "no ticker").into();
+        assert!(!is_transient(error.as_ref()));
+        let error: Box<dyn Error + Send + Sync> = SourceError::new(ErrorKind::QuotaExceeded, "no requests left today").into();
+        assert!(!is_transient(error.as_ref()));
+        let error: Box<dyn Error + Send + Sync> = "failed".into();
+        assert!(!is_transient(error.as_ref()));
+    }
+
+    #[tokio::test]
+    async fn test_retry() {
+        let (exec, _) = executor(Vec::new());
+
+        // Transient errors are retried
+        let task = Arc::new(FlakyTask { errors: Mutex::new(vec![ErrorKind::Server, ErrorKind::Transport]) });
+        let retry = Arc::new(Retry::new(&task, policy(3)));
+        exec.execute(&retry).await.unwrap().unwrap();
+        assert_eq!(retry.attempts(), 3);
+
+        // Up to the maximum number of attempts
+        let task = Arc::new(FlakyTask { errors: Mutex::new(vec![ErrorKind::Server; 3]) });
+        let retry = Arc::new(Retry::new(&task, policy(3)));
+        assert!(exec.execute(&retry).await.unwrap().is_err());
+        assert_eq!(retry.attempts(), 3);
+
+        // Other errors are returned immediately
+        let task = Arc::new(FlakyTask { errors: Mutex::new(vec![ErrorKind::Server, ErrorKind::Unauthorized]) });
+        let retry = Arc::new(Retry::new(&task, policy(3)));
+        assert!(exec.execute(&retry).await.unwrap().is_err());
+        assert_eq!(retry.attempts(), 1);
+
+        let task = Arc::new(FlakyTask { errors: Mutex::new(vec![ErrorKind::Unauthorized]) });
+        let retry = Arc::new(Retry::new(&task, policy(3).with_retryable(|_| true)));
+        exec.execute(&retry).await.unwrap().unwrap();
+        assert_eq!(retry.attempts(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_retry_quota() {
+        let mut source = StaticSource::new("polygon");
+        source.limiter = Some(RateLimiter::new(RateLimit::new(10, Duration::from_secs(1)).with_daily_quota(1)));
+        let (exec, _) = executor(vec![source]);
+
+        let task = Arc::new(UpdateCandleDataTask::new("nflx", Granularity::Days(1)));
+        exec.execute(&task).await.unwrap().unwrap();
+
+        // The quota is not reset by waiting, so the update is not retried
+        let retry = exec.retrying(&task);
+        let error = exec.execute(&retry).await.unwrap().unwrap_err();
+        assert_eq!(error.downcast_ref::<SourceError>().map(SourceError::kind), Some(ErrorKind::QuotaExceeded));
+        assert_eq!(retry.attempts(), 1);
+        assert_eq!(exec.sources().get("polygon").unwrap().quota().unwrap().requests, 1);
+    }
+}
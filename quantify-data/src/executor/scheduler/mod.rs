@@ -0,0 +1,307 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{Arc, Mutex, atomic::{AtomicBool, AtomicU64, Ordering}},
+    time::Duration,
+};
+
+use chrono::{DateTime, Datelike, NaiveTime, TimeZone, Utc, Weekday};
+use tokio::{spawn, time::sleep};
+
+use super::{Executor, TaskFactory, queue::Priority};
+
+/// Identifies a recurring job
+pub type JobId = u64;
+
+/// When a recurring job runs
+#[derive(Clone, Debug, PartialEq)]
+pub enum Schedule {
+    /// Every fixed period, starting one period after registration
+    Interval(Duration),
+    /// On the given weekdays at a UTC time of day
+    Calendar {
+        weekdays: Vec<Weekday>,
+        time: NaiveTime
+    }
+}
+
+impl Schedule {
+    /// Every day at a UTC time of day
+    pub fn daily(time: NaiveTime) -> Schedule {
+        Schedule::Calendar {
+            weekdays: vec![Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri, Weekday::Sat, Weekday::Sun],
+            time
+        }
+    }
+
+    /// Monday to Friday at a UTC time of day
+    pub fn weekdays(time: NaiveTime) -> Schedule {
+        Schedule::Calendar {
+            weekdays: vec![Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri],
+            time
+        }
+    }
+
+    /// Returns the first run strictly after 'now', or None if the schedule never runs
+    pub fn next_after(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Schedule::Interval(period) => {
+                if period.is_zero() {
+                    return None;
+                }
+                Some(now + chrono::Duration::from_std(*period).ok()?)
+            },
+            Schedule::Calendar { weekdays, time } => {
+                (0..=7)
+                    .filter_map(|offset| now.date_naive().checked_add_days(chrono::Days::new(offset)))
+                    .filter(|date| weekdays.contains(&date.weekday()))
+                    .map(|date| Utc.from_utc_datetime(&date.and_time(*time)))
+                    .find(|candidate| *candidate > now)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Schedule::Interval(period) => write!(f, "every {}s", period.as_secs()),
+            Schedule::Calendar { weekdays, time } => {
+                let days: Vec<String> = weekdays.iter().map(|d| d.to_string()).collect();
+                write!(f, "at {} UTC on {}", time, days.join(","))
+            }
+        }
+    }
+}
+
+/// A snapshot of a recurring job
+#[derive(Clone, Debug)]
+pub struct JobInfo {
+    pub id: JobId,
+    pub name: String,
+    pub schedule: Schedule,
+    pub paused: bool,
+    pub running: bool,
+    pub last_run: Option<DateTime<Utc>>,
+    pub next_run: Option<DateTime<Utc>>
+}
+
+struct Job {
+    name: String,
+    schedule: Schedule,
+    paused: AtomicBool,
+    running: AtomicBool,
+    last_run: Mutex<Option<DateTime<Utc>>>,
+    next_run: Mutex<Option<DateTime<Utc>>>
+}
+
+/// Runs recurring tasks on the executor
+///
+/// A job is awaited before its next run is planned, so runs of the same job never overlap.
+/// Ticks missed while a run is in progress are skipped.
+pub struct Scheduler {
+    jobs: Mutex<BTreeMap<JobId, Arc<Job>>>,
+    next_id: AtomicU64
+}
+
+impl Scheduler {
+    pub(super) fn new() -> Scheduler {
+        Scheduler { jobs: Mutex::new(BTreeMap::new()), next_id: AtomicU64::new(1) }
+    }
+
+    /// Registers a recurring task
+    ///
+    /// # Arguments
+    ///
+    /// * 'executor' - Runs the task
+    /// * 'name' - A human readable name for the job
+    /// * 'task' - The task to execute, in the form of a task factory
+    /// * 'schedule' - When the task runs
+    pub(super) fn register<T: TaskFactory + Send + Sync + 'static>(
+        &self,
+        executor: Arc<Executor>,
+        name: &str,
+        task: Arc<T>,
+        schedule: Schedule
+    ) -> JobId {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let job = Arc::new(Job {
+            name: String::from(name),
+            schedule: schedule.clone(),
+            paused: AtomicBool::new(false),
+            running: AtomicBool::new(false),
+            last_run: Mutex::new(None),
+            next_run: Mutex::new(None)
+        });
+
+        let state = job.clone();
+        spawn(async move {
+            while let Some(next) = state.schedule.next_after(Utc::now()) {
+                *state.next_run.lock().unwrap() = Some(next);
+                if let Ok(delay) = (next - Utc::now()).to_std() {
+                    sleep(delay).await;
+                }
+                if state.paused.load(Ordering::Acquire) {
+                    continue;
+                }
+
+                state.running.store(true, Ordering::Release);
+                *state.last_run.lock().unwrap() = Some(Utc::now());
+                match executor.submit(&state.name, Priority::Normal, &task).1.await {
+                    Ok(Ok(())) => {},
+                    Ok(Err(e)) => println!("Scheduled job {} failed: {}", state.name, e),
+                    Err(e) => println!("Scheduled job {} failed: {}", state.name, e),
+                }
+                state.running.store(false, Ordering::Release);
+            }
+            *state.next_run.lock().unwrap() = None;
+        });
+
+        self.jobs.lock().unwrap().insert(id, job);
+        id
+    }
+
+    /// Lists all registered jobs, ordered by id
+    pub fn list(&self) -> Vec<JobInfo> {
+        self.jobs.lock().unwrap().iter()
+            .map(|(id, job)| JobInfo {
+                id: *id,
+                name: job.name.clone(),
+                schedule: job.schedule.clone(),
+                paused: job.paused.load(Ordering::Acquire),
+                running: job.running.load(Ordering::Acquire),
+                last_run: *job.last_run.lock().unwrap(),
+                next_run: *job.next_run.lock().unwrap()
+            })
+            .collect()
+    }
+
+    /// Skips future runs of a job until resumed. A run in progress is not interrupted.
+    ///
+    /// Returns false if the job does not exist
+    pub fn pause(&self, id: JobId) -> bool {
+        self.set_paused(id, true)
+    }
+
+    /// Resumes a paused job
+    ///
+    /// Returns false if the job does not exist
+    pub fn resume(&self, id: JobId) -> bool {
+        self.set_paused(id, false)
+    }
+
+    fn set_paused(&self, id: JobId, paused: bool) -> bool {
+        match self.jobs.lock().unwrap().get(&id) {
+            Some(job) => {
+                job.paused.store(paused, Ordering::Release);
+                true
+            },
+            None => false
+        }
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use std::{sync::{Arc, atomic::{AtomicU32, Ordering}}, time::Duration};
+    use chrono::{NaiveTime, TimeZone, Utc, Weekday};
+    use tokio::sync::Notify;
+    use tokio_util::sync::CancellationToken;
+    use super::Schedule;
+    use crate::executor::{Executor, Task, TaskFactory, storage::Storage, testing::executor};
+
+    /// Counts its runs, each waiting for the gate if set
+    struct CountingTask {
+        runs: AtomicU32,
+        gate: Option<Notify>
+    }
+    impl TaskFactory for CountingTask {
+        fn init(this: Arc<Self>, _executor: Arc<Executor>, _storage: Arc<dyn Storage>, _client: reqwest::Client, _cancel: CancellationToken) -> Task {
+            Box::new(async move {
+                this.runs.fetch_add(1, Ordering::SeqCst);
+                if let Some(gate) = &this.gate {
+                    gate.notified().await;
+                }
+                Ok(())
+            })
+        }
+    }
+
+    /// Waits until a condition holds, or fails after a second
+    async fn wait_until(condition: impl Fn() -> bool) {
+        for _ in 0..1000 {
+            if condition() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        panic!("Condition not met");
+    }
+
+    #[test]
+    fn test_schedule_interval() {
+        let now = Utc.with_ymd_and_hms(2023, 8, 1, 12, 0, 0).unwrap();
+        let schedule = Schedule::Interval(Duration::from_secs(90));
+        assert_eq!(schedule.next_after(now), Some(Utc.with_ymd_and_hms(2023, 8, 1, 12, 1, 30).unwrap()));
+        assert_eq!(Schedule::Interval(Duration::ZERO).next_after(now), None);
+    }
+
+    #[test]
+    fn test_schedule_calendar() {
+        let time = NaiveTime::from_hms_opt(22, 0, 0).unwrap();
+        // Tuesday
+        let before = Utc.with_ymd_and_hms(2023, 8, 1, 12, 0, 0).unwrap();
+        let after = Utc.with_ymd_and_hms(2023, 8, 1, 22, 0, 0).unwrap();
+        assert_eq!(Schedule::daily(time).next_after(before), Some(after));
+        assert_eq!(Schedule::daily(time).next_after(after), Some(Utc.with_ymd_and_hms(2023, 8, 2, 22, 0, 0).unwrap()));
+
+        // Friday evening rolls over the weekend
+        let friday = Utc.with_ymd_and_hms(2023, 8, 4, 23, 0, 0).unwrap();
+        assert_eq!(Schedule::weekdays(time).next_after(friday), Some(Utc.with_ymd_and_hms(2023, 8, 7, 22, 0, 0).unwrap()));
+
+        // Weekly
+        let weekly = Schedule::Calendar { weekdays: vec![Weekday::Tue], time };
+        assert_eq!(weekly.next_after(after), Some(Utc.with_ymd_and_hms(2023, 8, 8, 22, 0, 0).unwrap()));
+
+        let never = Schedule::Calendar { weekdays: vec![], time };
+        assert_eq!(never.next_after(before), None);
+    }
+
+    #[tokio::test]
+    async fn test_scheduler_overlap() {
+        let (exec, _) = executor(Vec::new());
+        let task = Arc::new(CountingTask { runs: AtomicU32::new(0), gate: Some(Notify::new()) });
+        let id = exec.schedule("slow", &task, Schedule::Interval(Duration::from_millis(10)));
+        wait_until(|| task.runs.load(Ordering::SeqCst) == 1).await;
+
+        // Ticks are skipped while the first run waits
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(task.runs.load(Ordering::SeqCst), 1);
+        let job = exec.scheduler().list().into_iter().find(|job| job.id == id).unwrap();
+        assert_eq!((job.name.as_str(), job.running), ("slow", true));
+        assert!(job.last_run.is_some());
+
+        task.gate.as_ref().unwrap().notify_one();
+        wait_until(|| task.runs.load(Ordering::SeqCst) == 2).await;
+    }
+
+    #[tokio::test]
+    async fn test_scheduler_pause() {
+        let (exec, _) = executor(Vec::new());
+        let task = Arc::new(CountingTask { runs: AtomicU32::new(0), gate: None });
+        let id = exec.schedule("paused", &task, Schedule::Interval(Duration::from_millis(10)));
+        assert!(exec.scheduler().pause(id));
+        assert!(!exec.scheduler().pause(id + 1));
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(task.runs.load(Ordering::SeqCst), 0);
+        let job = &exec.scheduler().list()[0];
+        assert!(job.paused && !job.running);
+        assert!(job.next_run.is_some());
+
+        assert!(exec.scheduler().resume(id));
+        assert!(!exec.scheduler().list()[0].paused);
+        wait_until(|| task.runs.load(Ordering::SeqCst) >= 2).await;
+    }
+}
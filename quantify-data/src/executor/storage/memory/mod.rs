@@ -0,0 +1,255 @@
+use std::{collections::{BTreeMap, HashMap}, sync::Mutex};
+
+use chrono::{DateTime, Utc};
+use futures::{StreamExt, stream::{self, BoxStream}};
+use quantify_core::async_trait;
+
+use crate::executor::jobs::JobRecord;
+use crate::executor::tasks::{CandleData, CandleDiscrepancy, Granularity};
+
+use super::{BackfillJob, BackfillStatus, CandleQuery, Storage, StorageResult, TickerInfo};
+
+/// Candles of a granularity, by ticker then timestamp
+type Candles = BTreeMap<(String, DateTime<Utc>), CandleData>;
+
+#[derive(Default)]
+struct MemoryState {
+    tickers: BTreeMap<String, TickerInfo>,
+    candles: HashMap<&'static str, Candles>,
+    discrepancies: Vec<CandleDiscrepancy>,
+    archive: HashMap<&'static str, Vec<(DateTime<Utc>, CandleData)>>,
+    backfill_jobs: BTreeMap<String, BackfillJob>,
+    jobs: HashMap<String, JobRecord>
+}
+
+/// Storage in process memory, lost on exit
+///
+/// For tests and running the server without a database
+#[derive(Default)]
+pub struct MemoryStorage {
+    state: Mutex<MemoryState>
+}
+
+impl MemoryStorage {
+    /// Constructs an empty MemoryStorage
+    pub fn new() -> MemoryStorage {
+        MemoryStorage::default()
+    }
+}
+
+/// Removes the candles of a ticker from every granularity
+fn take_ticker_candles(state: &mut MemoryState, ticker: &str) -> Vec<(&'static str, CandleData)> {
+    let mut taken = Vec::new();
+    for (collection, candles) in state.candles.iter_mut() {
+        let keys: Vec<(String, DateTime<Utc>)> = candles.keys()
+            .filter(|(t, _)| t == ticker)
+            .cloned()
+            .collect();
+        taken.extend(keys.iter().filter_map(|key| candles.remove(key)).map(|candle| (*collection, candle)));
+    }
+    taken
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn upsert_ticker(&self, ticker: &TickerInfo) -> StorageResult<()> {
+        self.state.lock().unwrap().tickers.insert(ticker.ticker.clone(), ticker.clone());
+        Ok(())
+    }
+
+    async fn get_ticker(&self, ticker: &str) -> StorageResult<Option<TickerInfo>> {
+        Ok(self.state.lock().unwrap().tickers.get(ticker).cloned())
+    }
+
+    async fn list_tickers(&self) -> StorageResult<Vec<TickerInfo>> {
+        Ok(self.state.lock().unwrap().tickers.values().cloned().collect())
+    }
+
+    async fn remove_ticker(&self, ticker: &str) -> StorageResult<u64> {
+        Ok(self.state.lock().unwrap().tickers.remove(ticker).map_or(0, |_| 1))
+    }
+
+    async fn latest_candle_timestamp(&self, ticker: &str, granularity: Granularity) -> StorageResult<Option<DateTime<Utc>>> {
+        let state = self.state.lock().unwrap();
+        Ok(state.candles.get(granularity.collection())
+            .and_then(|candles| candles.keys().filter(|(t, _)| t == ticker).map(|(_, timestamp)| *timestamp).max()))
+    }
+
+    async fn upsert_candles(&self, granularity: Granularity, candles: Vec<CandleData>) -> StorageResult<u64> {
+        let mut state = self.state.lock().unwrap();
+        let stored = state.candles.entry(granularity.collection()).or_default();
+        let count = candles.len() as u64;
+        for candle in candles {
+            stored.insert((candle.ticker.clone(), candle.timestamp), candle);
+        }
+        Ok(count)
+    }
+
+    async fn find_candles(&self, query: &CandleQuery) -> StorageResult<Vec<CandleData>> {
+        let state = self.state.lock().unwrap();
+        let candles = match state.candles.get(query.granularity.collection()) {
+            Some(candles) => candles,
+            None => return Ok(Vec::new())
+        };
+        let limit = query.limit().unwrap_or(usize::MAX);
+        Ok(candles.values()
+            .filter(|candle| query.contains(candle))
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+
+    async fn stream_candles(&self, query: &CandleQuery) -> StorageResult<BoxStream<'static, StorageResult<CandleData>>> {
+        let candles = self.find_candles(query).await?;
+        Ok(stream::iter(candles.into_iter().map(Ok)).boxed())
+    }
+
+    async fn insert_discrepancies(&self, discrepancies: Vec<CandleDiscrepancy>) -> StorageResult<u64> {
+        let count = discrepancies.len() as u64;
+        self.state.lock().unwrap().discrepancies.extend(discrepancies);
+        Ok(count)
+    }
+
+    async fn purge_ticker_data(&self, ticker: &str) -> StorageResult<u64> {
+        let mut state = self.state.lock().unwrap();
+        Ok(take_ticker_candles(&mut state, ticker).len() as u64)
+    }
+
+    async fn archive_ticker_data(&self, ticker: &str) -> StorageResult<u64> {
+        let mut state = self.state.lock().unwrap();
+        let taken = take_ticker_candles(&mut state, ticker);
+        let count = taken.len() as u64;
+        let archived_at = Utc::now();
+        for (collection, candle) in taken {
+            state.archive.entry(collection).or_default().push((archived_at, candle));
+        }
+        Ok(count)
+    }
+
+    async fn upsert_backfill_job(&self, job: &BackfillJob) -> StorageResult<()> {
+        self.state.lock().unwrap().backfill_jobs.insert(job.id.clone(), job.clone());
+        Ok(())
+    }
+
+    async fn get_backfill_job(&self, id: &str) -> StorageResult<Option<BackfillJob>> {
+        Ok(self.state.lock().unwrap().backfill_jobs.get(id).cloned())
+    }
+
+    async fn list_backfill_jobs(&self, status: BackfillStatus) -> StorageResult<Vec<BackfillJob>> {
+        Ok(self.state.lock().unwrap().backfill_jobs.values()
+            .filter(|job| job.status == status)
+            .cloned()
+            .collect())
+    }
+
+    async fn upsert_job(&self, job: &JobRecord) -> StorageResult<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.jobs.get(&job.id).is_some_and(|stored| stored.owner.is_some() && stored.owner != job.owner) {
+            return Err(format!("Job {} is leased by another executor", job.id))?;
+        }
+        state.jobs.insert(job.id.clone(), job.clone());
+        Ok(())
+    }
+
+    async fn get_job(&self, id: &str) -> StorageResult<Option<JobRecord>> {
+        Ok(self.state.lock().unwrap().jobs.get(id).cloned())
+    }
+
+    async fn list_jobs(&self, limit: usize) -> StorageResult<Vec<JobRecord>> {
+        let mut jobs: Vec<JobRecord> = self.state.lock().unwrap().jobs.values().cloned().collect();
+        jobs.sort_by_key(|job| std::cmp::Reverse(job.created_at));
+        jobs.truncate(limit);
+        Ok(jobs)
+    }
+
+    async fn list_expired_jobs(&self, now: DateTime<Utc>) -> StorageResult<Vec<JobRecord>> {
+        Ok(self.state.lock().unwrap().jobs.values()
+            .filter(|job| job.is_unfinished() && job.lease_expired(now))
+            .cloned()
+            .collect())
+    }
+
+    async fn claim_job(&self, id: &str, owner: &str, lease_expires_at: DateTime<Utc>) -> StorageResult<Option<JobRecord>> {
+        let mut state = self.state.lock().unwrap();
+        let job = match state.jobs.get_mut(id) {
+            Some(job) if job.is_unfinished() && (job.owner.as_deref() == Some(owner) || job.lease_expired(Utc::now())) => job,
+            _ => return Ok(None)
+        };
+        job.owner = Some(String::from(owner));
+        job.lease_expires_at = Some(lease_expires_at);
+        Ok(Some(job.clone()))
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::MemoryStorage;
+    use crate::executor::storage::{CandleQuery, Storage, TickerInfo};
+    use crate::executor::tasks::{CandleData, Granularity};
+
+    fn candle(ticker: &str, day: u32, close: f64) -> CandleData {
+        CandleData {
+            ticker: String::from(ticker),
+            timestamp: Utc.with_ymd_and_hms(2023, 8, day, 0, 0, 0).unwrap(),
+            open: 10.0,
+            close,
+            high: 12.0,
+            low: 9.0,
+            volume: 1000,
+            num_transactions: 10,
+            vwap: None,
+            adjusted: true,
+            source: String::from("polygon")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tickers() {
+        let storage = MemoryStorage::new();
+        let nflx = TickerInfo { ticker: String::from("nflx"), company: String::from("netflix inc"), exchange: String::from("xnas"), granularities: vec![Granularity::Days(1)] };
+        storage.upsert_ticker(&nflx).await.unwrap();
+        storage.upsert_ticker(&TickerInfo { company: String::from("netflix"), ..nflx.clone() }).await.unwrap();
+
+        assert_eq!(storage.list_tickers().await.unwrap().len(), 1);
+        assert_eq!(storage.get_ticker("nflx").await.unwrap().unwrap().company, "netflix");
+        assert_eq!(storage.remove_ticker("nflx").await.unwrap(), 1);
+        assert_eq!(storage.remove_ticker("nflx").await.unwrap(), 0);
+        assert_eq!(storage.get_ticker("nflx").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_candles() {
+        let storage = MemoryStorage::new();
+        let day = Granularity::Days(1);
+        assert_eq!(storage.latest_candle_timestamp("nflx", day).await.unwrap(), None);
+
+        storage.upsert_candles(day, vec![candle("nflx", 3, 1.0), candle("nflx", 1, 1.0), candle("aapl", 5, 1.0)]).await.unwrap();
+        // Replaces the candle of the same timestamp
+        storage.upsert_candles(day, vec![candle("nflx", 1, 2.0), candle("nflx", 2, 2.0)]).await.unwrap();
+
+        assert_eq!(storage.latest_candle_timestamp("nflx", day).await.unwrap(), Some(Utc.with_ymd_and_hms(2023, 8, 3, 0, 0, 0).unwrap()));
+        assert_eq!(storage.latest_candle_timestamp("nflx", Granularity::Hours(1)).await.unwrap(), None);
+
+        let all = storage.find_candles(&CandleQuery::new("NFLX", day, None, None, None)).await.unwrap();
+        assert_eq!(all, vec![candle("nflx", 1, 2.0), candle("nflx", 2, 2.0), candle("nflx", 3, 1.0)]);
+
+        let start = Some(Utc.with_ymd_and_hms(2023, 8, 2, 0, 0, 0).unwrap());
+        let range = storage.find_candles(&CandleQuery::new("nflx", day, start, None, Some(1))).await.unwrap();
+        assert_eq!(range, vec![candle("nflx", 2, 2.0)]);
+    }
+
+    #[tokio::test]
+    async fn test_purge_and_archive() {
+        let storage = MemoryStorage::new();
+        storage.upsert_candles(Granularity::Days(1), vec![candle("nflx", 1, 1.0), candle("aapl", 1, 1.0)]).await.unwrap();
+        storage.upsert_candles(Granularity::Hours(1), vec![candle("nflx", 1, 1.0)]).await.unwrap();
+
+        assert_eq!(storage.archive_ticker_data("nflx").await.unwrap(), 2);
+        assert_eq!(storage.purge_ticker_data("nflx").await.unwrap(), 0);
+        assert_eq!(storage.purge_ticker_data("aapl").await.unwrap(), 1);
+        assert!(storage.find_candles(&CandleQuery::new("aapl", Granularity::Days(1), None, None, None)).await.unwrap().is_empty());
+    }
+}
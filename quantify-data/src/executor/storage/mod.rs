@@ -0,0 +1,206 @@
+use std::error::Error;
+
+use chrono::{DateTime, Utc};
+use futures::stream::BoxStream;
+use mongodb::bson::serde_helpers::chrono_datetime_as_bson_datetime;
+use quantify_core::async_trait;
+use serde::{Serialize, Deserialize};
+
+use super::jobs::JobRecord;
+use super::tasks::{CandleData, CandleDiscrepancy, Granularity};
+
+// Backends
+mod memory;
+pub use memory::MemoryStorage;
+mod mongo;
+pub use mongo::MongoStorage;
+
+/// Result of a storage operation
+pub type StorageResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// A subscribed ticker
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TickerInfo {
+    /// Lowercase financial ticker
+    pub ticker: String,
+    pub company: String,
+    pub exchange: String,
+    /// Stored granularities kept up to date
+    #[serde(default = "TickerInfo::default_granularities")]
+    pub granularities: Vec<Granularity>
+}
+
+impl TickerInfo {
+    /// Tickers subscribed before granularities were recorded collect daily candles
+    pub(crate) fn default_granularities() -> Vec<Granularity> {
+        vec![Granularity::Days(1)]
+    }
+}
+
+/// State of a backfill job
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BackfillStatus {
+    /// Chunks remain to be fetched. Backfills of a stopped server resume when their job is reclaimed.
+    Running,
+    /// A chunk failed. Running the backfill again resumes from that chunk.
+    Failed,
+    /// Every chunk was fetched
+    Completed
+}
+
+/// Progress of a historical backfill, fetched in chunks of consecutive dates
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BackfillJob {
+    /// Unique by ticker, granularity and start date
+    pub id: String,
+    pub ticker: String,
+    pub granularity: Granularity,
+    /// First date of the backfill
+    #[serde(with = "chrono_datetime_as_bson_datetime")]
+    pub start: DateTime<Utc>,
+    /// Last date of the backfill, set when the job is created
+    #[serde(with = "chrono_datetime_as_bson_datetime")]
+    pub end: DateTime<Utc>,
+    /// Number of dates per chunk
+    pub chunk_days: i64,
+    pub total_chunks: i64,
+    /// Chunks are completed in order, so this is also the index of the next chunk
+    pub completed_chunks: i64,
+    /// Candles written so far
+    pub inserted: i64,
+    pub status: BackfillStatus,
+    /// Cause of the last failure
+    pub error: Option<String>,
+    #[serde(with = "chrono_datetime_as_bson_datetime")]
+    pub updated_at: DateTime<Utc>
+}
+
+/// A time range query over stored candles
+#[derive(Clone, Debug)]
+pub struct CandleQuery {
+    ticker: String,
+    granularity: Granularity,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+    limit: Option<i64>
+}
+
+impl CandleQuery {
+    /// Constructs a new CandleQuery
+    ///
+    /// # Arguments
+    ///
+    /// * 'ticker' - The financial ticker
+    /// * 'granularity' - Selects the candle collection
+    /// * 'start' - Inclusive lower bound on the timestamp
+    /// * 'end' - Inclusive upper bound on the timestamp
+    /// * 'limit' - Maximum number of candles returned
+    pub fn new(
+        ticker: &str,
+        granularity: Granularity,
+        start: Option<DateTime<Utc>>,
+        end: Option<DateTime<Utc>>,
+        limit: Option<i64>
+    ) -> CandleQuery {
+        CandleQuery { ticker: ticker.to_lowercase(), granularity, start, end, limit }
+    }
+
+    /// The queried granularity
+    pub(crate) fn granularity(&self) -> Granularity {
+        self.granularity
+    }
+
+    /// The query of the stored candles this query is resampled from
+    ///
+    /// Covers the same range without a limit, which applies to the resampled candles.
+    pub(crate) fn base(&self) -> CandleQuery {
+        CandleQuery { granularity: self.granularity.base(), limit: None, ..self.clone() }
+    }
+
+    /// Whether a resampled candle starting at a timestamp is in the range
+    ///
+    /// Resampled candles starting before the range would be partial.
+    pub(crate) fn includes(&self, timestamp: DateTime<Utc>) -> bool {
+        self.start.is_none_or(|start| timestamp >= start)
+    }
+
+    /// The maximum number of candles returned, if limited
+    pub(crate) fn limit(&self) -> Option<usize> {
+        self.limit.filter(|limit| *limit > 0).map(|limit| limit as usize)
+    }
+
+    /// Whether a candle of the queried granularity is in the range
+    fn contains(&self, candle: &CandleData) -> bool {
+        candle.ticker == self.ticker
+            && self.start.is_none_or(|start| candle.timestamp >= start)
+            && self.end.is_none_or(|end| candle.timestamp <= end)
+    }
+}
+
+/// Persistence of tickers, candles and discrepancies
+///
+/// Tickers are stored lowercase. Candles are unique by (ticker, timestamp) within a granularity.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Inserts or replaces a ticker
+    async fn upsert_ticker(&self, ticker: &TickerInfo) -> StorageResult<()>;
+
+    /// Gets a ticker, if subscribed
+    async fn get_ticker(&self, ticker: &str) -> StorageResult<Option<TickerInfo>>;
+
+    /// Lists subscribed tickers
+    async fn list_tickers(&self) -> StorageResult<Vec<TickerInfo>>;
+
+    /// Removes a ticker, returning the number of removed entries
+    async fn remove_ticker(&self, ticker: &str) -> StorageResult<u64>;
+
+    /// The timestamp of the latest stored candle of a ticker
+    async fn latest_candle_timestamp(&self, ticker: &str, granularity: Granularity) -> StorageResult<Option<DateTime<Utc>>>;
+
+    /// Inserts candles, so that writing the same candles again is safe.
+    /// Stored candles of the same ticker and timestamp are replaced where the
+    /// backend supports it, and kept otherwise (Mongo time series collections).
+    /// Returns the number of candles written.
+    async fn upsert_candles(&self, granularity: Granularity, candles: Vec<CandleData>) -> StorageResult<u64>;
+
+    /// Gets candles in a range, sorted by time
+    async fn find_candles(&self, query: &CandleQuery) -> StorageResult<Vec<CandleData>>;
+
+    /// Walks candles in a range, sorted by time
+    async fn stream_candles(&self, query: &CandleQuery) -> StorageResult<BoxStream<'static, StorageResult<CandleData>>>;
+
+    /// Records disagreements between sources
+    async fn insert_discrepancies(&self, discrepancies: Vec<CandleDiscrepancy>) -> StorageResult<u64>;
+
+    /// Deletes the candles and fundamentals of a ticker, returning the number of deleted entries
+    async fn purge_ticker_data(&self, ticker: &str) -> StorageResult<u64>;
+
+    /// Moves the candles and fundamentals of a ticker into the archive, returning the number of archived entries
+    async fn archive_ticker_data(&self, ticker: &str) -> StorageResult<u64>;
+
+    /// Inserts or replaces the progress of a backfill
+    async fn upsert_backfill_job(&self, job: &BackfillJob) -> StorageResult<()>;
+
+    /// Gets the progress of a backfill, if started
+    async fn get_backfill_job(&self, id: &str) -> StorageResult<Option<BackfillJob>>;
+
+    /// Lists backfills in a state
+    async fn list_backfill_jobs(&self, status: BackfillStatus) -> StorageResult<Vec<BackfillJob>>;
+
+    /// Inserts or replaces the record of an executor job, unless another executor holds its lease
+    async fn upsert_job(&self, job: &JobRecord) -> StorageResult<()>;
+
+    /// Gets the record of an executor job
+    async fn get_job(&self, id: &str) -> StorageResult<Option<JobRecord>>;
+
+    /// Lists the most recent executor jobs, newest first
+    async fn list_jobs(&self, limit: usize) -> StorageResult<Vec<JobRecord>>;
+
+    /// Lists the unfinished executor jobs whose lease expired before a time
+    async fn list_expired_jobs(&self, now: DateTime<Utc>) -> StorageResult<Vec<JobRecord>>;
+
+    /// Takes or extends the lease of an unfinished executor job
+    ///
+    /// Returns the leased job, or None if it is finished or another executor's lease has not expired.
+    async fn claim_job(&self, id: &str, owner: &str, lease_expires_at: DateTime<Utc>) -> StorageResult<Option<JobRecord>>;
+}
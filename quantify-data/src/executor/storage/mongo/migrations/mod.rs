@@ -0,0 +1,254 @@
+use chrono::{DateTime, Utc};
+use futures::{TryStreamExt, future::BoxFuture};
+use mongodb::{
+    Database,
+    IndexModel,
+    bson::{self, Bson, Document, doc},
+    error::{Error, ErrorKind, WriteFailure},
+    options::{AggregateOptions, CreateCollectionOptions, IndexOptions, TimeseriesGranularity, TimeseriesOptions}};
+use serde::{Serialize, Deserialize};
+
+use crate::executor::{storage::StorageResult, tasks::{Granularity, CANDLE_COLLECTIONS}};
+use super::{BACKFILL_COLLECTION, DISCREPANCY_COLLECTION, FUNDAMENTALS_COLLECTION, JOB_COLLECTION, TICKER_COLLECTION};
+
+/// Records the applied schema versions
+const MIGRATION_COLLECTION: &str = "_migrations";
+/// Oldest supported server, as archiving deletes candles by id from time series collections
+const MIN_SERVER_VERSION: [i32; 2] = [7, 0];
+/// Server error code of a unique index violation
+const DUPLICATE_KEY: i32 = 11000;
+
+/// A schema change, applied once in version order
+///
+/// The version is recorded before the change is applied, so that concurrent executors
+/// apply it once. It is unrecorded if the change fails, but not if the process stops
+/// meanwhile, so migrations must be idempotent for the record to be deleted and re-applied.
+struct Migration {
+    version: i32,
+    name: &'static str,
+    apply: for<'a> fn(&'a Database) -> BoxFuture<'a, StorageResult<()>>
+}
+
+/// Every migration, by ascending version
+const MIGRATIONS: [Migration; 6] = [
+    Migration { version: 1, name: "create_collections", apply: |db_ref| Box::pin(create_collections(db_ref)) },
+    Migration { version: 2, name: "candle_indexes", apply: |db_ref| Box::pin(candle_indexes(db_ref)) },
+    Migration { version: 3, name: "ticker_index", apply: |db_ref| Box::pin(ticker_index(db_ref)) },
+    Migration { version: 4, name: "backfill_jobs", apply: |db_ref| Box::pin(backfill_jobs(db_ref)) },
+    Migration { version: 5, name: "jobs", apply: |db_ref| Box::pin(jobs(db_ref)) },
+    Migration { version: 6, name: "job_leases", apply: |db_ref| Box::pin(job_leases(db_ref)) },
+];
+
+#[derive(Serialize, Deserialize)]
+struct AppliedMigration {
+    version: i32,
+    name: String,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    applied_at: DateTime<Utc>
+}
+
+/// Applies the pending migrations, returning their names
+///
+/// Fails on servers older than [MIN_SERVER_VERSION]. Each migration is claimed by
+/// recording its version, unique in the collection. Once a migration is claimed by
+/// another executor, the later ones are left to it as they may depend on it.
+///
+/// # Arguments
+///
+/// * 'db_ref' - Mongo database handle for quantify
+pub async fn migrate(db_ref: &Database) -> StorageResult<Vec<&'static str>> {
+    check_server_version(db_ref).await?;
+    // Versions applied twice before the index existed are recorded twice
+    remove_duplicates(db_ref, MIGRATION_COLLECTION, &["version"]).await?;
+    let collection = db_ref.collection::<AppliedMigration>(MIGRATION_COLLECTION);
+    let index = IndexModel::builder()
+        .keys(doc! { "version": 1 })
+        .options(IndexOptions::builder().name(String::from("version")).unique(true).build())
+        .build();
+    collection.create_index(index, None).await?;
+    let current = collection.find(None, None).await?
+        .try_fold(0, |current, applied| async move { Ok(current.max(applied.version)) })
+        .await?;
+
+    let mut applied = Vec::new();
+    for migration in MIGRATIONS.iter().filter(|migration| migration.version > current) {
+        let record = AppliedMigration { version: migration.version, name: String::from(migration.name), applied_at: Utc::now() };
+        match collection.insert_one(record, None).await {
+            Ok(_) => {},
+            Err(e) if is_duplicate_key(&e) => break,
+            Err(e) => return Err(e.into())
+        }
+        if let Err(e) = (migration.apply)(db_ref).await {
+            collection.delete_one(doc! { "version": migration.version }, None).await?;
+            return Err(format!("Migration {} ({}) failed: {e}", migration.version, migration.name).into());
+        }
+        applied.push(migration.name);
+    }
+    Ok(applied)
+}
+
+/// Whether a write failed on a unique index
+fn is_duplicate_key(error: &Error) -> bool {
+    matches!(&*error.kind, ErrorKind::Write(WriteFailure::WriteError(e)) if e.code == DUPLICATE_KEY)
+}
+
+/// Fails if the server is older than [MIN_SERVER_VERSION]
+async fn check_server_version(db_ref: &Database) -> StorageResult<()> {
+    let info = db_ref.run_command(doc! { "buildInfo": 1 }, None).await?;
+    let version: Vec<i32> = info.get_array("versionArray")?.iter().filter_map(Bson::as_i32).collect();
+    if version.as_slice() < MIN_SERVER_VERSION.as_slice() {
+        let [major, minor] = MIN_SERVER_VERSION;
+        return Err(format!("MongoDB {} is not supported, {major}.{minor} or later is required", info.get_str("version").unwrap_or("?")).into());
+    }
+    Ok(())
+}
+
+/// Creates a collection unless it exists
+async fn create_collection(db_ref: &Database, existing: &[String], name: &str, options: CreateCollectionOptions) -> StorageResult<()> {
+    if !existing.iter().any(|collection| collection == name) {
+        db_ref.create_collection(name, options).await?;
+    }
+    Ok(())
+}
+
+/// Time series collection of candles
+fn candle_series(granularity: TimeseriesGranularity) -> CreateCollectionOptions {
+    let timeseries = TimeseriesOptions::builder()
+        .time_field(String::from("timestamp"))
+        .meta_field(Some(String::from("ticker")))
+        .granularity(Some(granularity))
+        .build();
+    CreateCollectionOptions::builder().timeseries(timeseries).build()
+}
+
+/// Deletes documents sharing the same keys, keeping the last inserted
+async fn remove_duplicates(db_ref: &Database, name: &str, keys: &[&str]) -> StorageResult<u64> {
+    let collection = db_ref.collection::<Document>(name);
+    let group: Document = keys.iter().map(|key| (String::from(*key), Bson::String(format!("${key}")))).collect();
+    let pipeline = vec![
+        doc! { "$sort": { "_id": 1 } },
+        doc! { "$group": { "_id": group, "ids": { "$push": "$_id" }, "count": { "$sum": 1 } } },
+        doc! { "$match": { "count": { "$gt": 1 } } },
+    ];
+    let options = AggregateOptions::builder().allow_disk_use(true).build();
+    let duplicates: Vec<Document> = collection.aggregate(pipeline, options).await?.try_collect().await?;
+
+    let mut deleted = 0;
+    for duplicate in duplicates {
+        let ids = duplicate.get_array("ids")?;
+        let stale = ids[..ids.len() - 1].to_vec();
+        deleted += collection.delete_many(doc! { "_id": { "$in": stale } }, None).await?.deleted_count;
+    }
+    Ok(deleted)
+}
+
+/// Tickers, candles, discrepancies and fundamentals
+async fn create_collections(db_ref: &Database) -> StorageResult<()> {
+    let existing = db_ref.list_collection_names(None).await?;
+
+    let validator = doc! {
+        "$jsonSchema": {
+            "bsonType": "object",
+            "title": "Ticker Object Validation",
+            "required": ["ticker", "exchange"],
+            "properties": {
+                "ticker": { "bsonType": "string", "description": "The ticker name for this asset" },
+                "company": { "bsonType": "string", "description": "The company name for this asset" },
+                "exchange": { "bsonType": "string", "description": "The exchange on which the ticker is traded" }
+            }
+        }
+    };
+    let ticker_options = CreateCollectionOptions::builder().validator(validator).build();
+    create_collection(db_ref, &existing, TICKER_COLLECTION, ticker_options).await?;
+
+    create_collection(db_ref, &existing, Granularity::Minutes(1).collection(), candle_series(TimeseriesGranularity::Minutes)).await?;
+    create_collection(db_ref, &existing, Granularity::Hours(1).collection(), candle_series(TimeseriesGranularity::Hours)).await?;
+    create_collection(db_ref, &existing, Granularity::Days(1).collection(), CreateCollectionOptions::default()).await?;
+    create_collection(db_ref, &existing, DISCREPANCY_COLLECTION, CreateCollectionOptions::default()).await?;
+    create_collection(db_ref, &existing, FUNDAMENTALS_COLLECTION, CreateCollectionOptions::default()).await
+}
+
+/// (ticker, timestamp) indexes on the candle collections
+///
+/// Day candles are unique by (ticker, timestamp). Time series collections do not
+/// support unique indexes, so they get a plain compound index and duplicates are
+/// filtered on insert instead.
+async fn candle_indexes(db_ref: &Database) -> StorageResult<()> {
+    for col_name in CANDLE_COLLECTIONS {
+        let unique = col_name == Granularity::Days(1).collection();
+        if unique {
+            remove_duplicates(db_ref, col_name, &["ticker", "timestamp"]).await?;
+        }
+        let index = IndexModel::builder()
+            .keys(doc! { "ticker": 1, "timestamp": 1 })
+            .options(IndexOptions::builder().name(String::from("ticker_timestamp")).unique(unique).build())
+            .build();
+        db_ref.collection::<Document>(col_name).create_index(index, None).await?;
+    }
+    Ok(())
+}
+
+/// Unique index on the ticker name
+async fn ticker_index(db_ref: &Database) -> StorageResult<()> {
+    remove_duplicates(db_ref, TICKER_COLLECTION, &["ticker"]).await?;
+    let index = IndexModel::builder()
+        .keys(doc! { "ticker": 1 })
+        .options(IndexOptions::builder().name(String::from("ticker")).unique(true).build())
+        .build();
+    db_ref.collection::<Document>(TICKER_COLLECTION).create_index(index, None).await?;
+    Ok(())
+}
+
+/// Progress of historical backfills, by job id
+async fn backfill_jobs(db_ref: &Database) -> StorageResult<()> {
+    let existing = db_ref.list_collection_names(None).await?;
+    create_collection(db_ref, &existing, BACKFILL_COLLECTION, CreateCollectionOptions::default()).await?;
+    let index = IndexModel::builder()
+        .keys(doc! { "id": 1 })
+        .options(IndexOptions::builder().name(String::from("id")).unique(true).build())
+        .build();
+    db_ref.collection::<Document>(BACKFILL_COLLECTION).create_index(index, None).await?;
+    Ok(())
+}
+
+/// Records of executor jobs, by id and recency
+async fn jobs(db_ref: &Database) -> StorageResult<()> {
+    let existing = db_ref.list_collection_names(None).await?;
+    create_collection(db_ref, &existing, JOB_COLLECTION, CreateCollectionOptions::default()).await?;
+    let indexes = vec![
+        IndexModel::builder()
+            .keys(doc! { "id": 1 })
+            .options(IndexOptions::builder().name(String::from("id")).unique(true).build())
+            .build(),
+        IndexModel::builder()
+            .keys(doc! { "created_at": -1 })
+            .options(IndexOptions::builder().name(String::from("created_at")).build())
+            .build(),
+    ];
+    db_ref.collection::<Document>(JOB_COLLECTION).create_indexes(indexes, None).await?;
+    Ok(())
+}
+
+/// Unfinished jobs by lease expiry, reclaimed on startup
+async fn job_leases(db_ref: &Database) -> StorageResult<()> {
+    let index = IndexModel::builder()
+        .keys(doc! { "state": 1, "lease_expires_at": 1 })
+        .options(IndexOptions::builder().name(String::from("lease")).build())
+        .build();
+    db_ref.collection::<Document>(JOB_COLLECTION).create_index(index, None).await?;
+    Ok(())
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::MIGRATIONS;
+
+    #[test]
+    fn test_migration_versions() {
+        // Versions start at 1 and are consecutive
+        for (i, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, i as i32 + 1, "{}", migration.name);
+        }
+    }
+}
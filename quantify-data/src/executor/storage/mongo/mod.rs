@@ -0,0 +1,339 @@
+use std::{collections::{HashMap, HashSet}, sync::{Arc, Mutex}};
+
+use chrono::{DateTime, Utc};
+use futures::{StreamExt, TryStreamExt, stream::BoxStream};
+use mongodb::{Collection, Database, options::{ClientOptions, FindOneAndUpdateOptions, FindOneOptions, FindOptions, ReplaceOptions, ReturnDocument}, bson::{self, Bson, Document, doc}};
+use quantify_core::async_trait;
+
+use crate::executor::jobs::{JobRecord, JobState};
+use crate::executor::tasks::{CandleData, CandleDiscrepancy, Granularity, CANDLE_COLLECTIONS};
+
+use super::{BackfillJob, BackfillStatus, CandleQuery, Storage, StorageResult, TickerInfo};
+
+// Schema
+mod migrations;
+
+// MongoDB constants
+const QUANTIFY_DATABASE: &str = "quantify";
+const TICKER_COLLECTION: &str = "tickers";
+const DISCREPANCY_COLLECTION: &str = "candle_discrepancies";
+const FUNDAMENTALS_COLLECTION: &str = "fundamentals";
+const BACKFILL_COLLECTION: &str = "backfill_jobs";
+const JOB_COLLECTION: &str = "jobs";
+const ARCHIVE_PREFIX: &str = "archive_";
+/// Maximum number of candles written per command, keeping commands below the BSON size limit
+const WRITE_BATCH_SIZE: usize = 1000;
+
+/// The states of queued and running jobs, as stored
+fn unfinished_states() -> StorageResult<Vec<Bson>> {
+    Ok(vec![bson::to_bson(&JobState::Queued)?, bson::to_bson(&JobState::Running)?])
+}
+
+/// Serializes the time series candle writes of a (ticker, collection)
+type WriteLocks = HashMap<(String, &'static str), Arc<tokio::sync::Mutex<()>>>;
+
+/// Storage in the quantify MongoDB database
+///
+/// The schema is created and upgraded by [MongoStorage::migrate]
+pub struct MongoStorage {
+    db_ref: Database,
+    candle_writes: Mutex<WriteLocks>
+}
+
+impl MongoStorage {
+    /// Constructs a new MongoStorage
+    ///
+    /// # Arguments
+    ///
+    /// * 'db_ref' - Mongo database handle for quantify
+    pub fn new(db_ref: Database) -> MongoStorage {
+        MongoStorage { db_ref, candle_writes: Mutex::new(HashMap::new()) }
+    }
+
+    /// Connects to the quantify database
+    ///
+    /// # Arguments
+    ///
+    /// * 'uri' - Mongo connection string
+    pub async fn connect(uri: &str) -> StorageResult<MongoStorage> {
+        let mut client_options = ClientOptions::parse(uri).await?;
+        client_options.app_name = Some("Quantify".to_string());
+        let mongo_client = mongodb::Client::with_options(client_options)?;
+        Ok(MongoStorage::new(mongo_client.database(QUANTIFY_DATABASE)))
+    }
+
+    /// Brings the database schema up to date, returning the names of the applied migrations
+    pub async fn migrate(&self) -> StorageResult<Vec<&'static str>> {
+        migrations::migrate(&self.db_ref).await
+    }
+
+    fn candles(&self, granularity: Granularity) -> Collection<CandleData> {
+        self.db_ref.collection(granularity.collection())
+    }
+
+    /// Collections holding the data of a ticker
+    fn data_collections() -> impl Iterator<Item = &'static str> {
+        CANDLE_COLLECTIONS.into_iter().chain([FUNDAMENTALS_COLLECTION])
+    }
+
+    /// Upserts day candles by (ticker, timestamp), in bulk
+    async fn upsert_day_candles(&self, col_name: &str, candles: &[CandleData]) -> StorageResult<u64> {
+        let mut written = 0;
+        for batch in candles.chunks(WRITE_BATCH_SIZE) {
+            let updates = batch.iter()
+                .map(|candle| Ok(doc! {
+                    "q": { "ticker": &candle.ticker, "timestamp": bson::DateTime::from_chrono(candle.timestamp) },
+                    "u": bson::to_document(candle)?,
+                    "upsert": true
+                }))
+                .collect::<Result<Vec<Document>, bson::ser::Error>>()?;
+            let reply = self.db_ref.run_command(doc! { "update": col_name, "updates": updates, "ordered": false }, None).await?;
+            if let Ok(errors) = reply.get_array("writeErrors") {
+                return Err(format!("{} candle writes failed on {col_name}: {:?}", errors.len(), errors.first()).into());
+            }
+            written += reply.get_i32("n").unwrap_or(0) as u64;
+        }
+        Ok(written)
+    }
+
+    /// Inserts time series candles whose timestamps are not yet stored
+    ///
+    /// Writes of a ticker are serialized, so that overlapping updates and backfills
+    /// never insert the same timestamp twice. Other processes writing the same ticker
+    /// are not excluded, and may still insert duplicates.
+    async fn insert_new_candles(&self, granularity: Granularity, candles: Vec<CandleData>) -> StorageResult<u64> {
+        let col_ref = self.candles(granularity);
+        let mut by_ticker: HashMap<String, Vec<CandleData>> = HashMap::new();
+        for candle in candles {
+            by_ticker.entry(candle.ticker.clone()).or_default().push(candle);
+        }
+
+        let mut inserted = 0;
+        for (ticker, candles) in by_ticker {
+            let lock = self.candle_writes.lock().unwrap()
+                .entry((ticker.clone(), granularity.collection()))
+                .or_default()
+                .clone();
+            let _guard = lock.lock().await;
+
+            let timestamps = candles.iter().map(|candle| candle.timestamp);
+            let (start, end) = match (timestamps.clone().min(), timestamps.max()) {
+                (Some(start), Some(end)) => (start, end),
+                _ => continue
+            };
+            let query = CandleQuery::new(&ticker, granularity, Some(start), Some(end), None);
+            let options = FindOptions::builder().projection(doc! { "_id": 0, "timestamp": 1 }).build();
+            let mut seen: HashSet<DateTime<Utc>> = self.db_ref.collection::<Document>(granularity.collection())
+                .find(query.filter(), options).await?
+                .try_filter_map(|document| async move { Ok(document.get_datetime("timestamp").ok().map(|timestamp| timestamp.to_chrono())) })
+                .try_collect().await?;
+
+            // Also drops duplicates within the batch
+            let candles: Vec<CandleData> = candles.into_iter()
+                .filter(|candle| seen.insert(candle.timestamp))
+                .collect();
+            for batch in candles.chunks(WRITE_BATCH_SIZE) {
+                inserted += col_ref.insert_many(batch, None).await?.inserted_ids.len() as u64;
+            }
+        }
+        Ok(inserted)
+    }
+
+    /// Copies matching documents into the archive collection, then deletes them
+    ///
+    /// Documents are moved in batches read from a cursor, so that a single batch is held
+    /// in memory. Only the copied documents are deleted, so that documents inserted
+    /// meanwhile are kept. Deleting by id from time series collections requires MongoDB 7.0.
+    async fn archive(&self, col_name: &str, filter: Document) -> StorageResult<u64> {
+        let collection: Collection<Document> = self.db_ref.collection(col_name);
+        let archive: Collection<Document> = self.db_ref.collection(&format!("{ARCHIVE_PREFIX}{col_name}"));
+
+        let archived_at = bson::DateTime::from_chrono(Utc::now());
+        let mut batches = collection.find(filter, None).await?.try_chunks(WRITE_BATCH_SIZE);
+        let mut deleted = 0;
+        while let Some(mut batch) = batches.try_next().await.map_err(|e| e.1)? {
+            let ids: Vec<Bson> = batch.iter().filter_map(|document| document.get("_id").cloned()).collect();
+            for document in &mut batch {
+                document.insert("archived_at", archived_at);
+            }
+            archive.insert_many(batch, None).await?;
+            deleted += collection.delete_many(doc! { "_id": { "$in": ids } }, None).await?.deleted_count;
+        }
+        Ok(deleted)
+    }
+}
+
+impl CandleQuery {
+    fn filter(&self) -> Document {
+        let mut filter = doc! { "ticker": &self.ticker };
+        let mut range = Document::new();
+        if let Some(start) = self.start {
+            range.insert("$gte", bson::DateTime::from_chrono(start));
+        }
+        if let Some(end) = self.end {
+            range.insert("$lte", bson::DateTime::from_chrono(end));
+        }
+        if !range.is_empty() {
+            filter.insert("timestamp", range);
+        }
+        filter
+    }
+
+    fn find_options(&self) -> FindOptions {
+        FindOptions::builder()
+            .sort(doc! { "timestamp": 1 })
+            .limit(self.limit)
+            .build()
+    }
+}
+
+#[async_trait]
+impl Storage for MongoStorage {
+    async fn upsert_ticker(&self, ticker: &TickerInfo) -> StorageResult<()> {
+        let options = ReplaceOptions::builder().upsert(true).build();
+        self.db_ref.collection::<TickerInfo>(TICKER_COLLECTION)
+            .replace_one(doc! { "ticker": &ticker.ticker }, ticker, options).await?;
+        Ok(())
+    }
+
+    async fn get_ticker(&self, ticker: &str) -> StorageResult<Option<TickerInfo>> {
+        Ok(self.db_ref.collection::<TickerInfo>(TICKER_COLLECTION)
+            .find_one(doc! { "ticker": ticker }, None).await?)
+    }
+
+    async fn list_tickers(&self) -> StorageResult<Vec<TickerInfo>> {
+        Ok(self.db_ref.collection::<TickerInfo>(TICKER_COLLECTION)
+            .find(None, None).await?
+            .try_collect().await?)
+    }
+
+    async fn remove_ticker(&self, ticker: &str) -> StorageResult<u64> {
+        Ok(self.db_ref.collection::<Document>(TICKER_COLLECTION)
+            .delete_many(doc! { "ticker": ticker }, None).await?
+            .deleted_count)
+    }
+
+    async fn latest_candle_timestamp(&self, ticker: &str, granularity: Granularity) -> StorageResult<Option<DateTime<Utc>>> {
+        let find_options = FindOneOptions::builder()
+            .sort(doc! { "timestamp": -1 })
+            .build();
+        Ok(self.candles(granularity).find_one(doc! { "ticker": ticker }, find_options).await?
+            .map(|candle| candle.timestamp))
+    }
+
+    async fn upsert_candles(&self, granularity: Granularity, candles: Vec<CandleData>) -> StorageResult<u64> {
+        if candles.is_empty() {
+            return Ok(0);
+        }
+        match granularity.base() {
+            Granularity::Days(_) => self.upsert_day_candles(granularity.collection(), &candles).await,
+            // Time series collections do not support replacing documents
+            _ => self.insert_new_candles(granularity, candles).await
+        }
+    }
+
+    async fn find_candles(&self, query: &CandleQuery) -> StorageResult<Vec<CandleData>> {
+        let cursor = self.candles(query.granularity).find(query.filter(), query.find_options()).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn stream_candles(&self, query: &CandleQuery) -> StorageResult<BoxStream<'static, StorageResult<CandleData>>> {
+        let cursor = self.candles(query.granularity).find(query.filter(), query.find_options()).await?;
+        Ok(cursor.map_err(|e| e.into()).boxed())
+    }
+
+    async fn insert_discrepancies(&self, discrepancies: Vec<CandleDiscrepancy>) -> StorageResult<u64> {
+        if discrepancies.is_empty() {
+            return Ok(0);
+        }
+        let count = discrepancies.len() as u64;
+        self.db_ref.collection::<CandleDiscrepancy>(DISCREPANCY_COLLECTION)
+            .insert_many(discrepancies, None).await?;
+        Ok(count)
+    }
+
+    async fn purge_ticker_data(&self, ticker: &str) -> StorageResult<u64> {
+        let mut deleted = 0;
+        for col_name in MongoStorage::data_collections() {
+            deleted += self.db_ref.collection::<Document>(col_name)
+                .delete_many(doc! { "ticker": ticker }, None).await?
+                .deleted_count;
+        }
+        Ok(deleted)
+    }
+
+    async fn archive_ticker_data(&self, ticker: &str) -> StorageResult<u64> {
+        let mut archived = 0;
+        for col_name in MongoStorage::data_collections() {
+            archived += self.archive(col_name, doc! { "ticker": ticker }).await?;
+        }
+        Ok(archived)
+    }
+
+    async fn upsert_backfill_job(&self, job: &BackfillJob) -> StorageResult<()> {
+        let options = ReplaceOptions::builder().upsert(true).build();
+        self.db_ref.collection::<BackfillJob>(BACKFILL_COLLECTION)
+            .replace_one(doc! { "id": &job.id }, job, options).await?;
+        Ok(())
+    }
+
+    async fn get_backfill_job(&self, id: &str) -> StorageResult<Option<BackfillJob>> {
+        Ok(self.db_ref.collection::<BackfillJob>(BACKFILL_COLLECTION)
+            .find_one(doc! { "id": id }, None).await?)
+    }
+
+    async fn list_backfill_jobs(&self, status: BackfillStatus) -> StorageResult<Vec<BackfillJob>> {
+        Ok(self.db_ref.collection::<BackfillJob>(BACKFILL_COLLECTION)
+            .find(doc! { "status": bson::to_bson(&status)? }, None).await?
+            .try_collect().await?)
+    }
+
+    async fn upsert_job(&self, job: &JobRecord) -> StorageResult<()> {
+        // Jobs leased by another executor are not matched, and fail to insert on the unique id
+        let options = ReplaceOptions::builder().upsert(true).build();
+        self.db_ref.collection::<JobRecord>(JOB_COLLECTION)
+            .replace_one(doc! { "id": &job.id, "owner": { "$in": [&job.owner, Bson::Null] } }, job, options).await?;
+        Ok(())
+    }
+
+    async fn get_job(&self, id: &str) -> StorageResult<Option<JobRecord>> {
+        Ok(self.db_ref.collection::<JobRecord>(JOB_COLLECTION)
+            .find_one(doc! { "id": id }, None).await?)
+    }
+
+    async fn list_jobs(&self, limit: usize) -> StorageResult<Vec<JobRecord>> {
+        let options = FindOptions::builder()
+            .sort(doc! { "created_at": -1 })
+            .limit(i64::try_from(limit).unwrap_or(i64::MAX))
+            .build();
+        Ok(self.db_ref.collection::<JobRecord>(JOB_COLLECTION)
+            .find(None, options).await?
+            .try_collect().await?)
+    }
+
+    async fn list_expired_jobs(&self, now: DateTime<Utc>) -> StorageResult<Vec<JobRecord>> {
+        let filter = doc! {
+            "state": { "$in": unfinished_states()? },
+            "$or": [{ "lease_expires_at": null }, { "lease_expires_at": { "$lt": bson::DateTime::from_chrono(now) } }]
+        };
+        Ok(self.db_ref.collection::<JobRecord>(JOB_COLLECTION)
+            .find(filter, None).await?
+            .try_collect().await?)
+    }
+
+    async fn claim_job(&self, id: &str, owner: &str, lease_expires_at: DateTime<Utc>) -> StorageResult<Option<JobRecord>> {
+        let filter = doc! {
+            "id": id,
+            "state": { "$in": unfinished_states()? },
+            "$or": [
+                { "owner": owner },
+                { "lease_expires_at": null },
+                { "lease_expires_at": { "$lt": bson::DateTime::from_chrono(Utc::now()) } }
+            ]
+        };
+        let update = doc! { "$set": { "owner": owner, "lease_expires_at": bson::DateTime::from_chrono(lease_expires_at) } };
+        let options = FindOneAndUpdateOptions::builder().return_document(ReturnDocument::After).build();
+        Ok(self.db_ref.collection::<JobRecord>(JOB_COLLECTION)
+            .find_one_and_update(filter, update, options).await?)
+    }
+}
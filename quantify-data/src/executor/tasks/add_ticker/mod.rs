@@ -1,15 +1,25 @@
-use std::{sync::Arc, process::CommandArgs};
+use std::sync::Arc;
 
-use mongodb::{Database, Collection, bson::{Document, doc}};
+use chrono::NaiveDate;
+use quantify_core::{ErrorKind, SourceError};
 use reqwest::Client;
+use tokio_util::sync::CancellationToken;
 
-use crate::executor::{TaskFactory, Executor, Task};
+use crate::executor::{TaskFactory, Executor, Task, cancel::Interrupted, jobs::{JobSpec, Resumable}, queue::Priority, storage::{Storage, TickerInfo}};
 
-use super::resolver::mode;
+use super::{BackfillTask, Granularity, resolver::Observation};
 
 /// Registers a ticker into the database
+///
+/// Metadata fields ("company", "exchange") reported by multiple sources are resolved by the executor's consensus engine.
+/// Granularities are added to those of an already subscribed ticker.
+/// Once cancelled, the task stops waiting for sources and fails without subscribing,
+/// so that no backfill is submitted.
 pub struct AddTickerTask {
-    ticker: String
+    ticker: String,
+    granularities: Vec<Granularity>,
+    /// First date backfilled once subscribed
+    backfill_from: Option<NaiveDate>
 }
 impl AddTickerTask {
     /// Constructs a new instance of AddTickerTask
@@ -17,65 +27,212 @@ impl AddTickerTask {
     /// # Arguments
     /// 
     /// * 'ticker' - The financial ticker
-    pub fn new(ticker: &str) -> AddTickerTask{
+    /// * 'granularities' - Stored granularities to keep up to date
+    pub fn new(ticker: &str, granularities: &[Granularity]) -> AddTickerTask{
         let t = String::from(ticker);
-        AddTickerTask{ticker: t}
+        AddTickerTask{ticker: t, granularities: granularities.to_vec(), backfill_from: None}
+    }
+
+    /// Backfills the granularities from a start date once subscribed, see [AddTickerTask::submit_backfills]
+    pub fn with_backfill(self, start_date: NaiveDate) -> AddTickerTask {
+        AddTickerTask { backfill_from: Some(start_date), ..self }
+    }
+
+    /// Submits the backfills of the subscribed ticker, if any, returning their ids
+    ///
+    /// Backfills run in the background, their failures logged and recorded in their jobs.
+    pub fn submit_backfills(&self, executor: &Arc<Executor>) -> Vec<String> {
+        let Some(start_date) = self.backfill_from else { return Vec::new() };
+        self.granularities.iter()
+            .map(|granularity| {
+                let backfill = Arc::new(BackfillTask::new(&self.ticker, *granularity, start_date));
+                let (_, handle) = executor.submit_resumable(Priority::Background, &backfill);
+                tokio::spawn(async move {
+                    if let Ok(Err(e)) = handle.await {
+                        println!("{e}");
+                    }
+                });
+                backfill.job_id()
+            })
+            .collect()
+    }
+}
+
+impl Resumable for AddTickerTask {
+    fn spec(&self) -> JobSpec {
+        JobSpec::AddTicker { ticker: self.ticker.clone(), granularities: self.granularities.clone(), backfill_from: self.backfill_from }
     }
 }
 
 impl TaskFactory for AddTickerTask {
     /// [AddTickerTask]
-    fn init (this: Arc<Self>, _executor: Arc<Executor>, db_ref: Database, client: Client) -> Task {
+    fn init (this: Arc<Self>, executor: Arc<Executor>, storage: Arc<dyn Storage>, _client: Client, cancel: CancellationToken) -> Task {
         Box::new(async move {
             let ticker: &String = &this.ticker.to_lowercase();
-            // Initialize clients
-            let tiingo_client = tiingo::TiingoRESTClient::new(client.clone());
-            let polygon_client = polygon::PolygonRESTClient::new(client.clone());
-            
-            // Get data
-            let ticker_data_tiingo = tiingo_client.get_metadata(&this.ticker).await;
-            let ticker_data_polygon = polygon_client.get_meta(&this.ticker, None).await;
-
-            let mut company: Vec<String> = Vec::new();
-            let mut exchange: Vec<String> = Vec::new();
-
-            match ticker_data_tiingo {
-                Ok(tiingo_metadata) => {
-                    company.push(tiingo_metadata.name);
-                    exchange.push(tiingo_metadata.exchange_code)
-                },
-                Err(_) => ()
+
+            // Get data, in source priority order
+            let mut company: Vec<Observation<String>> = Vec::new();
+            let mut exchange: Vec<Observation<String>> = Vec::new();
+            let mut errors: Vec<SourceError> = Vec::new();
+
+            for source in executor.sources().iter() {
+                let result = tokio::select! {
+                    result = source.get_metadata(&this.ticker) => result,
+                    _ = cancel.cancelled() => return Err(Interrupted::Cancelled)?
+                };
+                match result {
+                    Ok(metadata) => {
+                        company.push(Observation::new(source.name(), metadata.name));
+                        exchange.push(Observation::new(source.name(), metadata.exchange))
+                    },
+                    Err(e) => {
+                        match e.kind() {
+                            ErrorKind::Unauthorized => println!("Check the {} credentials: {e}", source.name()),
+                            _ => println!("{} metadata unavailable for {ticker}: {e}", source.name())
+                        }
+                        errors.push(e);
+                    }
+                }
             }
-            match ticker_data_polygon {
-                Ok(polygon_metadata) => {
-                    company.push(polygon_metadata.name);
-                    exchange.push(polygon_metadata.primary_exchange)
-                },
-                Err(_) => ()
+
+            if company.is_empty() || exchange.is_empty() {
+                // Report the ticker as unknown only if no source failed for another reason
+                return match errors.into_iter().find(|e| e.kind() != ErrorKind::NotFound) {
+                    Some(e) => Err(e)?,
+                    None => Err(format!("Ticker {ticker} not found"))?
+                };
             }
+            let company = executor.consensus().resolve_text("company", &company).unwrap().to_lowercase();
+            let exchange = executor.consensus().resolve_text("exchange", &exchange).unwrap().to_lowercase();
 
-            if company.len() == 0 || exchange.len() == 0 {
-                return Err(format!("No data found for {ticker}"))?;
+            let mut granularities = match storage.get_ticker(ticker).await? {
+                Some(subscribed) => subscribed.granularities,
+                None => Vec::new()
+            };
+            for granularity in &this.granularities {
+                if !granularities.contains(granularity) {
+                    granularities.push(*granularity);
+                }
             }
-            let company = mode::<String>(&company).unwrap().to_lowercase();
-            let exchange = mode::<String>(&exchange).unwrap().to_lowercase();
 
             // Update meta table
-            let collection: Collection<Document> = db_ref.collection::<Document>("tickers");
-            let ticker_document = doc! {
-                "ticker": ticker,
-                "company": company,
-                "exchange": exchange
-            };
-                // TODO: Error handling
-            collection.delete_many(
-                doc! {
-                    "ticker": ticker
-                } ,
-                None
-            ).await.unwrap();
-            collection.insert_one(ticker_document, None).await.unwrap();
+            if cancel.is_cancelled() {
+                return Err(Interrupted::Cancelled)?;
+            }
+            storage.upsert_ticker(&TickerInfo {
+                ticker: ticker.clone(),
+                company,
+                exchange,
+                granularities
+            }).await?;
             Ok(())
         })
     }
-}
\ No newline at end of file
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use quantify_core::{ErrorKind, Metadata, RateLimit, RateLimiter};
+
+    use super::AddTickerTask;
+    use crate::executor::jobs::{JobRecord, JobState, Resumable};
+    use crate::executor::queue::Priority;
+    use crate::executor::tasks::{BackfillTask, Granularity};
+    use crate::executor::storage::{BackfillStatus, Storage};
+    use crate::executor::testing::{executor, StaticSource};
+
+    fn source(name: &str, company: &str) -> StaticSource {
+        let mut source = StaticSource::new(name);
+        source.metadata = Some(Metadata { ticker: String::from("NFLX"), name: String::from(company), exchange: String::from("XNAS") });
+        source
+    }
+
+    #[tokio::test]
+    async fn test_add_ticker() {
+        let mut unauthorized = StaticSource::new("tiingo");
+        unauthorized.error = Some(ErrorKind::Unauthorized);
+        let (exec, storage) = executor(vec![source("polygon", "Netflix Inc"), unauthorized, source("yfinance", "Netflix, Inc.")]);
+
+        let task = Arc::new(AddTickerTask::new("NFLX", &[Granularity::Days(1)]));
+        exec.execute(&task).await.unwrap().unwrap();
+
+        let ticker = storage.get_ticker("nflx").await.unwrap().unwrap();
+        assert_eq!(ticker.company, "netflix inc");
+        assert_eq!(ticker.exchange, "xnas");
+
+        // Granularities accumulate
+        let task = Arc::new(AddTickerTask::new("nflx", &[Granularity::Hours(1), Granularity::Days(1)]));
+        exec.execute(&task).await.unwrap().unwrap();
+        let ticker = storage.get_ticker("nflx").await.unwrap().unwrap();
+        assert_eq!(ticker.granularities, vec![Granularity::Days(1), Granularity::Hours(1)]);
+    }
+
+    #[tokio::test]
+    async fn test_add_ticker_errors() {
+        // Unknown to every source
+        let (exec, storage) = executor(vec![StaticSource::new("polygon"), StaticSource::new("yfinance")]);
+        let error = exec.execute(&Arc::new(AddTickerTask::new("XXXX", &[Granularity::Days(1)]))).await.unwrap().err().unwrap();
+        assert_eq!(error.to_string(), "Ticker xxxx not found");
+        assert!(storage.list_tickers().await.unwrap().is_empty());
+
+        // Other failures are reported as such
+        let mut failing = StaticSource::new("yfinance");
+        failing.error = Some(ErrorKind::RateLimited);
+        let (exec, _) = executor(vec![StaticSource::new("polygon"), failing]);
+        let error = exec.execute(&Arc::new(AddTickerTask::new("NFLX", &[Granularity::Days(1)]))).await.unwrap().err().unwrap();
+        assert_eq!(error.to_string(), "yfinance has no metadata (rate limited)");
+    }
+
+    #[tokio::test]
+    async fn test_add_ticker_cancel() {
+        // The metadata request waits for the limiter
+        let mut waiting = source("polygon", "Netflix Inc");
+        let limiter = RateLimiter::new(RateLimit::new(1, std::time::Duration::from_secs(3600)));
+        limiter.acquire().await.unwrap();
+        waiting.limiter = Some(limiter);
+        let (exec, storage) = executor(vec![waiting]);
+
+        let start_date = chrono::Utc::now().date_naive() - chrono::Duration::days(5);
+        let task = Arc::new(AddTickerTask::new("NFLX", &[Granularity::Days(1)]).with_backfill(start_date));
+        let (id, handle) = exec.submit_resumable(Priority::Interactive, &exec.retrying(&task));
+        while exec.jobs().get(&id).is_none_or(|job| job.started_at.is_none()) {
+            tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+        }
+
+        assert!(exec.jobs().cancel(&id));
+        let result = tokio::time::timeout(std::time::Duration::from_secs(1), handle).await.unwrap();
+        assert_eq!(result.unwrap().unwrap_err().to_string(), "Task cancelled");
+        assert!(storage.get_ticker("nflx").await.unwrap().is_none());
+        let backfill = BackfillTask::new("NFLX", Granularity::Days(1), start_date).job_id();
+        assert!(storage.get_backfill_job(&backfill).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_add_ticker_resume() {
+        let (exec, storage) = executor(vec![source("polygon", "Netflix Inc")]);
+        let start_date = chrono::Utc::now().date_naive() - chrono::Duration::days(5);
+
+        // Stopped before subscribing the ticker
+        let spec = AddTickerTask::new("NFLX", &[Granularity::Days(1)]).with_backfill(start_date).spec();
+        let mut stopped = JobRecord::new(&spec.name(), Priority::Interactive, Some(spec));
+        stopped.state = JobState::Running;
+        storage.upsert_job(&stopped).await.unwrap();
+
+        for (_, handle) in exec.reclaim_jobs().await.unwrap() {
+            handle.await.unwrap().unwrap();
+        }
+        assert!(storage.get_ticker("nflx").await.unwrap().is_some());
+        // The backfills are submitted once subscribed
+        let id = BackfillTask::new("NFLX", Granularity::Days(1), start_date).job_id();
+        for _ in 0..100 {
+            if storage.get_backfill_job(&id).await.unwrap().is_some_and(|job| job.status == BackfillStatus::Completed) {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+        }
+        panic!("Backfill {id} not completed");
+    }
+}
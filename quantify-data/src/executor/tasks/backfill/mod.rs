@@ -0,0 +1,352 @@
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
+use polygon::MAX_POLYGON_AGGS_LIMIT;
+use reqwest::Client;
+use tokio_util::sync::CancellationToken;
+
+use crate::executor::{Executor, Task, TaskFactory, cancel::Interrupted, jobs::{JobSpec, Resumable}, storage::{BackfillJob, BackfillStatus, Storage}};
+
+use super::{Granularity, UpdateCandleDataTask};
+
+/// The most candles of a stored granularity in a day, including extended trading hours
+fn candles_per_day(granularity: Granularity) -> i64 {
+    match granularity {
+        Granularity::Minutes(_) => 24 * 60,
+        Granularity::Hours(_) => 24,
+        _ => 1
+    }
+}
+
+/// Midnight UTC of a date
+fn midnight(date: NaiveDate) -> DateTime<Utc> {
+    Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap())
+}
+
+impl BackfillJob {
+    /// The dates of a chunk, inclusive
+    fn chunk(&self, index: i64) -> (NaiveDate, NaiveDate) {
+        let start = self.start.date_naive() + Duration::days(index * self.chunk_days);
+        let end = (start + Duration::days(self.chunk_days - 1)).min(self.end.date_naive());
+        (start, end)
+    }
+}
+
+/// Fetches the history of a ticker from a start date until today
+///
+/// The range is split in chunks which fit in a single Polygon aggregates request,
+/// each fetched by an [UpdateCandleDataTask]. Progress is recorded after every chunk,
+/// so running the backfill again resumes after the last completed chunk. Once cancelled,
+/// the backfill is recorded as failed after the current chunk is interrupted.
+pub struct BackfillTask {
+    ticker: String,
+    granularity: Granularity,
+    start_date: NaiveDate,
+    /// Maximum number of candles per chunk
+    chunk_size: i64,
+    job: Mutex<Option<BackfillJob>>
+}
+
+impl BackfillTask {
+    /// Constructs a new instance of BackfillTask
+    ///
+    /// # Arguments
+    ///
+    /// * 'ticker' - The financial ticker
+    /// * 'granularity' - A stored granularity (single minute, hour or day)
+    /// * 'start_date' - First date to fetch
+    pub fn new(ticker: &str, granularity: Granularity, start_date: NaiveDate) -> BackfillTask {
+        BackfillTask {
+            ticker: ticker.to_lowercase(),
+            granularity,
+            start_date,
+            chunk_size: i64::from(MAX_POLYGON_AGGS_LIMIT),
+            job: Mutex::new(None)
+        }
+    }
+
+    /// The id of the job recording the progress of the backfill
+    pub fn job_id(&self) -> String {
+        format!("{}:{}:{}", self.ticker, self.granularity.collection(), self.start_date)
+    }
+
+    /// The progress of the backfill after the last run of the task
+    #[cfg(test)]
+    pub fn job(&self) -> Option<BackfillJob> {
+        self.job.lock().unwrap().clone()
+    }
+
+    /// A new job covering the start date until today
+    fn create_job(&self) -> BackfillJob {
+        let end_date = Utc::now().date_naive();
+        let chunk_days = (self.chunk_size / candles_per_day(self.granularity)).max(1);
+        let days = (end_date - self.start_date).num_days() + 1;
+        BackfillJob {
+            id: self.job_id(),
+            ticker: self.ticker.clone(),
+            granularity: self.granularity,
+            start: midnight(self.start_date),
+            end: midnight(end_date),
+            chunk_days,
+            total_chunks: (days.max(0) + chunk_days - 1) / chunk_days,
+            completed_chunks: 0,
+            inserted: 0,
+            status: BackfillStatus::Running,
+            error: None,
+            updated_at: Utc::now()
+        }
+    }
+}
+
+impl Resumable for BackfillTask {
+    fn spec(&self) -> JobSpec {
+        JobSpec::Backfill { ticker: self.ticker.clone(), granularity: self.granularity, start_date: self.start_date }
+    }
+}
+
+impl TaskFactory for BackfillTask {
+    /// [BackfillTask]
+    fn init (this: Arc<Self>, executor: Arc<Executor>, storage: Arc<dyn Storage>, _client: Client, cancel: CancellationToken) -> Task {
+        Box::new(async move {
+            if !this.granularity.is_base() {
+                return Err(format!("Cannot backfill {:?} candles, they are resampled from {:?} candles", this.granularity, this.granularity.base()))?;
+            }
+
+            let mut job = match storage.get_backfill_job(&this.job_id()).await? {
+                Some(job) => job,
+                None => this.create_job()
+            };
+            if job.status != BackfillStatus::Completed {
+                job.status = BackfillStatus::Running;
+                job.error = None;
+                storage.upsert_backfill_job(&job).await?;
+            }
+
+            while job.completed_chunks < job.total_chunks {
+                let (start_date, end_date) = job.chunk(job.completed_chunks);
+                let task = Arc::new(UpdateCandleDataTask::for_range(&job.ticker, job.granularity, start_date, end_date));
+                let error = match cancel.is_cancelled() {
+                    true => Some(Interrupted::Cancelled.to_string()),
+                    false => match executor.execute(&executor.retrying(&task)).await {
+                        Ok(Ok(())) => None,
+                        Ok(Err(e)) => Some(e.to_string()),
+                        Err(e) => Some(e.to_string())
+                    }
+                };
+                job.updated_at = Utc::now();
+                if let Some(error) = error {
+                    let error = format!("Backfill of {} failed from {start_date} to {end_date}: {error}", job.ticker);
+                    job.status = BackfillStatus::Failed;
+                    job.error = Some(error.clone());
+                    storage.upsert_backfill_job(&job).await?;
+                    *this.job.lock().unwrap() = Some(job);
+                    if cancel.is_cancelled() {
+                        return Err(Interrupted::Cancelled)?;
+                    }
+                    return Err(error)?;
+                }
+                job.completed_chunks += 1;
+                job.inserted += task.inserted() as i64;
+                if job.completed_chunks == job.total_chunks {
+                    job.status = BackfillStatus::Completed;
+                }
+                storage.upsert_backfill_job(&job).await?;
+            }
+
+            if job.status != BackfillStatus::Completed {
+                // Nothing to fetch
+                job.status = BackfillStatus::Completed;
+                storage.upsert_backfill_job(&job).await?;
+            }
+            *this.job.lock().unwrap() = Some(job);
+            Ok(())
+        })
+    }
+
+    /// None, each chunk has its own timeout
+    fn timeout(&self, _default: Option<std::time::Duration>) -> Option<std::time::Duration> {
+        None
+    }
+}
+
+/// Retrieves the progress of a backfill
+pub struct GetBackfillJobTask {
+    id: String,
+    job: Mutex<Option<BackfillJob>>
+}
+
+impl GetBackfillJobTask {
+    /// Constructs a new instance of GetBackfillJobTask
+    ///
+    /// # Arguments
+    ///
+    /// * 'id' - See [BackfillTask::job_id]
+    pub fn new(id: &str) -> GetBackfillJobTask {
+        GetBackfillJobTask { id: String::from(id), job: Mutex::new(None) }
+    }
+
+    /// The job retrieved by the task, if found
+    pub fn job(&self) -> Option<BackfillJob> {
+        self.job.lock().unwrap().clone()
+    }
+}
+
+impl TaskFactory for GetBackfillJobTask {
+    /// [GetBackfillJobTask]
+    fn init (this: Arc<Self>, _executor: Arc<Executor>, storage: Arc<dyn Storage>, _client: Client, _cancel: CancellationToken) -> Task {
+        Box::new(async move {
+            let job = storage.get_backfill_job(&this.id).await?;
+            *this.job.lock().unwrap() = job;
+            Ok(())
+        })
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::{Duration, NaiveDate, Utc};
+    use quantify_core::{Bar, ErrorKind, RateLimit, RateLimiter};
+
+    use super::{BackfillTask, midnight};
+    use crate::executor::jobs::{JobRecord, JobState, Resumable};
+    use crate::executor::queue::Priority;
+    use crate::executor::storage::{BackfillStatus, CandleQuery, Storage};
+    use crate::executor::tasks::Granularity;
+    use crate::executor::testing::{executor, StaticSource};
+
+    /// A source with a daily bar for each of the last days
+    fn source(days: i64) -> (StaticSource, NaiveDate) {
+        let today = Utc::now().date_naive();
+        let mut source = StaticSource::new("polygon");
+        source.bars = (0..days)
+            .map(|ago| Bar {
+                timestamp: midnight(today - Duration::days(ago)),
+                open: 1.0, high: 1.0, low: 1.0, close: 1.0, volume: 1.0,
+                num_transactions: None, vwap: None, adjusted: true
+            })
+            .collect();
+        (source, today - Duration::days(days - 1))
+    }
+
+    fn backfill(start_date: NaiveDate, chunk_size: i64) -> Arc<BackfillTask> {
+        Arc::new(BackfillTask { chunk_size, ..BackfillTask::new("NFLX", Granularity::Days(1), start_date) })
+    }
+
+    async fn stored(storage: &dyn Storage) -> usize {
+        storage.find_candles(&CandleQuery::new("nflx", Granularity::Days(1), None, None, None)).await.unwrap().len()
+    }
+
+    #[test]
+    fn test_chunk_size() {
+        let start_date = NaiveDate::from_ymd_opt(2023, 1, 1).unwrap();
+        let job = BackfillTask::new("nflx", Granularity::Minutes(1), start_date).create_job();
+        assert_eq!(job.id, "nflx:minute_candle:2023-01-01");
+        // 50000 candles cover 34 days of minutes
+        assert_eq!(job.chunk_days, 34);
+        assert_eq!(job.chunk(1), (NaiveDate::from_ymd_opt(2023, 2, 4).unwrap(), NaiveDate::from_ymd_opt(2023, 3, 9).unwrap()));
+
+        let job = BackfillTask::new("nflx", Granularity::Days(1), start_date).create_job();
+        assert_eq!(job.total_chunks, 1);
+    }
+
+    #[tokio::test]
+    async fn test_backfill() {
+        let (source, start_date) = source(10);
+        let (exec, storage) = executor(vec![source]);
+
+        let task = backfill(start_date, 3);
+        exec.execute(&task).await.unwrap().unwrap();
+        let job = task.job().unwrap();
+        assert_eq!((job.status, job.completed_chunks, job.total_chunks, job.inserted), (BackfillStatus::Completed, 4, 4, 10));
+        assert_eq!(storage.get_backfill_job(&task.job_id()).await.unwrap(), Some(job));
+        assert_eq!(stored(storage.as_ref()).await, 10);
+
+        // Completed backfills are not fetched again
+        let task = backfill(start_date, 3);
+        exec.execute(&task).await.unwrap().unwrap();
+        assert_eq!(task.job().unwrap().inserted, 10);
+
+        let task = Arc::new(BackfillTask::new("nflx", Granularity::Hours(4), start_date));
+        assert!(exec.execute(&task).await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_backfill_resume() {
+        let (source, start_date) = source(10);
+        let (exec, storage) = executor(vec![source]);
+
+        // Stopped after the first two chunks
+        let mut job = backfill(start_date, 3).create_job();
+        job.completed_chunks = 2;
+        storage.upsert_backfill_job(&job).await.unwrap();
+        let spec = backfill(start_date, 3).spec();
+        let mut stopped = JobRecord::new(&spec.name(), Priority::Background, Some(spec));
+        stopped.state = JobState::Running;
+        storage.upsert_job(&stopped).await.unwrap();
+
+        let resumed = exec.reclaim_jobs().await.unwrap();
+        assert_eq!(resumed.len(), 1);
+        for (_, handle) in resumed {
+            handle.await.unwrap().unwrap();
+        }
+        let job = storage.get_backfill_job(&job.id).await.unwrap().unwrap();
+        assert_eq!((job.status, job.completed_chunks, job.inserted), (BackfillStatus::Completed, 4, 4));
+        assert_eq!(stored(storage.as_ref()).await, 4);
+    }
+
+    #[tokio::test]
+    async fn test_backfill_failure() {
+        let (mut source, start_date) = source(10);
+        source.error = Some(ErrorKind::Server);
+        let (exec, storage) = executor(vec![source]);
+
+        let task = backfill(start_date, 3);
+        let (id, handle) = exec.submit_resumable(Priority::Background, &task);
+        assert!(handle.await.unwrap().is_err());
+        assert_eq!(storage.get_job(&id).await.unwrap().unwrap().state, JobState::Failed);
+        let job = storage.get_backfill_job(&task.job_id()).await.unwrap().unwrap();
+        assert_eq!((job.status, job.completed_chunks), (BackfillStatus::Failed, 0));
+        assert!(job.error.unwrap().starts_with(&format!("Backfill of nflx failed from {start_date}")));
+        // Failed backfills are not resumed on startup
+        assert!(exec.reclaim_jobs().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_backfill_cancel() {
+        let (mut source, start_date) = source(10);
+        // The second chunk waits for the next request
+        source.limiter = Some(RateLimiter::new(RateLimit::new(1, std::time::Duration::from_secs(3600))));
+        let (exec, storage) = executor(vec![source]);
+
+        let task = backfill(start_date, 3);
+        let (id, handle) = exec.submit_resumable(Priority::Background, &task);
+        while stored(storage.as_ref()).await < 3 {
+            tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+        }
+        assert!(exec.jobs().cancel(&id));
+        assert_eq!(handle.await.unwrap().unwrap_err().to_string(), "Task cancelled");
+        assert_eq!(storage.get_job(&id).await.unwrap().unwrap().state, JobState::Cancelled);
+
+        // Interrupted backfills are not left running
+        let job = storage.get_backfill_job(&task.job_id()).await.unwrap().unwrap();
+        assert_eq!((job.status, job.completed_chunks), (BackfillStatus::Failed, 1));
+        assert!(job.error.unwrap().ends_with("Task cancelled"));
+    }
+
+    #[tokio::test]
+    async fn test_backfill_orphaned() {
+        let (source, start_date) = source(10);
+        let (exec, storage) = executor(vec![source]);
+
+        // Left running by a job which timed out, or by a stopped server
+        let job = backfill(start_date, 3).create_job();
+        storage.upsert_backfill_job(&job).await.unwrap();
+
+        assert!(exec.reclaim_jobs().await.unwrap().is_empty());
+        let job = storage.get_backfill_job(&job.id).await.unwrap().unwrap();
+        assert_eq!((job.status, job.error.as_deref()), (BackfillStatus::Failed, Some("Interrupted by a stopped server")));
+    }
+}
@@ -1,88 +1,749 @@
-use std::sync::Arc;
+use std::{sync::{Arc, Mutex}, collections::BTreeMap};
 
-use chrono::NaiveDateTime;
-use futures::TryStreamExt;
-use mongodb::{Database, options::FindOptions, bson::doc};
-use polygon::PolygonRESTClient;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Utc};
+use futures::{SinkExt, StreamExt, TryStreamExt, channel::mpsc::Sender, future, stream::BoxStream};
+use mongodb::bson::serde_helpers::chrono_datetime_as_bson_datetime;
+use quantify_core::{Bar, Interval, SourceError};
 use serde::{Serialize, Deserialize};
+use tokio_util::sync::CancellationToken;
 
-use crate::executor::{Executor, Task, TaskFactory};
+use crate::executor::{Executor, Task, TaskFactory, cancel::Interrupted, jobs::{JobSpec, Resumable}, storage::{CandleQuery, Storage, StorageResult}};
+
+use super::resolver::{ConsensusEngine, Observation};
+use super::resample::resample;
 
 // MongoDB constants
 const DAY_CANDLE_COLLECTION: &str = "day_candle";
 const HOUR_CANDLE_COLLECTION: &str = "hour_candle";
 const MINUTE_CANDLE_COLLECTION: &str = "minute_candle";
+pub(crate) const CANDLE_COLLECTIONS: [&str; 3] = [
+    DAY_CANDLE_COLLECTION,
+    HOUR_CANDLE_COLLECTION,
+    MINUTE_CANDLE_COLLECTION
+];
+
+/// How far back to fetch when a ticker has no stored candles
+const DEFAULT_LOOKBACK_DAYS: i64 = 365 * 2;
+const DAY_SECONDS: i64 = 24 * 3600;
 
+/// Candle size, as a multiplier of a time unit
+///
+/// Only single minute, hour and day candles are stored. Other granularities are
+/// resampled from the stored candles of their [Granularity::base].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Granularity {
+    Months(i32),
+    Weeks(i32),
     Days(i32),
     Hours(i32),
     Minutes(i32)
 }
 
+impl Granularity {
+    /// The collection storing candles of the base granularity
+    pub(crate) fn collection(&self) -> &'static str {
+        match self {
+            Granularity::Months(_) | Granularity::Weeks(_) | Granularity::Days(_) => DAY_CANDLE_COLLECTION,
+            Granularity::Hours(_) => HOUR_CANDLE_COLLECTION,
+            Granularity::Minutes(_) => MINUTE_CANDLE_COLLECTION,
+        }
+    }
+
+    /// The stored granularity candles of this granularity are resampled from
+    pub(crate) fn base(&self) -> Granularity {
+        match self {
+            Granularity::Months(_) | Granularity::Weeks(_) | Granularity::Days(_) => Granularity::Days(1),
+            Granularity::Hours(_) => Granularity::Hours(1),
+            Granularity::Minutes(_) => Granularity::Minutes(1),
+        }
+    }
+
+    /// Whether candles of this granularity are stored
+    pub(crate) fn is_base(&self) -> bool {
+        *self == self.base()
+    }
+
+    /// The multiplier of the granularity (eg. 5 for 5 minute candles)
+    fn multiplier(&self) -> i32 {
+        match self {
+            Granularity::Months(m) | Granularity::Weeks(m) | Granularity::Days(m)
+                | Granularity::Hours(m) | Granularity::Minutes(m) => *m
+        }
+    }
+
+    /// The start of the candle containing a timestamp
+    ///
+    /// Candles are aligned to the Unix epoch, except weeks which start on Monday
+    /// and months which start on the first.
+    pub(crate) fn bucket(&self, timestamp: DateTime<Utc>) -> DateTime<Utc> {
+        let multiplier = i64::from(self.multiplier().max(1));
+        let floor = |value: i64, step: i64| value - value.rem_euclid(step);
+        let from_seconds = |seconds: i64| Utc.timestamp_opt(seconds, 0).unwrap();
+        let seconds = timestamp.timestamp();
+        match self {
+            Granularity::Minutes(_) => from_seconds(floor(seconds, 60 * multiplier)),
+            Granularity::Hours(_) => from_seconds(floor(seconds, 3600 * multiplier)),
+            Granularity::Days(_) => from_seconds(floor(seconds, DAY_SECONDS * multiplier)),
+            Granularity::Weeks(_) => {
+                // 1970-01-05 was a Monday
+                let monday = 4 * DAY_SECONDS;
+                from_seconds(floor(seconds - monday, 7 * DAY_SECONDS * multiplier) + monday)
+            },
+            Granularity::Months(_) => {
+                let months = floor(i64::from(timestamp.year() - 1970) * 12 + i64::from(timestamp.month0()), multiplier);
+                Utc.with_ymd_and_hms(1970 + months.div_euclid(12) as i32, months.rem_euclid(12) as u32 + 1, 1, 0, 0, 0).unwrap()
+            }
+        }
+    }
+
+    /// The equivalent market data source interval
+    fn interval(&self) -> Interval {
+        match self {
+            Granularity::Months(m) => Interval::Months(*m as u32),
+            Granularity::Weeks(m) => Interval::Weeks(*m as u32),
+            Granularity::Days(m) => Interval::Days(*m as u32),
+            Granularity::Hours(m) => Interval::Hours(*m as u32),
+            Granularity::Minutes(m) => Interval::Minutes(*m as u32),
+        }
+    }
+}
+
 // Definitions
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CandleData {
+    pub ticker: String,
+    #[serde(with = "chrono_datetime_as_bson_datetime")]
+    pub timestamp: DateTime<Utc>,
+    pub open: f64,
+    pub close: f64,
+    pub high: f64,
+    pub low: f64,
+    pub volume: i64,
+    pub num_transactions: i64,
+    /// Volume weighted average price, if reported by a source
+    #[serde(default)]
+    pub vwap: Option<f64>,
+    /// Whether prices are adjusted for splits
+    #[serde(default)]
+    pub adjusted: bool,
+    /// Comma separated sources the candle was resolved from, in source priority order
+    #[serde(default)]
+    pub source: String
+}
+
+impl CandleData {
+    /// Resolves bars of the same timestamp reported by multiple sources
+    ///
+    /// # Arguments
+    ///
+    /// * 'ticker'
+    /// * 'bars' - Bars with their source, in source priority order. Must not be empty
+    /// * 'consensus' - Resolves each of [CANDLE_FIELDS]
+    fn from_bars(ticker: &str, bars: &[(&str, Bar)], consensus: &ConsensusEngine) -> CandleData {
+        let resolve = |field: &str| {
+            consensus.resolve_numeric(field, &observations(bars, field)).unwrap_or(0.0)
+        };
+        CandleData {
+            ticker: ticker.to_string(),
+            timestamp: bars[0].1.timestamp,
+            open: resolve("open"),
+            close: resolve("close"),
+            high: resolve("high"),
+            low: resolve("low"),
+            volume: resolve("volume").round() as i64,
+            num_transactions: resolve("num_transactions").round() as i64,
+            vwap: consensus.resolve_numeric("vwap", &observations(bars, "vwap")),
+            adjusted: bars.iter().all(|(_, bar)| bar.adjusted),
+            source: bars.iter().map(|(source, _)| *source).collect::<Vec<&str>>().join(",")
+        }
+    }
+
+    /// The value of one of [CANDLE_FIELDS]
+    fn field(&self, field: &str) -> Option<f64> {
+        match field {
+            "open" => Some(self.open),
+            "close" => Some(self.close),
+            "high" => Some(self.high),
+            "low" => Some(self.low),
+            "volume" => Some(self.volume as f64),
+            "num_transactions" => Some(self.num_transactions as f64),
+            "vwap" => self.vwap,
+            _ => None
+        }
+    }
+}
+
+/// Candle fields resolved across sources
+const CANDLE_FIELDS: [&str; 7] = ["open", "close", "high", "low", "volume", "num_transactions", "vwap"];
+
+/// The value of one of [CANDLE_FIELDS] reported in a bar, if any
+fn bar_field(bar: &Bar, field: &str) -> Option<f64> {
+    match field {
+        "open" => Some(bar.open),
+        "close" => Some(bar.close),
+        "high" => Some(bar.high),
+        "low" => Some(bar.low),
+        "volume" => Some(bar.volume),
+        "num_transactions" => bar.num_transactions.map(|n| n as f64),
+        "vwap" => bar.vwap,
+        _ => None
+    }
+}
+
+/// Values of a field reported by each source, in source priority order
+fn observations(bars: &[(&str, Bar)], field: &str) -> Vec<Observation<f64>> {
+    bars.iter()
+        .filter_map(|(source, bar)| bar_field(bar, field).map(|v| Observation::new(source, v)))
+        .collect()
+}
+
+/// The spread of values relative to the largest magnitude
+fn relative_difference(values: &[f64]) -> f64 {
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let magnitude = max.abs().max(min.abs());
+    if values.len() < 2 || magnitude == 0.0 {
+        return 0.0;
+    }
+    (max - min) / magnitude
+}
+
+/// A disagreement between sources on a candle field
 #[derive(Debug, Serialize, Deserialize)]
-struct CandleData {
-    ticker: String,
-    timestamp: NaiveDateTime,
-    open: f64,
-    close: f64,
-    high: f64,
-    low: f64,
-    volume: i64,
-    num_transactions: i64
+pub struct CandleDiscrepancy {
+    pub ticker: String,
+    /// Candle collection of the disputed candle
+    pub collection: String,
+    #[serde(with = "chrono_datetime_as_bson_datetime")]
+    pub timestamp: DateTime<Utc>,
+    pub field: String,
+    /// Reporting sources, in source priority order
+    pub sources: Vec<String>,
+    /// Values reported by each source
+    pub values: Vec<f64>,
+    /// The stored value
+    pub consensus: f64,
+    /// (max - min) / max(|max|, |min|)
+    pub relative_difference: f64,
+    #[serde(with = "chrono_datetime_as_bson_datetime")]
+    pub detected_at: DateTime<Utc>
 }
 
+impl CandleDiscrepancy {
+    /// Finds the fields of a consensus candle on which sources disagree beyond the tolerance
+    fn detect(candle: &CandleData, collection: &str, bars: &[(&str, Bar)], tolerance: f64) -> Vec<CandleDiscrepancy> {
+        let detected_at = Utc::now();
+        CANDLE_FIELDS.iter()
+            .filter_map(|field| {
+                let observations = observations(bars, field);
+                let values: Vec<f64> = observations.iter().map(|o| o.value).collect();
+                let relative_difference = relative_difference(&values);
+                if relative_difference <= tolerance {
+                    return None;
+                }
+                Some(CandleDiscrepancy {
+                    ticker: candle.ticker.clone(),
+                    collection: String::from(collection),
+                    timestamp: candle.timestamp,
+                    field: field.to_string(),
+                    sources: observations.into_iter().map(|o| o.source).collect(),
+                    values,
+                    consensus: candle.field(field).unwrap_or_default(),
+                    relative_difference,
+                    detected_at
+                })
+            })
+            .collect()
+    }
+}
+
+/// Fetches candles missing from the database since the latest stored entry, or in a date range
+///
+/// Candles reported by multiple sources are resolved field by field by the executor's consensus engine.
+/// Disagreements beyond the executor's discrepancy tolerance are recorded alongside. Once cancelled,
+/// the task stops fetching and stores nothing.
 pub struct UpdateCandleDataTask {
     ticker: String,
-    granularity: Granularity
+    granularity: Granularity,
+    range: Option<(NaiveDate, NaiveDate)>,
+    inserted: Mutex<u64>,
+    discrepancies: Mutex<u64>
 }
 
 impl UpdateCandleDataTask {
     pub fn new(ticker: &str, granularity: Granularity) -> UpdateCandleDataTask{
         let t = String::from(ticker);
-        UpdateCandleDataTask{ticker: t, granularity}
+        UpdateCandleDataTask{ticker: t, granularity, range: None, inserted: Mutex::new(0), discrepancies: Mutex::new(0)}
+    }
+
+    /// Constructs a task fetching every candle between two dates, inclusive
+    ///
+    /// Stored candles in the range are replaced.
+    pub fn for_range(ticker: &str, granularity: Granularity, start_date: NaiveDate, end_date: NaiveDate) -> UpdateCandleDataTask {
+        UpdateCandleDataTask { range: Some((start_date, end_date)), ..UpdateCandleDataTask::new(ticker, granularity) }
+    }
+
+    /// The number of candles inserted by the last run of the task
+    pub fn inserted(&self) -> u64 {
+        *self.inserted.lock().unwrap()
+    }
+
+    /// The number of discrepancies recorded by the last run of the task
+    pub fn discrepancies(&self) -> u64 {
+        *self.discrepancies.lock().unwrap()
+    }
+}
+
+impl Resumable for UpdateCandleDataTask {
+    fn spec(&self) -> JobSpec {
+        JobSpec::UpdateCandleData { ticker: self.ticker.clone(), granularity: self.granularity, range: self.range }
     }
 }
 
 impl TaskFactory for UpdateCandleDataTask {
-    fn init (this: Arc<Self>, _executor: Arc<Executor>, db_ref: Database, client: reqwest::Client) -> Task {
+    /// [UpdateCandleDataTask]
+    fn init (this: Arc<Self>, executor: Arc<Executor>, storage: Arc<dyn Storage>, _client: reqwest::Client, cancel: CancellationToken) -> Task {
         Box::new(async move {
-            // Initialize clients
-            let _polygon_client = PolygonRESTClient::new(client);
-
-            // Get collection handle and granularity
-            let col_name: &str;
-            let granularity: i32;
-            match this.granularity {
-                Granularity::Days(m) => {
-                    col_name = DAY_CANDLE_COLLECTION;
-                    granularity = m;
-                },
-                Granularity::Hours(m) => {
-                    col_name = HOUR_CANDLE_COLLECTION;
-                    granularity = m;
-                },
-                Granularity::Minutes(m) => {
-                    col_name = MINUTE_CANDLE_COLLECTION;
-                    granularity = m;
+            let ticker = this.ticker.to_lowercase();
+            // Collections hold base resolution candles only
+            if !this.granularity.is_base() {
+                return Err(format!("Cannot store {:?} candles, they are resampled from {:?} candles", this.granularity, this.granularity.base()))?;
+            }
+
+            // Compute missing range from the latest entry
+            let (start_date, end_date, latest) = match this.range {
+                Some((start_date, end_date)) => (start_date, end_date, None),
+                None => {
+                    let latest = storage.latest_candle_timestamp(&ticker, this.granularity).await?;
+                    let end_date = Utc::now().date_naive();
+                    let start_date = match latest {
+                        Some(timestamp) => timestamp.date_naive(),
+                        None => end_date - Duration::days(DEFAULT_LOOKBACK_DAYS)
+                    };
+                    (start_date, end_date, latest)
                 }
             };
-            let col_ref = db_ref.collection::<CandleData>(col_name);
-
-            // Get latest entry
-            let find_options = FindOptions::builder()
-                .sort(doc! { "timestamp": -1 })
-                .limit(1)
-                .build();
-            let mut cursor = col_ref.find(None, find_options).await.unwrap();
-            
-            if let Some(_latest_data) = cursor.try_next().await.unwrap() {
-                // Latest data found
 
+            // Fetch from sources, grouped by timestamp in source priority order
+            let interval = this.granularity.interval();
+            let mut bars: BTreeMap<DateTime<Utc>, Vec<(&str, Bar)>> = BTreeMap::new();
+            let mut errors: Vec<(&str, SourceError)> = Vec::new();
+
+            for source in executor.sources().iter().filter(|source| source.supports(&interval)) {
+                let result = tokio::select! {
+                    result = source.get_bars(&ticker, &start_date, &end_date, &interval) => result,
+                    _ = cancel.cancelled() => return Err(Interrupted::Cancelled)?
+                };
+                match result {
+                    Ok(source_bars) => {
+                        for bar in source_bars {
+                            bars.entry(bar.timestamp).or_default().push((source.name(), bar));
+                        }
+                    },
+                    Err(e) => errors.push((source.name(), e))
+                }
+            }
+
+            if bars.is_empty() && !errors.is_empty() {
+                // Retryable only if every source may succeed later
+                let kind = errors.iter()
+                    .map(|(_, e)| e.kind())
+                    .find(|kind| !kind.is_transient())
+                    .unwrap_or(errors[0].1.kind());
+                let errors: Vec<String> = errors.iter().map(|(name, e)| format!("{name}: {e}")).collect();
+                return Err(SourceError::new(kind, format!("No candle data fetched for {ticker} ({})", errors.join(", "))))?;
+            }
+            for (name, error) in &errors {
+                println!("Partial candle data for {ticker}: {name}: {error}");
+            }
+
+            // Only insert candles after the latest stored entry
+            let collection = this.granularity.collection();
+            let tolerance = executor.discrepancy_tolerance();
+            let mut new_candles: Vec<CandleData> = Vec::new();
+            let mut discrepancies: Vec<CandleDiscrepancy> = Vec::new();
+            for bars in bars.values() {
+                let candle = CandleData::from_bars(&ticker, bars, executor.consensus());
+                if latest.is_some_and(|latest| candle.timestamp <= latest) {
+                    continue;
+                }
+                discrepancies.extend(CandleDiscrepancy::detect(&candle, collection, bars, tolerance));
+                new_candles.push(candle);
+            }
+
+            if cancel.is_cancelled() {
+                return Err(Interrupted::Cancelled)?;
+            }
+            let count = storage.upsert_candles(this.granularity, new_candles).await?;
+            let discrepancy_count = storage.insert_discrepancies(discrepancies).await?;
+            *this.inserted.lock().unwrap() = count;
+            *this.discrepancies.lock().unwrap() = discrepancy_count;
+            Ok(())
+        })
+    }
+}
+
+/// Updates the candle data of every ticker subscribed to a granularity
+pub struct UpdateAllCandleDataTask {
+    granularity: Granularity
+}
+
+impl UpdateAllCandleDataTask {
+    pub fn new(granularity: Granularity) -> UpdateAllCandleDataTask {
+        UpdateAllCandleDataTask { granularity }
+    }
+}
+
+impl TaskFactory for UpdateAllCandleDataTask {
+    /// [UpdateAllCandleDataTask]
+    fn init (this: Arc<Self>, executor: Arc<Executor>, storage: Arc<dyn Storage>, _client: reqwest::Client, cancel: CancellationToken) -> Task {
+        Box::new(async move {
+            let tickers = storage.list_tickers().await?;
+
+            let mut updates = Vec::new();
+            let subscribed = tickers.iter().filter(|t| t.granularities.contains(&this.granularity));
+            for ticker in subscribed.map(|t| t.ticker.as_str()) {
+                let task = Arc::new(UpdateCandleDataTask::new(ticker, this.granularity));
+                updates.push((ticker, executor.execute(&executor.retrying(&task))));
+            }
+
+            let mut failed: Vec<&str> = Vec::new();
+            for (ticker, handle) in updates {
+                if !matches!(handle.await, Ok(Ok(()))) {
+                    failed.push(ticker);
+                }
+            }
+            if cancel.is_cancelled() {
+                return Err(Interrupted::Cancelled)?;
+            }
+            if !failed.is_empty() {
+                return Err(format!("Candle data update failed for {}", failed.join(", ")))?;
+            }
+            Ok(())
+        })
+    }
+
+    /// None, each update has its own timeout
+    fn timeout(&self, _default: Option<std::time::Duration>) -> Option<std::time::Duration> {
+        None
+    }
+}
+
+/// Walks the candles of a query, resampling stored candles if needed
+async fn query_candles(storage: &dyn Storage, query: &CandleQuery) -> StorageResult<BoxStream<'static, StorageResult<CandleData>>> {
+    if query.granularity().is_base() {
+        return storage.stream_candles(query).await;
+    }
+    let included = query.clone();
+    let candles = resample(storage.stream_candles(&query.base()).await?, query.granularity())
+        .try_filter(move |candle| future::ready(included.includes(candle.timestamp)));
+    Ok(match query.limit() {
+        Some(limit) => candles.take(limit).boxed(),
+        None => candles.boxed()
+    })
+}
+
+/// Retrieves candles, sorted by time
+///
+/// Granularities which are not stored are resampled from the stored candles.
+pub struct GetCandleDataTask {
+    query: CandleQuery,
+    candles: Mutex<Vec<CandleData>>
+}
+
+impl GetCandleDataTask {
+    pub fn new(query: CandleQuery) -> GetCandleDataTask {
+        GetCandleDataTask { query, candles: Mutex::new(Vec::new()) }
+    }
+
+    /// Takes the candles retrieved by the task
+    pub fn take_candles(&self) -> Vec<CandleData> {
+        std::mem::take(&mut *self.candles.lock().unwrap())
+    }
+}
+
+impl TaskFactory for GetCandleDataTask {
+    /// [GetCandleDataTask]
+    fn init (this: Arc<Self>, _executor: Arc<Executor>, storage: Arc<dyn Storage>, _client: reqwest::Client, _cancel: CancellationToken) -> Task {
+        Box::new(async move {
+            let candles = if this.query.granularity().is_base() {
+                storage.find_candles(&this.query).await?
             } else {
-                // Latest data not found
+                query_candles(storage.as_ref(), &this.query).await?.try_collect().await?
+            };
+            *this.candles.lock().unwrap() = candles;
+            Ok(())
+        })
+    }
+}
+
+/// Walks candles, sending them in batches, sorted by time
+///
+/// Granularities which are not stored are resampled from the stored candles.
+/// The channel is closed when the task completes. Run with [Executor::execute_stream],
+/// as the task waits for the receiver, which stops waiting once cancelled.
+pub struct StreamCandleDataTask {
+    query: CandleQuery,
+    batch_size: usize,
+    sender: Mutex<Option<Sender<Vec<CandleData>>>>
+}
+
+impl StreamCandleDataTask {
+    /// Constructs a new StreamCandleDataTask
+    ///
+    /// # Arguments
+    ///
+    /// * 'query' - The candles to stream
+    /// * 'batch_size' - Maximum number of candles per batch
+    /// * 'sender' - Receives the batches. A bounded channel limits buffered batches.
+    pub fn new(query: CandleQuery, batch_size: usize, sender: Sender<Vec<CandleData>>) -> StreamCandleDataTask {
+        StreamCandleDataTask { query, batch_size: batch_size.max(1), sender: Mutex::new(Some(sender)) }
+    }
+}
+
+impl TaskFactory for StreamCandleDataTask {
+    /// [StreamCandleDataTask]
+    fn init (this: Arc<Self>, _executor: Arc<Executor>, storage: Arc<dyn Storage>, _client: reqwest::Client, cancel: CancellationToken) -> Task {
+        Box::new(async move {
+            let mut sender = match this.sender.lock().unwrap().take() {
+                Some(sender) => sender,
+                None => return Err("StreamCandleDataTask can only run once")?
+            };
+            let mut cursor = query_candles(storage.as_ref(), &this.query).await?;
+
+            let mut batch: Vec<CandleData> = Vec::with_capacity(this.batch_size);
+            while let Some(candle) = cursor.try_next().await? {
+                batch.push(candle);
+                if batch.len() == this.batch_size {
+                    let full = std::mem::replace(&mut batch, Vec::with_capacity(this.batch_size));
+                    tokio::select! {
+                        sent = sender.send(full) => if sent.is_err() {
+                            // Receiver dropped
+                            return Ok(());
+                        },
+                        _ = cancel.cancelled() => return Err(Interrupted::Cancelled)?
+                    }
+                }
+            }
+            if !batch.is_empty() {
+                tokio::select! {
+                    _ = sender.send(batch) => {},
+                    _ = cancel.cancelled() => return Err(Interrupted::Cancelled)?
+                }
             }
             Ok(())
         })
     }
-}
\ No newline at end of file
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::{Datelike, Duration, TimeZone, Utc};
+    use futures::{StreamExt, channel::mpsc};
+    use quantify_core::{Bar, ErrorKind};
+    use tokio_util::sync::CancellationToken;
+
+    use super::{CandleData, CandleDiscrepancy, Granularity, GetCandleDataTask, StreamCandleDataTask, UpdateAllCandleDataTask, UpdateCandleDataTask, relative_difference};
+    use crate::executor::{Executor, Task, TaskFactory, queue::Priority};
+    use crate::executor::storage::{CandleQuery, Storage, TickerInfo};
+    use crate::executor::tasks::resolver::ConsensusEngine;
+    use crate::executor::testing::{executor, StaticSource};
+
+    fn bar(close: f64, volume: f64, num_transactions: Option<u64>) -> Bar {
+        Bar {
+            timestamp: Utc.with_ymd_and_hms(2023, 8, 1, 0, 0, 0).unwrap(),
+            open: 10.0,
+            high: 12.0,
+            low: 9.0,
+            close,
+            volume,
+            num_transactions,
+            vwap: None,
+            adjusted: true
+        }
+    }
+
+    #[test]
+    fn test_relative_difference() {
+        assert_eq!(relative_difference(&[]), 0.0);
+        assert_eq!(relative_difference(&[5.0]), 0.0);
+        assert_eq!(relative_difference(&[0.0, 0.0]), 0.0);
+        assert_eq!(relative_difference(&[10.0, 8.0]), 0.2);
+    }
+
+    #[test]
+    fn test_reconcile_candle() {
+        let bars = [
+            ("polygon", Bar { vwap: Some(11.2), ..bar(11.0, 1000.0, Some(20)) }),
+            ("tiingo", Bar { adjusted: false, ..bar(11.5, 1001.0, None) }),
+            ("yfinance", bar(11.5, 1000.0, None))
+        ];
+        let candle = CandleData::from_bars("nflx", &bars, &ConsensusEngine::default());
+        assert_eq!(candle.close, 11.5);
+        assert_eq!(candle.volume, 1000);
+        // Only reported by one source
+        assert_eq!(candle.num_transactions, 20);
+        assert_eq!(candle.vwap, Some(11.2));
+        // Tiingo prices are not adjusted
+        assert!(!candle.adjusted);
+        assert_eq!(candle.source, "polygon,tiingo,yfinance");
+
+        let discrepancies = CandleDiscrepancy::detect(&candle, "day_candle", &bars, 0.01);
+        assert_eq!(discrepancies.len(), 1);
+        let close = &discrepancies[0];
+        assert_eq!(close.field, "close");
+        assert_eq!(close.sources, vec!["polygon", "tiingo", "yfinance"]);
+        assert_eq!(close.values, vec![11.0, 11.5, 11.5]);
+        assert_eq!(close.consensus, 11.5);
+        assert!((close.relative_difference - 0.5 / 11.5).abs() < 1e-12);
+
+        assert_eq!(CandleDiscrepancy::detect(&candle, "day_candle", &bars, 0.05).len(), 0);
+    }
+
+    // Daily bars of the last days, oldest first
+    fn recent_bars(days: i64, close: f64) -> Vec<Bar> {
+        let today = Utc::now().date_naive();
+        (1..=days).rev()
+            .map(|ago| Bar {
+                timestamp: Utc.from_utc_datetime(&(today - Duration::days(ago)).and_hms_opt(0, 0, 0).unwrap()),
+                ..bar(close, 1000.0, None)
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_update_candle_data() {
+        let mut polygon = StaticSource::new("polygon");
+        polygon.bars = recent_bars(3, 11.0);
+        let mut tiingo = StaticSource::new("tiingo");
+        tiingo.bars = recent_bars(2, 12.0);
+        let (exec, storage) = executor(vec![polygon, tiingo]);
+
+        let task = Arc::new(UpdateCandleDataTask::new("NFLX", Granularity::Days(1)));
+        exec.execute(&task).await.unwrap().unwrap();
+        assert_eq!(task.inserted(), 3);
+        // Close differs on the two days reported by both sources
+        assert_eq!(task.discrepancies(), 2);
+
+        let query = CandleQuery::new("nflx", Granularity::Days(1), None, None, None);
+        let candles = storage.find_candles(&query).await.unwrap();
+        assert_eq!(candles.len(), 3);
+        // Mode ties are broken by source priority
+        assert!(candles.iter().all(|candle| candle.close == 11.0));
+
+        // Nothing new on the next run
+        exec.execute(&task).await.unwrap().unwrap();
+        assert_eq!(task.inserted(), 0);
+
+        let get = Arc::new(GetCandleDataTask::new(CandleQuery::new("NFLX", Granularity::Days(1), None, None, Some(2))));
+        exec.execute(&get).await.unwrap().unwrap();
+        assert_eq!(get.take_candles(), candles[..2]);
+
+        let (sender, receiver) = mpsc::channel(1);
+        let stream = Arc::new(StreamCandleDataTask::new(query, 2, sender));
+        let handle = exec.execute_stream(&stream);
+        let batches: Vec<Vec<CandleData>> = receiver.collect().await;
+        handle.await.unwrap().unwrap();
+        assert_eq!(batches.iter().map(Vec::len).collect::<Vec<_>>(), vec![2, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_update_candle_data_errors() {
+        let mut failing = StaticSource::new("polygon");
+        failing.error = Some(ErrorKind::Server);
+        let (exec, storage) = executor(vec![failing]);
+
+        let task = Arc::new(UpdateCandleDataTask::new("nflx", Granularity::Days(1)));
+        let error = exec.execute(&task).await.unwrap().err().unwrap();
+        // Kept retryable
+        assert!(crate::executor::retry::is_transient(error.as_ref()));
+        let task = Arc::new(UpdateCandleDataTask::new("nflx", Granularity::Days(5)));
+        assert!(exec.execute(&task).await.unwrap().is_err());
+
+        // Every subscribed ticker is attempted
+        for ticker in ["nflx", "aapl"] {
+            storage.upsert_ticker(&TickerInfo { ticker: String::from(ticker), company: String::new(), exchange: String::new(), granularities: vec![Granularity::Days(1)] }).await.unwrap();
+        }
+        let task = Arc::new(UpdateAllCandleDataTask::new(Granularity::Days(1)));
+        let error = exec.execute(&task).await.unwrap().err().unwrap();
+        assert_eq!(error.to_string(), "Candle data update failed for aapl, nflx");
+        // No ticker collects hour candles
+        let task = Arc::new(UpdateAllCandleDataTask::new(Granularity::Hours(1)));
+        exec.execute(&task).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_get_resampled_candle_data() {
+        let (exec, storage) = executor(Vec::new());
+        // Tuesday 2023-08-01 to Friday 2023-08-18
+        let candles: Vec<CandleData> = (1..=18)
+            .map(|day| CandleData {
+                timestamp: Utc.with_ymd_and_hms(2023, 8, day, 0, 0, 0).unwrap(),
+                ..CandleData::from_bars("nflx", &[("polygon", bar(day as f64, 10.0, Some(1)))], &ConsensusEngine::default())
+            })
+            .collect();
+        storage.upsert_candles(Granularity::Days(1), candles).await.unwrap();
+
+        let weekly = |start, limit| CandleQuery::new("nflx", Granularity::Weeks(1), start, None, limit);
+        let get = Arc::new(GetCandleDataTask::new(weekly(None, None)));
+        exec.execute(&get).await.unwrap().unwrap();
+        let weeks = get.take_candles();
+        assert_eq!(weeks.iter().map(|week| week.timestamp.day()).collect::<Vec<_>>(), vec![31, 7, 14]);
+        assert_eq!(weeks.iter().map(|week| week.volume).collect::<Vec<_>>(), vec![60, 70, 50]);
+        assert_eq!((weeks[1].close, weeks[1].num_transactions), (13.0, 7));
+
+        // The partial week before the start is dropped, and the limit applies to weeks
+        let start = Some(Utc.with_ymd_and_hms(2023, 8, 3, 0, 0, 0).unwrap());
+        let get = Arc::new(GetCandleDataTask::new(weekly(start, Some(1))));
+        exec.execute(&get).await.unwrap().unwrap();
+        assert_eq!(get.take_candles(), weeks[1..2]);
+
+        let (sender, receiver) = mpsc::channel(1);
+        let stream = Arc::new(StreamCandleDataTask::new(CandleQuery::new("nflx", Granularity::Months(1), None, None, None), 10, sender));
+        let handle = exec.execute_stream(&stream);
+        let batches: Vec<Vec<CandleData>> = receiver.collect().await;
+        handle.await.unwrap().unwrap();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].len(), 1);
+        assert_eq!((batches[0][0].open, batches[0][0].close, batches[0][0].volume), (10.0, 18.0, 180));
+    }
+
+    #[tokio::test]
+    async fn test_stream_candle_data_cancel() {
+        let (exec, storage) = executor(Vec::new());
+        let candles: Vec<CandleData> = (1..=10)
+            .map(|day| CandleData {
+                timestamp: Utc.with_ymd_and_hms(2023, 8, day, 0, 0, 0).unwrap(),
+                ..CandleData::from_bars("nflx", &[("polygon", bar(10.0, 10.0, Some(1)))], &ConsensusEngine::default())
+            })
+            .collect();
+        storage.upsert_candles(Granularity::Days(1), candles).await.unwrap();
+
+        struct ClientTask {
+            stream: Arc<StreamCandleDataTask>
+        }
+        impl TaskFactory for ClientTask {
+            fn init(this: Arc<Self>, executor: Arc<Executor>, _storage: Arc<dyn Storage>, _client: reqwest::Client, _cancel: CancellationToken) -> Task {
+                Box::new(async move {
+                    executor.execute_stream(&this.stream).await?
+                })
+            }
+        }
+
+        // The client reads a single batch, the stream waits for it to read the next
+        let (sender, mut receiver) = mpsc::channel(0);
+        let stream = Arc::new(StreamCandleDataTask::new(CandleQuery::new("nflx", Granularity::Days(1), None, None, None), 1, sender));
+        let (id, handle) = exec.submit("stream", Priority::Interactive, &Arc::new(ClientTask { stream }));
+        assert_eq!(receiver.next().await.unwrap().len(), 1);
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+
+        assert!(exec.jobs().cancel(&id));
+        let result = tokio::time::timeout(std::time::Duration::from_secs(1), handle).await.unwrap();
+        assert_eq!(result.unwrap().unwrap_err().to_string(), "Task cancelled");
+        // The channel is closed
+        assert!(receiver.collect::<Vec<_>>().await.len() < 9);
+    }
+}
@@ -0,0 +1,144 @@
+use std::{sync::{Arc, Mutex}, time::Duration};
+
+use reqwest::Client;
+use serde::{Serialize, Deserialize};
+use tokio_util::sync::CancellationToken;
+
+use crate::executor::{TaskFactory, Executor, Task, cancel::GRACE_PERIOD, jobs::{JobSpec, Resumable}, storage::Storage};
+
+/// What happens to the stored data of a removed ticker
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DataPolicy {
+    /// Leave candle data and fundamentals untouched
+    Keep,
+    /// Delete candle data and fundamentals
+    Purge,
+    /// Move candle data and fundamentals into archive collections
+    Archive
+}
+
+/// Unregisters a ticker from the database
+///
+/// The jobs fetching the ticker's candles are cancelled first, so that they do not
+/// write purged candles back.
+pub struct RemoveTickerTask {
+    ticker: String,
+    policy: DataPolicy,
+    affected: Mutex<u64>
+}
+impl RemoveTickerTask {
+    /// Constructs a new instance of RemoveTickerTask
+    ///
+    /// # Arguments
+    ///
+    /// * 'ticker' - The financial ticker
+    /// * 'policy' - Cleanup applied to the ticker's stored data
+    pub fn new(ticker: &str, policy: DataPolicy) -> RemoveTickerTask {
+        let t = String::from(ticker);
+        RemoveTickerTask{ticker: t, policy, affected: Mutex::new(0)}
+    }
+
+    /// The number of documents deleted or archived by the last run of the task
+    pub fn affected(&self) -> u64 {
+        *self.affected.lock().unwrap()
+    }
+}
+
+impl Resumable for RemoveTickerTask {
+    fn spec(&self) -> JobSpec {
+        JobSpec::RemoveTicker { ticker: self.ticker.clone(), data_policy: self.policy }
+    }
+}
+
+impl TaskFactory for RemoveTickerTask {
+    /// [RemoveTickerTask]
+    fn init (this: Arc<Self>, executor: Arc<Executor>, storage: Arc<dyn Storage>, _client: Client, _cancel: CancellationToken) -> Task {
+        Box::new(async move {
+            let ticker = this.ticker.to_lowercase();
+
+            // Interrupted jobs stop within their grace period
+            let cancelled = executor.jobs().cancel_ticker(&ticker);
+            let stopped = async {
+                while cancelled.iter().any(|id| executor.jobs().get(id).is_some_and(|job| job.finished_at.is_none())) {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            };
+            let _ = tokio::time::timeout(GRACE_PERIOD, stopped).await;
+
+            let mut affected = storage.remove_ticker(&ticker).await?;
+            affected += match this.policy {
+                DataPolicy::Keep => 0,
+                DataPolicy::Purge => storage.purge_ticker_data(&ticker).await?,
+                DataPolicy::Archive => storage.archive_ticker_data(&ticker).await?
+            };
+
+            *this.affected.lock().unwrap() = affected;
+            Ok(())
+        })
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::{Duration, TimeZone, Utc};
+    use quantify_core::{Bar, RateLimit, RateLimiter};
+
+    use super::{DataPolicy, RemoveTickerTask};
+    use crate::executor::queue::Priority;
+    use crate::executor::storage::{CandleQuery, Storage, TickerInfo};
+    use crate::executor::tasks::{CandleData, Granularity, UpdateCandleDataTask};
+    use crate::executor::testing::{executor, StaticSource};
+
+    #[tokio::test]
+    async fn test_remove_ticker() {
+        for (policy, affected) in [(DataPolicy::Keep, 1), (DataPolicy::Purge, 2), (DataPolicy::Archive, 2)] {
+            let (exec, storage) = executor(Vec::new());
+            storage.upsert_ticker(&TickerInfo { ticker: String::from("nflx"), company: String::new(), exchange: String::new(), granularities: vec![Granularity::Days(1)] }).await.unwrap();
+            let candle = CandleData {
+                ticker: String::from("nflx"),
+                timestamp: Utc.with_ymd_and_hms(2023, 8, 1, 0, 0, 0).unwrap(),
+                open: 1.0, close: 1.0, high: 1.0, low: 1.0, volume: 1, num_transactions: 1,
+                vwap: None, adjusted: true, source: String::from("polygon")
+            };
+            storage.upsert_candles(Granularity::Days(1), vec![candle]).await.unwrap();
+
+            let keep = matches!(policy, DataPolicy::Keep);
+            let task = Arc::new(RemoveTickerTask::new("NFLX", policy));
+            exec.execute(&task).await.unwrap().unwrap();
+
+            assert_eq!(task.affected(), affected);
+            assert!(storage.list_tickers().await.unwrap().is_empty());
+            let candles = storage.find_candles(&CandleQuery::new("nflx", Granularity::Days(1), None, None, None)).await.unwrap();
+            assert_eq!(candles.len(), if keep { 1 } else { 0 });
+        }
+    }
+
+    #[tokio::test]
+    async fn test_remove_ticker_cancels_jobs() {
+        let mut source = StaticSource::new("polygon");
+        source.bars = vec![Bar {
+            timestamp: Utc::now() - Duration::days(1),
+            open: 1.0, high: 1.0, low: 1.0, close: 1.0, volume: 1.0,
+            num_transactions: None, vwap: None, adjusted: true
+        }];
+        // The update waits for its request
+        let limiter = RateLimiter::new(RateLimit::new(1, std::time::Duration::from_secs(3600)));
+        limiter.acquire().await.unwrap();
+        source.limiter = Some(limiter);
+        let (exec, storage) = executor(vec![source]);
+
+        let update = Arc::new(UpdateCandleDataTask::new("NFLX", Granularity::Days(1)));
+        let (id, handle) = exec.submit_resumable(Priority::Normal, &update);
+        while exec.jobs().get(&id).is_none_or(|job| job.started_at.is_none()) {
+            tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+        }
+
+        let task = Arc::new(RemoveTickerTask::new("nflx", DataPolicy::Purge));
+        exec.execute(&task).await.unwrap().unwrap();
+        assert_eq!(handle.await.unwrap().unwrap_err().to_string(), "Task cancelled");
+        assert!(storage.find_candles(&CandleQuery::new("nflx", Granularity::Days(1), None, None, None)).await.unwrap().is_empty());
+    }
+}
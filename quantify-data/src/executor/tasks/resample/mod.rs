@@ -0,0 +1,143 @@
+use futures::{StreamExt, stream::{self, BoxStream}};
+
+use crate::executor::storage::StorageResult;
+
+use super::{CandleData, Granularity};
+
+impl CandleData {
+    /// Extends a resampled candle with the next candle in time
+    fn merge(&mut self, next: &CandleData) {
+        let volume = self.volume + next.volume;
+        self.vwap = match (self.vwap, next.vwap) {
+            (Some(vwap), Some(next_vwap)) if volume > 0 =>
+                Some((vwap * self.volume as f64 + next_vwap * next.volume as f64) / volume as f64),
+            _ => None
+        };
+        self.high = self.high.max(next.high);
+        self.low = self.low.min(next.low);
+        self.close = next.close;
+        self.volume = volume;
+        self.num_transactions += next.num_transactions;
+        self.adjusted &= next.adjusted;
+        for source in next.source.split(',').filter(|source| !source.is_empty()) {
+            if !self.source.split(',').any(|s| s == source) {
+                if !self.source.is_empty() {
+                    self.source.push(',');
+                }
+                self.source.push_str(source);
+            }
+        }
+    }
+}
+
+/// Aggregates time sorted candles into candles of a coarser granularity
+///
+/// Each candle takes the first open, highest high, lowest low, last close and
+/// summed volume and transactions of the candles in its [Granularity::bucket].
+/// The stream ends after the first error.
+///
+/// # Arguments
+///
+/// * 'candles' - Candles of a finer granularity, sorted by time
+/// * 'granularity' - The granularity to resample to
+pub(crate) fn resample(
+    candles: BoxStream<'static, StorageResult<CandleData>>,
+    granularity: Granularity
+) -> BoxStream<'static, StorageResult<CandleData>> {
+    stream::unfold(Some((candles, None::<CandleData>)), move |state| async move {
+        let (mut candles, mut current) = state?;
+        loop {
+            match candles.next().await {
+                Some(Ok(candle)) => {
+                    let bucket = granularity.bucket(candle.timestamp);
+                    match current.as_mut() {
+                        Some(resampled) if resampled.timestamp == bucket => resampled.merge(&candle),
+                        _ => {
+                            let next = CandleData { timestamp: bucket, ..candle };
+                            if let Some(complete) = current.replace(next) {
+                                return Some((Ok(complete), Some((candles, current))));
+                            }
+                        }
+                    }
+                },
+                Some(Err(e)) => return Some((Err(e), None)),
+                None => return current.map(|resampled| (Ok(resampled), None))
+            }
+        }
+    }).boxed()
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, TimeZone, Utc};
+    use futures::{StreamExt, TryStreamExt, stream};
+
+    use super::resample;
+    use crate::executor::tasks::{CandleData, Granularity};
+
+    fn time(month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2023, month, day, hour, minute, 0).unwrap()
+    }
+
+    fn candle(timestamp: DateTime<Utc>, open: f64, close: f64, volume: i64) -> CandleData {
+        CandleData {
+            ticker: String::from("nflx"),
+            timestamp,
+            open,
+            close,
+            high: open.max(close),
+            low: open.min(close),
+            volume,
+            num_transactions: 1,
+            vwap: Some((open + close) / 2.0),
+            adjusted: true,
+            source: String::from("polygon")
+        }
+    }
+
+    #[test]
+    fn test_bucket() {
+        let timestamp = time(8, 2, 13, 47);
+        assert_eq!(Granularity::Minutes(1).bucket(timestamp), timestamp);
+        assert_eq!(Granularity::Minutes(5).bucket(timestamp), time(8, 2, 13, 45));
+        assert_eq!(Granularity::Hours(4).bucket(timestamp), time(8, 2, 12, 0));
+        assert_eq!(Granularity::Days(1).bucket(timestamp), time(8, 2, 0, 0));
+        // 2023-08-02 is a Wednesday
+        assert_eq!(Granularity::Weeks(1).bucket(timestamp), time(7, 31, 0, 0));
+        assert_eq!(Granularity::Weeks(1).bucket(time(7, 30, 23, 59)), time(7, 24, 0, 0));
+        assert_eq!(Granularity::Months(1).bucket(timestamp), time(8, 1, 0, 0));
+        assert_eq!(Granularity::Months(3).bucket(timestamp), time(7, 1, 0, 0));
+        assert_eq!(Granularity::Months(12).bucket(timestamp), time(1, 1, 0, 0));
+    }
+
+    #[tokio::test]
+    async fn test_resample() {
+        let candles = vec![
+            candle(time(8, 2, 13, 44), 10.0, 11.0, 100),
+            candle(time(8, 2, 13, 45), 11.0, 13.0, 100),
+            candle(time(8, 2, 13, 46), 13.0, 9.0, 300),
+            CandleData { vwap: None, source: String::from("tiingo"), ..candle(time(8, 2, 13, 50), 9.0, 10.0, 50) },
+        ];
+        let resampled: Vec<CandleData> = resample(stream::iter(candles.into_iter().map(Ok)).boxed(), Granularity::Minutes(5))
+            .try_collect().await.unwrap();
+
+        assert_eq!(resampled.len(), 3);
+        assert_eq!(resampled[0], CandleData { timestamp: time(8, 2, 13, 40), ..candle(time(8, 2, 13, 44), 10.0, 11.0, 100) });
+        let merged = &resampled[1];
+        assert_eq!(merged.timestamp, time(8, 2, 13, 45));
+        assert_eq!((merged.open, merged.high, merged.low, merged.close), (11.0, 13.0, 9.0, 9.0));
+        assert_eq!((merged.volume, merged.num_transactions), (400, 2));
+        assert_eq!(merged.vwap, Some((12.0 * 100.0 + 11.0 * 300.0) / 400.0));
+        assert_eq!(resampled[2].source, "tiingo");
+        assert_eq!(resampled[2].vwap, None);
+    }
+
+    #[tokio::test]
+    async fn test_resample_error() {
+        let candles = vec![Ok(candle(time(8, 2, 0, 0), 1.0, 1.0, 1)), Err("cursor failed".into()), Ok(candle(time(8, 3, 0, 0), 1.0, 1.0, 1))];
+        let resampled: Vec<_> = resample(stream::iter(candles).boxed(), Granularity::Days(1)).collect().await;
+        assert_eq!(resampled.len(), 1);
+        assert!(resampled[0].is_err());
+    }
+}
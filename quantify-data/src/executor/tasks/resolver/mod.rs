@@ -0,0 +1,287 @@
+//! Utility functions to resolve discrepancies involving multiple data sources
+//!
+//! Values are always passed in source priority order, which breaks ties.
+
+use std::{cmp::Ordering, collections::HashMap, str::FromStr};
+
+/// A value reported by a source
+#[derive(Clone, Debug, PartialEq)]
+pub struct Observation<T> {
+    pub source: String,
+    pub value: T
+}
+
+impl<T> Observation<T> {
+    pub fn new(source: &str, value: T) -> Observation<T> {
+        Observation { source: String::from(source), value }
+    }
+}
+
+/// How a single field is resolved
+#[derive(Clone, Debug, PartialEq)]
+pub enum Strategy {
+    /// The most common value. The tiebreaker is the index of the value.
+    Mode,
+    /// The middle value. The tiebreaker (even collection) is the smaller value.
+    Median,
+    /// Numeric fields take the mean weighted by source weight.
+    /// Other fields take the value with the highest total source weight.
+    Weighted,
+    /// The mean after discarding the given fraction (0 to 0.5) of both the lowest and highest values.
+    /// Other than numeric fields fall back to the mode.
+    TrimmedMean(f64)
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    /// Parses "mode", "median", "weighted" or "trimmed_mean:<fraction>"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "mode" => Ok(Strategy::Mode),
+            "median" => Ok(Strategy::Median),
+            "weighted" => Ok(Strategy::Weighted),
+            other => match other.strip_prefix("trimmed_mean:").map(str::parse::<f64>) {
+                Some(Ok(fraction)) if (0.0..=0.5).contains(&fraction) => Ok(Strategy::TrimmedMean(fraction)),
+                _ => Err(format!("Unknown consensus strategy {other}"))
+            }
+        }
+    }
+}
+
+/// Returns the most common value. The tiebreaker is the index of the value.
+pub fn mode<T: PartialEq>(values: &[T]) -> Option<&T> {
+    let weights = vec![1.0; values.len()];
+    weighted_vote(values, &weights)
+}
+
+/// Returns the value with the highest total weight. The tiebreaker is the index of the value.
+///
+/// # Arguments
+///
+/// * 'values'
+/// * 'weights' - The weight of each value, by index
+pub fn weighted_vote<'a, T: PartialEq>(values: &'a [T], weights: &[f64]) -> Option<&'a T> {
+    let mut best: Option<(&T, f64)> = None;
+    for v in values {
+        let total: f64 = values.iter().zip(weights)
+            .filter(|(other, _)| *other == v)
+            .map(|(_, weight)| weight)
+            .sum();
+        // Strictly greater, so the earliest value wins ties
+        if best.is_none_or(|(_, maximum)| total > maximum) {
+            best = Some((v, total));
+        }
+    }
+    best.map(|(v, _)| v)
+}
+
+/// Returns the median value. The tiebreaker (even collection) is the smaller value.
+///
+/// Values that cannot be ordered (eg. NaN) are ignored.
+pub fn median<T: PartialOrd>(values: &[T]) -> Option<&T> {
+    let mut sorted: Vec<&T> = values.iter()
+        .filter(|v| v.partial_cmp(v).is_some())
+        .collect();
+    if sorted.is_empty() {
+        return None;
+    }
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    Some(sorted[(sorted.len() - 1) / 2])
+}
+
+/// Returns the mean weighted by the weight of each value, or None if the total weight is not positive
+pub fn weighted_mean(values: &[f64], weights: &[f64]) -> Option<f64> {
+    let total: f64 = weights.iter().take(values.len()).sum();
+    if values.is_empty() || total <= 0.0 {
+        return None;
+    }
+    Some(values.iter().zip(weights).map(|(v, w)| v * w).sum::<f64>() / total)
+}
+
+/// Returns the mean after discarding the given fraction of both the lowest and highest values
+///
+/// At least one value (two for an even collection) is always kept. NaN values are ignored.
+pub fn trimmed_mean(values: &[f64], fraction: f64) -> Option<f64> {
+    let mut sorted: Vec<f64> = values.iter().copied().filter(|v| !v.is_nan()).collect();
+    if sorted.is_empty() {
+        return None;
+    }
+    sorted.sort_by(f64::total_cmp);
+    let n = sorted.len();
+    let trim = ((n as f64 * fraction.clamp(0.0, 0.5)).floor() as usize).min((n - 1) / 2);
+    let kept = &sorted[trim..n - trim];
+    Some(kept.iter().sum::<f64>() / kept.len() as f64)
+}
+
+/// Resolves fields reported by multiple sources, with a strategy per field
+#[derive(Clone, Debug)]
+pub struct ConsensusEngine {
+    default: Strategy,
+    fields: HashMap<String, Strategy>,
+    weights: HashMap<String, f64>
+}
+
+impl Default for ConsensusEngine {
+    fn default() -> Self {
+        ConsensusEngine::new(Strategy::Mode)
+    }
+}
+
+impl ConsensusEngine {
+    /// Constructs an engine applying a strategy to all fields
+    ///
+    /// Sources have a weight of 1 unless set otherwise
+    pub fn new(default: Strategy) -> ConsensusEngine {
+        ConsensusEngine { default, fields: HashMap::new(), weights: HashMap::new() }
+    }
+
+    /// Overrides the strategy of a field
+    pub fn with_field(mut self, field: &str, strategy: Strategy) -> ConsensusEngine {
+        self.fields.insert(String::from(field), strategy);
+        self
+    }
+
+    /// Weighs sources by priority: with n sources, the first has weight n and the last weight 1
+    pub fn with_priority<'a>(mut self, sources: impl IntoIterator<Item = &'a str>) -> ConsensusEngine {
+        let sources: Vec<&str> = sources.into_iter().collect();
+        let n = sources.len();
+        for (i, source) in sources.into_iter().enumerate() {
+            self.weights.insert(String::from(source), (n - i) as f64);
+        }
+        self
+    }
+
+    /// Parses a default strategy followed by per field overrides,
+    /// eg. "median,volume=weighted,close=trimmed_mean:0.2"
+    pub fn parse(spec: &str) -> Result<ConsensusEngine, String> {
+        let mut parts = spec.split(',');
+        let mut engine = ConsensusEngine::new(parts.next().unwrap_or_default().parse()?);
+        for part in parts {
+            let (field, strategy) = part.split_once('=')
+                .ok_or_else(|| format!("Expected field=strategy, found {part}"))?;
+            engine = engine.with_field(field.trim(), strategy.parse()?);
+        }
+        Ok(engine)
+    }
+
+    /// The strategy used for a field
+    pub fn strategy(&self, field: &str) -> &Strategy {
+        self.fields.get(field).unwrap_or(&self.default)
+    }
+
+    fn weights<T>(&self, values: &[Observation<T>]) -> Vec<f64> {
+        values.iter()
+            .map(|o| *self.weights.get(&o.source).unwrap_or(&1.0))
+            .collect()
+    }
+
+    /// Resolves a numeric field. Observations must be in source priority order.
+    pub fn resolve_numeric(&self, field: &str, values: &[Observation<f64>]) -> Option<f64> {
+        let raw: Vec<f64> = values.iter().map(|o| o.value).collect();
+        match self.strategy(field) {
+            Strategy::Mode => mode(&raw).copied(),
+            Strategy::Median => median(&raw).copied(),
+            Strategy::Weighted => weighted_mean(&raw, &self.weights(values)),
+            Strategy::TrimmedMean(fraction) => trimmed_mean(&raw, *fraction)
+        }
+    }
+
+    /// Resolves a text field. Observations must be in source priority order.
+    pub fn resolve_text(&self, field: &str, values: &[Observation<String>]) -> Option<String> {
+        let raw: Vec<&String> = values.iter().map(|o| &o.value).collect();
+        let resolved = match self.strategy(field) {
+            Strategy::Mode | Strategy::TrimmedMean(_) => mode(&raw),
+            Strategy::Median => median(&raw),
+            Strategy::Weighted => weighted_vote(&raw, &self.weights(values))
+        };
+        resolved.map(|v| v.to_string())
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mode() {
+        assert_eq!(mode::<i32>(&[]), None);
+        assert_eq!(mode(&[3, 1, 1, 3, 2, 1]), Some(&1));
+        // Ties are broken by index, whatever the order
+        for _ in 0..10 {
+            assert_eq!(mode(&["b", "a", "a", "b", "c"]), Some(&"b"));
+            assert_eq!(mode(&["a", "b", "c"]), Some(&"a"));
+        }
+        assert_eq!(mode(&[1.5, 2.5, 2.5]), Some(&2.5));
+    }
+
+    #[test]
+    fn test_median() {
+        assert_eq!(median::<i32>(&[]), None);
+        assert_eq!(median(&[5, 1, 3]), Some(&3));
+        // Even collection takes the smaller value
+        assert_eq!(median(&[4, 1, 3, 2]), Some(&2));
+        assert_eq!(median(&[2.0, f64::NAN, 1.0]), Some(&1.0));
+        assert_eq!(median(&[f64::NAN]), None);
+    }
+
+    #[test]
+    fn test_weighted() {
+        assert_eq!(weighted_mean(&[1.0, 4.0], &[2.0, 1.0]), Some(2.0));
+        assert_eq!(weighted_mean(&[1.0, 4.0], &[0.0, 0.0]), None);
+        assert_eq!(weighted_mean(&[], &[]), None);
+        assert_eq!(weighted_vote(&["a", "b", "b"], &[3.0, 1.0, 1.0]), Some(&"a"));
+        assert_eq!(weighted_vote(&["a", "b", "b"], &[2.0, 1.0, 1.0]), Some(&"a"));
+        assert_eq!(weighted_vote(&["a", "b", "b"], &[1.0, 1.0, 1.0]), Some(&"b"));
+    }
+
+    #[test]
+    fn test_trimmed_mean() {
+        assert_eq!(trimmed_mean(&[], 0.1), None);
+        assert_eq!(trimmed_mean(&[1.0, 2.0, 3.0, 100.0], 0.0), Some(26.5));
+        assert_eq!(trimmed_mean(&[1.0, 2.0, 3.0, 100.0], 0.25), Some(2.5));
+        // At least the middle values are kept
+        assert_eq!(trimmed_mean(&[1.0, 2.0, 3.0], 0.5), Some(2.0));
+        assert_eq!(trimmed_mean(&[1.0, 3.0], 0.5), Some(2.0));
+    }
+
+    #[test]
+    fn test_consensus_engine() {
+        let engine = ConsensusEngine::new(Strategy::Median)
+            .with_field("volume", Strategy::Weighted)
+            .with_field("name", Strategy::Weighted)
+            .with_priority(["polygon", "tiingo", "yfinance"]);
+
+        let close = [
+            Observation::new("polygon", 10.0),
+            Observation::new("tiingo", 12.0),
+            Observation::new("yfinance", 11.0)
+        ];
+        assert_eq!(engine.resolve_numeric("close", &close), Some(11.0));
+        assert_eq!(engine.resolve_numeric("volume", &close), Some((30.0 + 24.0 + 11.0) / 6.0));
+
+        let name = [
+            Observation::new("polygon", String::from("Alphabet Inc.")),
+            Observation::new("tiingo", String::from("Alphabet Inc - Class A")),
+            Observation::new("yfinance", String::from("Alphabet Inc - Class A"))
+        ];
+        // Priority weights 3 against 2 + 1: tie broken by index
+        assert_eq!(engine.resolve_text("name", &name), Some(String::from("Alphabet Inc.")));
+        assert_eq!(ConsensusEngine::default().resolve_text("name", &name), Some(String::from("Alphabet Inc - Class A")));
+        assert_eq!(engine.resolve_numeric("close", &[]), None);
+    }
+
+    #[test]
+    fn test_consensus_engine_parse() {
+        let engine = ConsensusEngine::parse("median, volume=weighted, close=trimmed_mean:0.2").unwrap();
+        assert_eq!(engine.strategy("open"), &Strategy::Median);
+        assert_eq!(engine.strategy("volume"), &Strategy::Weighted);
+        assert_eq!(engine.strategy("close"), &Strategy::TrimmedMean(0.2));
+        assert_eq!(ConsensusEngine::parse("mode").unwrap().strategy("open"), &Strategy::Mode);
+
+        assert!(ConsensusEngine::parse("average").is_err());
+        assert!(ConsensusEngine::parse("mode,volume").is_err());
+        assert!(ConsensusEngine::parse("trimmed_mean:0.7").is_err());
+    }
+}
@@ -0,0 +1,86 @@
+//! Test doubles for tasks and RPC handlers
+
+use std::{sync::Arc, time::Duration};
+
+use chrono::NaiveDate;
+use quantify_core::{async_trait, Bar, ErrorKind, Interval, MarketDataSource, Metadata, QuotaUsage, RateLimiter, SourceError, SourceRegistry, SourceResult};
+
+use super::{Executor, retry::RetryPolicy, storage::MemoryStorage, tasks::resolver::ConsensusEngine};
+
+/// A market data source serving fixed data, or failing with a fixed error kind
+pub struct StaticSource {
+    pub name: String,
+    pub metadata: Option<Metadata>,
+    pub bars: Vec<Bar>,
+    pub error: Option<ErrorKind>,
+    /// Counts requests, if set
+    pub limiter: Option<RateLimiter>
+}
+
+impl StaticSource {
+    pub fn new(name: &str) -> StaticSource {
+        StaticSource { name: String::from(name), metadata: None, bars: Vec::new(), error: None, limiter: None }
+    }
+
+    async fn acquire(&self) -> SourceResult<()> {
+        match &self.limiter {
+            Some(limiter) => Ok(limiter.acquire().await?),
+            None => Ok(())
+        }
+    }
+
+    fn fail<T>(&self, what: &str) -> SourceResult<T> {
+        let kind = self.error.unwrap_or(ErrorKind::NotFound);
+        Err(SourceError::new(kind, format!("{} has no {what}", self.name)))
+    }
+}
+
+#[async_trait]
+impl MarketDataSource for StaticSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn supports(&self, interval: &Interval) -> bool {
+        *interval == Interval::Days(1)
+    }
+
+    fn quota(&self) -> Option<QuotaUsage> {
+        self.limiter.as_ref().map(RateLimiter::usage)
+    }
+
+    async fn get_metadata(&self, _ticker: &str) -> SourceResult<Metadata> {
+        self.acquire().await?;
+        match (&self.error, &self.metadata) {
+            (None, Some(metadata)) => Ok(metadata.clone()),
+            _ => self.fail("metadata")
+        }
+    }
+
+    async fn get_bars(&self, _ticker: &str, start_date: &NaiveDate, end_date: &NaiveDate, _interval: &Interval) -> SourceResult<Vec<Bar>> {
+        self.acquire().await?;
+        if self.error.is_some() {
+            return self.fail("bars");
+        }
+        Ok(self.bars.iter()
+            .filter(|bar| (*start_date..=*end_date).contains(&bar.timestamp.date_naive()))
+            .cloned()
+            .collect())
+    }
+}
+
+/// Creates an executor with in-memory storage and the given sources, in priority order
+///
+/// Tasks are retried without waiting.
+pub fn executor(sources: Vec<StaticSource>) -> (Arc<Executor>, Arc<MemoryStorage>) {
+    let storage = Arc::new(MemoryStorage::new());
+    let mut registry = SourceRegistry::new();
+    for source in sources {
+        registry.register(Arc::new(source));
+    }
+    let consensus = ConsensusEngine::default().with_priority(registry.iter().map(|source| source.name()));
+    let retry_policy = RetryPolicy::default().with_backoff(Duration::ZERO, Duration::ZERO, 1.0);
+    let executor = Executor::new(storage.clone(), reqwest::Client::new(), registry, consensus, 0.005)
+        .with_retry_policy(retry_policy);
+    (Arc::new(executor), storage)
+}
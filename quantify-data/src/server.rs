@@ -1,31 +1,211 @@
 #![allow(non_snake_case)]
+// tonic::Status is large, and returned by every handler
+#![allow(clippy::result_large_err)]
 
 
 
-use std::sync::Arc;
+use std::{pin::Pin, sync::Arc};
 
-use executor::Executor;
+use chrono::{DateTime, Duration, NaiveTime, TimeZone, Utc};
+use executor::{Executor, queue::Priority, scheduler::Schedule};
+use futures::{Stream, StreamExt, channel::mpsc, stream};
 use tonic::{transport::Server, Request, Response, Status};
 
 // gRPC
 use quantify::{
     Ticker,
     CandleData,
+    CandleDataV2,
     AddTickerRequest,
     RemoveTickerRequest,
     UpdateCandleDataRequest,
     GetCandleDataRequest,
     StatusResponse,
-    GetCandleDataResponse};
+    GetCandleDataResponse,
+    GranularityType,
+    DataPolicy,
+    ScheduledJob,
+    ScheduledJobRequest,
+    ListScheduledJobsRequest,
+    ListScheduledJobsResponse,
+    AddTickerResponse,
+    BackfillJob,
+    BackfillJobRequest,
+    BackfillStatus,
+    Job,
+    JobRequest,
+    JobState,
+    ListJobsRequest,
+    ListJobsResponse,
+    ProviderQuota,
+    GetQuotasRequest,
+    GetQuotasResponse};
 use quantify::quantify_data_server::{QuantifyData, QuantifyDataServer};
 
 // Library
 mod executor;
 
+/// Number of candles per StreamCandleData message
+const CANDLE_STREAM_BATCH_SIZE: usize = 1000;
+/// Number of batches buffered ahead of the client
+const CANDLE_STREAM_BUFFER: usize = 4;
+/// UTC hour at which daily candles are updated
+const DAILY_UPDATE_HOUR: u32 = 22;
+/// Period of the hour candle updates
+const HOURLY_UPDATE_PERIOD: std::time::Duration = std::time::Duration::from_secs(3600);
+/// Period of the minute candle updates
+const MINUTE_UPDATE_PERIOD: std::time::Duration = std::time::Duration::from_secs(15 * 60);
+/// Number of jobs listed when the request sets no limit
+const DEFAULT_JOB_LIST_LIMIT: usize = 100;
+
 pub mod quantify {
     tonic::include_proto!("quantify");
 }
 
+/// Converts a gRPC granularity into the executor representation
+fn granularity(granularity_type: i32, granularity_value: i64) -> Option<executor::tasks::Granularity> {
+    // An unset granularity value defaults to a single unit
+    let value = match granularity_value {
+        0 => 1,
+        v => i32::try_from(v).ok().filter(|v| *v > 0)?
+    };
+    match GranularityType::from_i32(granularity_type)? {
+        GranularityType::Minutes => Some(executor::tasks::Granularity::Minutes(value)),
+        GranularityType::Hours => Some(executor::tasks::Granularity::Hours(value)),
+        GranularityType::Days => Some(executor::tasks::Granularity::Days(value)),
+        GranularityType::Weeks => Some(executor::tasks::Granularity::Weeks(value)),
+        GranularityType::Months => Some(executor::tasks::Granularity::Months(value)),
+    }
+}
+
+/// Converts a gRPC timestamp (Unix milliseconds)
+fn timestamp(millis: i64) -> Result<DateTime<Utc>, Status> {
+    Utc.timestamp_millis_opt(millis).single()
+        .ok_or_else(|| Status::invalid_argument(format!("Invalid timestamp {millis}")))
+}
+
+/// Converts a gRPC candle data request into a query
+fn candle_query(request: &GetCandleDataRequest) -> Result<executor::storage::CandleQuery, Status> {
+    let ticker = match &request.ticker {
+        Some(t) => &t.name,
+        None => return Err(Status::invalid_argument("Ticker not provided")),
+    };
+    let granularity = granularity(request.granularity_type, request.granularity_value)
+        .ok_or_else(|| Status::invalid_argument("Invalid granularity"))?;
+    let start = request.start_timestamp.map(timestamp).transpose()?;
+    let end = request.end_timestamp.map(timestamp).transpose()?;
+    if request.limit.is_some_and(|limit| limit < 0) {
+        return Err(Status::invalid_argument("Limit must not be negative"));
+    }
+    Ok(executor::storage::CandleQuery::new(ticker, granularity, start, end, request.limit))
+}
+
+/// Converts a stored candle into its gRPC representation
+fn candle_data(candle: &executor::tasks::CandleData) -> CandleData {
+    CandleData {
+        ticker: Some(Ticker{name: candle.ticker.clone()}),
+        timestamp: candle.timestamp.timestamp_millis(),
+        open: candle.open as f32,
+        close: candle.close as f32,
+        high: candle.high as f32,
+        low: candle.low as f32,
+        volume: candle.volume,
+        num_transactions: candle.num_transactions,
+    }
+}
+
+/// Converts a stored candle into its full precision gRPC representation
+fn candle_data_v2(candle: &executor::tasks::CandleData) -> CandleDataV2 {
+    CandleDataV2 {
+        ticker: Some(Ticker{name: candle.ticker.clone()}),
+        timestamp: Some(prost_types::Timestamp {
+            seconds: candle.timestamp.timestamp(),
+            nanos: candle.timestamp.timestamp_subsec_nanos() as i32
+        }),
+        open: candle.open,
+        close: candle.close,
+        high: candle.high,
+        low: candle.low,
+        volume: candle.volume,
+        num_transactions: candle.num_transactions,
+        vwap: candle.vwap,
+        adjusted: candle.adjusted,
+        source: candle.source.clone(),
+    }
+}
+
+/// Builds a candle data response, in both representations
+fn candle_response(candles: &[executor::tasks::CandleData]) -> GetCandleDataResponse {
+    GetCandleDataResponse {
+        candle_data: candles.iter().map(candle_data).collect(),
+        candles: candles.iter().map(candle_data_v2).collect()
+    }
+}
+
+/// Converts a stored granularity into its gRPC representation
+fn granularity_message(granularity: executor::tasks::Granularity) -> quantify::Granularity {
+    let (granularity_type, value) = match granularity {
+        executor::tasks::Granularity::Minutes(m) => (GranularityType::Minutes, m),
+        executor::tasks::Granularity::Hours(m) => (GranularityType::Hours, m),
+        executor::tasks::Granularity::Days(m) => (GranularityType::Days, m),
+        executor::tasks::Granularity::Weeks(m) => (GranularityType::Weeks, m),
+        executor::tasks::Granularity::Months(m) => (GranularityType::Months, m),
+    };
+    quantify::Granularity { granularity_type: granularity_type as i32, granularity_value: i64::from(value) }
+}
+
+/// Converts the progress of a backfill into its gRPC representation
+fn backfill_job(job: executor::storage::BackfillJob) -> BackfillJob {
+    let status = match job.status {
+        executor::storage::BackfillStatus::Running => BackfillStatus::Running,
+        executor::storage::BackfillStatus::Failed => BackfillStatus::Failed,
+        executor::storage::BackfillStatus::Completed => BackfillStatus::Completed,
+    };
+    BackfillJob {
+        id: job.id,
+        ticker: Some(Ticker{name: job.ticker}),
+        granularity: Some(granularity_message(job.granularity)),
+        start: job.start.timestamp_millis(),
+        end: job.end.timestamp_millis(),
+        completed_chunks: job.completed_chunks,
+        total_chunks: job.total_chunks,
+        inserted: job.inserted,
+        status: status as i32,
+        error: job.error,
+        updated_at: job.updated_at.timestamp_millis(),
+    }
+}
+
+/// Converts the record of a job into its gRPC representation
+fn job_message(job: executor::jobs::JobRecord) -> Job {
+    let state = match job.state {
+        executor::jobs::JobState::Queued => JobState::Queued,
+        executor::jobs::JobState::Running => JobState::Running,
+        executor::jobs::JobState::Succeeded => JobState::Succeeded,
+        executor::jobs::JobState::Failed => JobState::Failed,
+        executor::jobs::JobState::Cancelled => JobState::Cancelled,
+        executor::jobs::JobState::TimedOut => JobState::TimedOut,
+    };
+    Job {
+        id: job.id,
+        name: job.name,
+        state: state as i32,
+        created_at: job.created_at.timestamp_millis(),
+        started_at: job.started_at.map(|t| t.timestamp_millis()),
+        finished_at: job.finished_at.map(|t| t.timestamp_millis()),
+        error: job.error,
+    }
+}
+
+/// Logs the failure of a task nobody waits for
+fn detach(handle: executor::TaskHandle) {
+    tokio::spawn(async move {
+        if let Ok(Err(e)) = handle.await {
+            println!("{e}");
+        }
+    });
+}
+
 // gRPC Entry Points
 pub struct QuantifyDataImpl {
     pub executor: Arc<executor::Executor>
@@ -44,31 +224,54 @@ impl QuantifyData for QuantifyDataImpl {
     async fn add_ticker(
         &self,
         request: Request<AddTickerRequest>
-    ) -> Result<Response<StatusResponse>, Status> {
+    ) -> Result<Response<AddTickerResponse>, Status> {
         println!("Adding ticker {:?}", request);
 
-        let ticker = match &request.get_ref().ticker {
+        let failure = |info: String, job_id: Option<String>| Ok(Response::new(AddTickerResponse {
+            success: false,
+            info: Some(info),
+            backfill_job_ids: Vec::new(),
+            job_id
+        }));
+        let request = request.get_ref();
+        let ticker = match &request.ticker {
             Some(t) => &t.name,
-            None => 
-                return Ok(Response::new(StatusResponse {
-                    success: false,
-                    info: Some(String::from("Ticker not provided"))
-                })),
+            None => return failure(String::from("Ticker not provided"), None),
         };
+        // Collected at the stored granularities
+        let mut granularities: Vec<executor::tasks::Granularity> = Vec::new();
+        for requested in &request.granularities {
+            match granularity(requested.granularity_type, requested.granularity_value) {
+                Some(g) if !granularities.contains(&g.base()) => granularities.push(g.base()),
+                Some(_) => {},
+                None => return failure(String::from("Invalid granularity"), None),
+            }
+        }
+        if granularities.is_empty() {
+            granularities.push(executor::tasks::Granularity::Days(1));
+        }
+        if request.lookback_days < 0 {
+            return failure(String::from("Lookback must not be negative"), None);
+        }
 
-        let task = Arc::new(executor::tasks::AddTickerTask::new(ticker));
-        match __self.executor.execute(&task).await {
-            Ok(_) => {},
-            Err(_) => 
-                return Ok(Response::new(StatusResponse {
-                    success: false,
-                    info: Some(String::from("Ticker subscription failed"))
-                })),
+        // Recurring updates cover the ticker's granularities, the history is backfilled once
+        let mut task = executor::tasks::AddTickerTask::new(ticker, &granularities);
+        if request.lookback_days > 0 {
+            task = task.with_backfill(Utc::now().date_naive() - Duration::days(request.lookback_days));
+        }
+        let task = Arc::new(task);
+        let (job_id, handle) = self.executor.submit_resumable(Priority::Interactive, &self.executor.retrying(&task));
+        match handle.await {
+            Ok(Ok(())) => {},
+            Ok(Err(e)) => return failure(format!("Ticker subscription failed: {e}"), Some(job_id)),
+            Err(_) => return failure(String::from("Ticker subscription failed"), Some(job_id)),
         };
 
-        let reply = StatusResponse {
+        let reply = AddTickerResponse {
             success: true,
-            info: Some(String::from("Subscribed to ticker"))
+            info: Some(String::from("Subscribed to ticker")),
+            backfill_job_ids: task.submit_backfills(&self.executor),
+            job_id: Some(job_id)
         };
 
         Ok(Response::new(reply))
@@ -80,9 +283,50 @@ impl QuantifyData for QuantifyDataImpl {
     ) -> Result<Response<StatusResponse>, Status> {
         println!("Removing ticker {:?}", request);
 
+        let request = request.get_ref();
+        let ticker = match &request.ticker {
+            Some(t) => &t.name,
+            None =>
+                return Ok(Response::new(StatusResponse {
+                    success: false,
+                    info: Some(String::from("Ticker not provided")),
+                    job_id: None
+                })),
+        };
+        let policy = match DataPolicy::from_i32(request.data_policy) {
+            Some(DataPolicy::Keep) => executor::tasks::DataPolicy::Keep,
+            Some(DataPolicy::Purge) => executor::tasks::DataPolicy::Purge,
+            Some(DataPolicy::Archive) => executor::tasks::DataPolicy::Archive,
+            None =>
+                return Ok(Response::new(StatusResponse {
+                    success: false,
+                    info: Some(String::from("Invalid data policy")),
+                    job_id: None
+                })),
+        };
+
+        let task = Arc::new(executor::tasks::RemoveTickerTask::new(ticker, policy));
+        let (job_id, handle) = self.executor.submit_resumable(Priority::Interactive, &task);
+        match handle.await {
+            Ok(Ok(())) => {},
+            Ok(Err(e)) =>
+                return Ok(Response::new(StatusResponse {
+                    success: false,
+                    info: Some(format!("Ticker removal failed: {e}")),
+                    job_id: Some(job_id)
+                })),
+            Err(_) =>
+                return Ok(Response::new(StatusResponse {
+                    success: false,
+                    info: Some(String::from("Ticker removal failed")),
+                    job_id: Some(job_id)
+                })),
+        };
+
         let reply = StatusResponse {
             success: true,
-            info: Some(String::from("Removed ticker"))
+            info: Some(format!("Removed ticker, {} documents affected", task.affected())),
+            job_id: Some(job_id)
         };
 
         Ok(Response::new(reply))
@@ -94,9 +338,48 @@ impl QuantifyData for QuantifyDataImpl {
     ) -> Result<Response<StatusResponse>, Status> {
         println!("Adding candle data {:?}", request);
 
+        let request = request.get_ref();
+        let ticker = match &request.ticker {
+            Some(t) => &t.name,
+            None =>
+                return Ok(Response::new(StatusResponse {
+                    success: false,
+                    info: Some(String::from("Ticker not provided")),
+                    job_id: None
+                })),
+        };
+        let granularity = match granularity(request.granularity_type, request.granularity_value) {
+            Some(g) => g,
+            None =>
+                return Ok(Response::new(StatusResponse {
+                    success: false,
+                    info: Some(String::from("Invalid granularity")),
+                    job_id: None
+                })),
+        };
+
+        let task = Arc::new(executor::tasks::UpdateCandleDataTask::new(ticker, granularity));
+        let (job_id, handle) = self.executor.submit_resumable(Priority::Interactive, &self.executor.retrying(&task));
+        match handle.await {
+            Ok(Ok(())) => {},
+            Ok(Err(e)) =>
+                return Ok(Response::new(StatusResponse {
+                    success: false,
+                    info: Some(format!("Candle data update failed: {e}")),
+                    job_id: Some(job_id)
+                })),
+            Err(_) =>
+                return Ok(Response::new(StatusResponse {
+                    success: false,
+                    info: Some(String::from("Candle data update failed")),
+                    job_id: Some(job_id)
+                })),
+        };
+
         let reply = StatusResponse {
             success: true,
-            info: Some(String::from("Added candle data"))
+            info: Some(format!("Added {} candles, {} discrepancies between sources", task.inserted(), task.discrepancies())),
+            job_id: Some(job_id)
         };
 
         Ok(Response::new(reply))
@@ -108,28 +391,182 @@ impl QuantifyData for QuantifyDataImpl {
     ) -> Result<Response<GetCandleDataResponse>, Status> {
         println!("Retrieving candle data {:?}", request);
 
-        let reply = GetCandleDataResponse {
-            candle_data: vec![CandleData{
-                ticker: Some(Ticker{name: "test".to_string()}),
-                timestamp: 100,
-                open: 5.0,
-                close: 5.0,
-                high: 5.0,
-                low: 5.0,
-                volume: 10,
-                num_transactions: 20,
-            }]
+        let query = candle_query(request.get_ref())?;
+        let task = Arc::new(executor::tasks::GetCandleDataTask::new(query));
+        match self.executor.execute_with_priority(Priority::Interactive, &task).await {
+            Ok(Ok(())) => {},
+            Ok(Err(e)) => return Err(Status::internal(format!("Candle data retrieval failed: {e}"))),
+            Err(_) => return Err(Status::internal("Candle data retrieval failed")),
         };
 
+        let reply = candle_response(&task.take_candles());
+
         Ok(Response::new(reply))
     }
+
+    type StreamCandleDataStream = Pin<Box<dyn Stream<Item = Result<GetCandleDataResponse, Status>> + Send>>;
+
+    async fn stream_candle_data(
+        &self,
+        request: Request<GetCandleDataRequest>
+    ) -> Result<Response<Self::StreamCandleDataStream>, Status> {
+        println!("Streaming candle data {:?}", request);
+
+        let query = candle_query(request.get_ref())?;
+        let (sender, receiver) = mpsc::channel(CANDLE_STREAM_BUFFER);
+        let task = Arc::new(executor::tasks::StreamCandleDataTask::new(query, CANDLE_STREAM_BATCH_SIZE, sender));
+        let handle = self.executor.execute_stream(&task);
+
+        let batches = receiver.map(|batch| Ok(candle_response(&batch)));
+        // Surface a failed task as the final stream item
+        let completion = stream::once(handle).filter_map(|result| async move {
+            match result {
+                Ok(Ok(())) => None,
+                Ok(Err(e)) => Some(Err(Status::internal(format!("Candle data retrieval failed: {e}")))),
+                Err(_) => Some(Err(Status::internal("Candle data retrieval failed"))),
+            }
+        });
+
+        Ok(Response::new(Box::pin(batches.chain(completion))))
+    }
+
+    async fn get_backfill_job(
+        &self,
+        request: Request<BackfillJobRequest>
+    ) -> Result<Response<BackfillJob>, Status> {
+        let task = Arc::new(executor::tasks::GetBackfillJobTask::new(&request.get_ref().id));
+        match self.executor.execute_with_priority(Priority::Interactive, &task).await {
+            Ok(Ok(())) => {},
+            Ok(Err(e)) => return Err(Status::internal(format!("Backfill job retrieval failed: {e}"))),
+            Err(_) => return Err(Status::internal("Backfill job retrieval failed")),
+        };
+
+        match task.job() {
+            Some(job) => Ok(Response::new(backfill_job(job))),
+            None => Err(Status::not_found(format!("No backfill job {}", request.get_ref().id)))
+        }
+    }
+
+    async fn list_scheduled_jobs(
+        &self,
+        _request: Request<ListScheduledJobsRequest>
+    ) -> Result<Response<ListScheduledJobsResponse>, Status> {
+        let jobs = self.executor.scheduler().list().into_iter()
+            .map(|job| ScheduledJob {
+                id: job.id,
+                name: job.name,
+                schedule: job.schedule.to_string(),
+                paused: job.paused,
+                running: job.running,
+                last_run: job.last_run.map(|t| t.timestamp_millis()),
+                next_run: job.next_run.map(|t| t.timestamp_millis()),
+            })
+            .collect();
+
+        Ok(Response::new(ListScheduledJobsResponse { jobs }))
+    }
+
+    async fn pause_scheduled_job(
+        &self,
+        request: Request<ScheduledJobRequest>
+    ) -> Result<Response<StatusResponse>, Status> {
+        println!("Pausing scheduled job {:?}", request);
+
+        let reply = match self.executor.scheduler().pause(request.get_ref().id) {
+            true => StatusResponse { success: true, info: Some(String::from("Paused job")), job_id: None },
+            false => StatusResponse { success: false, info: Some(String::from("Job not found")), job_id: None },
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    async fn resume_scheduled_job(
+        &self,
+        request: Request<ScheduledJobRequest>
+    ) -> Result<Response<StatusResponse>, Status> {
+        println!("Resuming scheduled job {:?}", request);
+
+        let reply = match self.executor.scheduler().resume(request.get_ref().id) {
+            true => StatusResponse { success: true, info: Some(String::from("Resumed job")), job_id: None },
+            false => StatusResponse { success: false, info: Some(String::from("Job not found")), job_id: None },
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    async fn get_job(
+        &self,
+        request: Request<JobRequest>
+    ) -> Result<Response<Job>, Status> {
+        let id = &request.get_ref().id;
+        match self.executor.job(id).await {
+            Ok(Some(job)) => Ok(Response::new(job_message(job))),
+            Ok(None) => Err(Status::not_found(format!("No job {id}"))),
+            Err(e) => Err(Status::internal(format!("Job retrieval failed: {e}"))),
+        }
+    }
+
+    async fn list_jobs(
+        &self,
+        request: Request<ListJobsRequest>
+    ) -> Result<Response<ListJobsResponse>, Status> {
+        let limit = match request.get_ref().limit {
+            Some(limit) => usize::try_from(limit).map_err(|_| Status::invalid_argument("Limit must not be negative"))?,
+            None => DEFAULT_JOB_LIST_LIMIT
+        };
+        match self.executor.list_jobs(limit).await {
+            Ok(jobs) => Ok(Response::new(ListJobsResponse { jobs: jobs.into_iter().map(job_message).collect() })),
+            Err(e) => Err(Status::internal(format!("Job listing failed: {e}"))),
+        }
+    }
+
+    async fn cancel_job(
+        &self,
+        request: Request<JobRequest>
+    ) -> Result<Response<StatusResponse>, Status> {
+        println!("Cancelling job {:?}", request);
+
+        let id = &request.get_ref().id;
+        let reply = match self.executor.jobs().cancel(id) {
+            true => StatusResponse { success: true, info: Some(String::from("Cancelled job")), job_id: Some(id.clone()) },
+            false => StatusResponse { success: false, info: Some(String::from("Job not running")), job_id: Some(id.clone()) },
+        };
+
+        Ok(Response::new(reply))
+    }
+
+    async fn get_quotas(
+        &self,
+        _request: Request<GetQuotasRequest>
+    ) -> Result<Response<GetQuotasResponse>, Status> {
+        let quotas = self.executor.sources().iter()
+            .filter_map(|source| source.quota().map(|usage| ProviderQuota {
+                source: String::from(source.name()),
+                rate_limit: usage.limit.to_string(),
+                requests_today: usage.requests,
+                daily_quota: usage.limit.daily_quota,
+            }))
+            .collect();
+
+        Ok(Response::new(GetQuotasResponse { quotas }))
+    }
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let mongo_addr = std::env::var("QUANTIFY_DATABASE_URI").expect("You must set the QUANTIFY_DATABASE_URI environment var!");
+
+    // `quantify-data-server migrate` upgrades the database schema and exits
+    if std::env::args().nth(1).as_deref() == Some("migrate") {
+        let applied = executor::storage::MongoStorage::connect(&mongo_addr).await?.migrate().await?;
+        println!("Applied {} migrations {:?}", applied.len(), applied);
+        return Ok(());
+    }
+
     let server_addr = "[::1]:50051".parse()?;
     let server = QuantifyDataImpl::build(&mongo_addr).await;
+    schedule_jobs(&server.executor);
+    reclaim_jobs(&server.executor).await;
 
     Server::builder()
         .add_service(QuantifyDataServer::new(server))
@@ -140,4 +577,204 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 }
 
 // Polling / automatic behavior
-// TODO
\ No newline at end of file
+async fn reclaim_jobs(executor: &Arc<Executor>) {
+    // Including the backfills of a stopped server
+    match executor.reclaim_jobs().await {
+        Ok(resumed) => {
+            println!("Reclaimed {} jobs", resumed.len());
+            for (_, handle) in resumed {
+                detach(handle);
+            }
+        },
+        Err(e) => println!("Failed to reclaim jobs: {e}")
+    }
+}
+
+fn schedule_jobs(executor: &Arc<Executor>) {
+    // Daily candles, after the US market close
+    let update_time = NaiveTime::from_hms_opt(DAILY_UPDATE_HOUR, 0, 0).unwrap();
+    executor.schedule(
+        "Update daily candles",
+        &Arc::new(executor::tasks::UpdateAllCandleDataTask::new(executor::tasks::Granularity::Days(1))),
+        Schedule::weekdays(update_time)
+    );
+    // Intraday candles, of the tickers collecting them
+    executor.schedule(
+        "Update hour candles",
+        &Arc::new(executor::tasks::UpdateAllCandleDataTask::new(executor::tasks::Granularity::Hours(1))),
+        Schedule::Interval(HOURLY_UPDATE_PERIOD)
+    );
+    executor.schedule(
+        "Update minute candles",
+        &Arc::new(executor::tasks::UpdateAllCandleDataTask::new(executor::tasks::Granularity::Minutes(1))),
+        Schedule::Interval(MINUTE_UPDATE_PERIOD)
+    );
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+    use quantify_core::{Bar, Metadata, RateLimit, RateLimiter};
+    use tonic::{Code, Request};
+
+    use super::QuantifyDataImpl;
+    use super::executor::storage::Storage;
+    use super::executor::testing::{executor, StaticSource};
+    use super::quantify::{
+        AddTickerRequest, BackfillJobRequest, BackfillStatus, DataPolicy, GetCandleDataRequest,
+        GetQuotasRequest, Granularity, GranularityType, JobRequest, JobState, ListJobsRequest, RemoveTickerRequest, Ticker};
+    use super::quantify::quantify_data_server::QuantifyData;
+
+    fn ticker(name: &str) -> Option<Ticker> {
+        Some(Ticker { name: String::from(name) })
+    }
+
+    #[tokio::test]
+    async fn test_add_and_remove_ticker() {
+        let mut source = StaticSource::new("polygon");
+        source.metadata = Some(Metadata { ticker: String::from("NFLX"), name: String::from("Netflix Inc"), exchange: String::from("XNAS") });
+        let (exec, storage) = executor(vec![source]);
+        let server = QuantifyDataImpl { executor: exec };
+
+        let reply = server.add_ticker(Request::new(AddTickerRequest::default())).await.unwrap().into_inner();
+        assert!(!reply.success);
+
+        let request = AddTickerRequest { ticker: ticker("NFLX"), ..Default::default() };
+        let reply = server.add_ticker(Request::new(request)).await.unwrap().into_inner();
+        assert!(reply.success);
+        assert!(reply.backfill_job_ids.is_empty());
+        assert!(storage.get_ticker("nflx").await.unwrap().is_some());
+
+        let request = RemoveTickerRequest { ticker: ticker("NFLX"), data_policy: DataPolicy::Purge as i32 };
+        let reply = server.remove_ticker(Request::new(request)).await.unwrap().into_inner();
+        assert!(reply.success);
+        assert_eq!(reply.info.unwrap(), "Removed ticker, 1 documents affected");
+        assert!(storage.list_tickers().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_jobs() {
+        let (exec, _) = executor(vec![StaticSource::new("polygon")]);
+        let server = QuantifyDataImpl { executor: exec };
+
+        // The source has no metadata for the ticker
+        let request = AddTickerRequest { ticker: ticker("NFLX"), ..Default::default() };
+        let reply = server.add_ticker(Request::new(request)).await.unwrap().into_inner();
+        assert!(!reply.success);
+
+        let id = JobRequest { id: reply.job_id.unwrap() };
+        let job = server.get_job(Request::new(id.clone())).await.unwrap().into_inner();
+        assert_eq!((job.name.as_str(), job.state()), ("Add ticker NFLX", JobState::Failed));
+        assert_eq!(job.error.as_deref(), Some("Ticker nflx not found"));
+        assert!(job.finished_at.is_some());
+
+        let jobs = server.list_jobs(Request::new(ListJobsRequest { limit: None })).await.unwrap().into_inner().jobs;
+        assert_eq!(jobs.iter().map(|job| &job.id).collect::<Vec<_>>(), vec![&id.id]);
+        let invalid = ListJobsRequest { limit: Some(-1) };
+        assert_eq!(server.list_jobs(Request::new(invalid)).await.err().unwrap().code(), Code::InvalidArgument);
+
+        // Only running jobs can be cancelled
+        assert!(!server.cancel_job(Request::new(id)).await.unwrap().into_inner().success);
+        let missing = JobRequest { id: String::from("missing") };
+        assert_eq!(server.get_job(Request::new(missing)).await.err().unwrap().code(), Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_add_ticker_backfill() {
+        let today = Utc::now().date_naive();
+        let mut source = StaticSource::new("polygon");
+        source.metadata = Some(Metadata { ticker: String::from("NFLX"), name: String::from("Netflix Inc"), exchange: String::from("XNAS") });
+        source.bars = (0..3)
+            .map(|ago| Bar {
+                timestamp: Utc.from_utc_datetime(&(today - Duration::days(ago)).and_hms_opt(0, 0, 0).unwrap()),
+                open: 1.0, high: 1.0, low: 1.0, close: 1.0, volume: 1.0,
+                num_transactions: None, vwap: None, adjusted: true
+            })
+            .collect();
+        let (exec, storage) = executor(vec![source]);
+        let server = QuantifyDataImpl { executor: exec };
+
+        // Weekly candles are resampled from daily ones
+        let weekly = Granularity { granularity_type: GranularityType::Weeks as i32, granularity_value: 1 };
+        let request = AddTickerRequest { ticker: ticker("NFLX"), granularities: vec![weekly], lookback_days: 5 };
+        let reply = server.add_ticker(Request::new(request)).await.unwrap().into_inner();
+        assert!(reply.success);
+        assert_eq!(reply.backfill_job_ids, vec![format!("nflx:day_candle:{}", today - Duration::days(5))]);
+        assert_eq!(storage.get_ticker("nflx").await.unwrap().unwrap().granularities, vec![super::executor::tasks::Granularity::Days(1)]);
+
+        let id = BackfillJobRequest { id: reply.backfill_job_ids[0].clone() };
+        let mut job = server.get_backfill_job(Request::new(id.clone())).await;
+        for _ in 0..100 {
+            if job.as_ref().is_ok_and(|job| job.get_ref().status() == BackfillStatus::Completed) {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            job = server.get_backfill_job(Request::new(id.clone())).await;
+        }
+        let job = job.unwrap().into_inner();
+        assert_eq!(job.status(), BackfillStatus::Completed);
+        assert_eq!(job.inserted, 3);
+
+        let missing = BackfillJobRequest { id: String::from("nflx:day_candle:2000-01-01") };
+        assert_eq!(server.get_backfill_job(Request::new(missing)).await.err().unwrap().code(), Code::NotFound);
+
+        let request = AddTickerRequest { ticker: ticker("NFLX"), granularities: Vec::new(), lookback_days: -1 };
+        assert!(!server.add_ticker(Request::new(request)).await.unwrap().into_inner().success);
+    }
+
+    #[tokio::test]
+    async fn test_get_quotas() {
+        let mut limited = StaticSource::new("polygon");
+        limited.metadata = Some(Metadata { ticker: String::from("NFLX"), name: String::from("Netflix Inc"), exchange: String::from("XNAS") });
+        limited.limiter = Some(RateLimiter::new(RateLimit::new(5, std::time::Duration::from_secs(60)).with_daily_quota(100)));
+        let (exec, _) = executor(vec![limited, StaticSource::new("unlimited")]);
+        let server = QuantifyDataImpl { executor: exec };
+
+        let request = AddTickerRequest { ticker: ticker("NFLX"), ..Default::default() };
+        assert!(server.add_ticker(Request::new(request)).await.unwrap().into_inner().success);
+
+        // Sources without a rate limit are left out
+        let quotas = server.get_quotas(Request::new(GetQuotasRequest {})).await.unwrap().into_inner().quotas;
+        assert_eq!(quotas.len(), 1);
+        assert_eq!(quotas[0].source, "polygon");
+        assert_eq!(quotas[0].rate_limit, "5/60s, 100/day");
+        assert_eq!((quotas[0].requests_today, quotas[0].daily_quota), (1, Some(100)));
+    }
+
+    #[tokio::test]
+    async fn test_get_candle_data() {
+        let (exec, storage) = executor(Vec::new());
+        let candles = (1..=3)
+            .map(|day| super::executor::tasks::CandleData {
+                ticker: String::from("nflx"),
+                timestamp: Utc.with_ymd_and_hms(2023, 8, day, 0, 0, 0).unwrap(),
+                open: 1.0, close: 2.0, high: 3.0, low: 0.5, volume: 100, num_transactions: 10,
+                vwap: Some(1.5), adjusted: true, source: String::from("polygon")
+            })
+            .collect();
+        storage.upsert_candles(super::executor::tasks::Granularity::Days(1), candles).await.unwrap();
+        let server = QuantifyDataImpl { executor: exec };
+
+        let request = GetCandleDataRequest {
+            ticker: ticker("NFLX"),
+            granularity_type: GranularityType::Days as i32,
+            granularity_value: 1,
+            start_timestamp: Some(Utc.with_ymd_and_hms(2023, 8, 2, 0, 0, 0).unwrap().timestamp_millis()),
+            end_timestamp: None,
+            limit: None
+        };
+        let reply = server.get_candle_data(Request::new(request.clone())).await.unwrap().into_inner();
+        assert_eq!(reply.candle_data.len(), 2);
+        assert_eq!(reply.candle_data[0].timestamp, Utc.with_ymd_and_hms(2023, 8, 2, 0, 0, 0).unwrap().timestamp_millis());
+        assert_eq!(reply.candle_data[0].close, 2.0);
+        let candle = &reply.candles[0];
+        assert_eq!(candle.timestamp.as_ref().map(|timestamp| timestamp.seconds), Some(Utc.with_ymd_and_hms(2023, 8, 2, 0, 0, 0).unwrap().timestamp()));
+        assert_eq!((candle.close, candle.vwap, candle.adjusted), (2.0, Some(1.5), true));
+        assert_eq!(candle.source, "polygon");
+
+        let invalid = GetCandleDataRequest { limit: Some(-1), ..request };
+        let status = server.get_candle_data(Request::new(invalid)).await.err().unwrap();
+        assert_eq!(status.code(), Code::InvalidArgument);
+    }
+}